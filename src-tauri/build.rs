@@ -1,3 +1,6 @@
 fn main() {
-    tauri_build::build()
+    // 只构建命令行版本（--no-default-features）时不需要 Tauri 的资源和配置
+    if std::env::var_os("CARGO_FEATURE_GUI").is_some() {
+        tauri_build::build()
+    }
 }
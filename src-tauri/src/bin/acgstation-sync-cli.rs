@@ -0,0 +1,978 @@
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use acgstation_sync_lib::events::EventSink;
+use acgstation_sync_lib::export::{self, Compression, ExportSettings, FileItem, OutputMode};
+use acgstation_sync_lib::history::{self, HistoryEvent, SyncTrigger};
+use acgstation_sync_lib::hooks::{self, SyncHooks};
+use acgstation_sync_lib::manifest::{self, FileGroup, Manifest};
+use acgstation_sync_lib::platform::{FileCondition, Platform};
+use acgstation_sync_lib::signing::SignatureInfo;
+use acgstation_sync_lib::sync::{self, FileStatus};
+use acgstation_sync_lib::profiles::{self, AutoSync, SyncProfile};
+use acgstation_sync_lib::report::SyncStatus;
+use acgstation_sync_lib::snapshots::{self, SnapshotReason};
+use acgstation_sync_lib::updates::{self, UpdateCheckSettings};
+use acgstation_sync_lib::{paths, scheduler, session};
+
+// 退出码，脚本和流水线据此判断结果
+const EXIT_OK: u8 = 0;
+const EXIT_ERROR: u8 = 1;
+// 参数错误，与 clap 自身的参数错误一致
+const EXIT_USAGE: u8 = 2;
+// verify：目标目录与清单不一致
+const EXIT_OUT_OF_DATE: u8 = 3;
+// 清单签名校验失败，或包中含有不安全的路径
+const EXIT_VERIFY_FAILED: u8 = 4;
+// 被 Ctrl+C 中断
+const EXIT_INTERRUPTED: u8 = 130;
+
+#[derive(Parser)]
+#[command(name = "acgstation-sync-cli", version, about = "ACGStation 游戏文件同步器（命令行版本）")]
+struct Cli {
+    /// Print progress events and the final result as JSON lines on stdout
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Export files and folders into a sync package (zip with manifest.json)
+    Export(Box<ExportArgs>),
+    /// Compare a manifest with a local directory
+    Diff(DiffArgs),
+    /// Bring a local directory up to date with a manifest or a local package
+    Sync(SyncArgs),
+    /// Check a manifest's signature and, with --target, whether a directory is up to date
+    Verify(VerifyArgs),
+    /// List the snapshots taken before each sync of a directory
+    Snapshots(SnapshotsArgs),
+    /// Restore a directory to its state before a snapshot was taken
+    Restore(RestoreArgs),
+    /// Manage and run saved sync profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// List the saved profiles
+    List,
+    /// Save a new profile
+    Create(ProfileCreateArgs),
+    /// Change a profile; options that are not given keep their saved values
+    Update(ProfileUpdateArgs),
+    /// Delete a profile
+    Delete {
+        /// Profile id or name
+        profile: String,
+    },
+    /// Sync a profile and record the synced version
+    Run {
+        /// Profile id or name
+        profile: String,
+
+        /// Approve the run actions of the manifest hooks with their arguments
+        #[arg(long)]
+        approve_hooks: bool,
+    },
+    /// Check every profile's manifest for a new version
+    Check,
+    /// Show the update checks and syncs recorded for the profiles
+    History {
+        /// Only show entries of this profile (id or name)
+        profile: Option<String>,
+
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+    },
+    /// Keep checking for updates and run the profiles' auto syncs until interrupted
+    Watch {
+        /// Minutes between update checks
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+}
+
+#[derive(Args)]
+struct ProfileCreateArgs {
+    name: String,
+
+    /// Manifest URL, exported .zip package or path to a manifest.json
+    source: String,
+
+    /// Local directory to update
+    target: String,
+
+    /// Trusted publisher key (base64), used in addition to the local trust store
+    #[arg(long = "trusted-key")]
+    trusted_keys: Vec<String>,
+
+    /// Optional file group to enable; without any, the selection saved in the target directory is used
+    #[arg(long = "group")]
+    groups: Vec<String>,
+
+    #[arg(long)]
+    disable_hash_check: bool,
+
+    #[arg(long)]
+    disable_size_check: bool,
+
+    #[arg(long)]
+    force_rescan: bool,
+
+    /// Sync automatically when an update is found: off, idle, or a daily local time such as 04:30
+    #[arg(long, value_parser = parse_auto_sync, default_value = "off")]
+    auto_sync: AutoSync,
+}
+
+#[derive(Args)]
+struct ProfileUpdateArgs {
+    /// Profile id or name
+    profile: String,
+
+    #[arg(long)]
+    name: Option<String>,
+
+    #[arg(long)]
+    source: Option<String>,
+
+    #[arg(long)]
+    target: Option<String>,
+
+    /// Replace the trusted publisher keys
+    #[arg(long = "trusted-key")]
+    trusted_keys: Option<Vec<String>>,
+
+    /// Replace the enabled optional file groups
+    #[arg(long = "group", conflicts_with = "saved_groups")]
+    groups: Option<Vec<String>>,
+
+    /// Use the group selection saved in the target directory again
+    #[arg(long)]
+    saved_groups: bool,
+
+    #[arg(long)]
+    disable_hash_check: Option<bool>,
+
+    #[arg(long)]
+    disable_size_check: Option<bool>,
+
+    #[arg(long)]
+    force_rescan: Option<bool>,
+
+    /// off, idle, or a daily local time such as 04:30
+    #[arg(long, value_parser = parse_auto_sync)]
+    auto_sync: Option<AutoSync>,
+}
+
+fn parse_auto_sync(value: &str) -> Result<AutoSync, String> {
+    match value {
+        "off" => Ok(AutoSync::Off),
+        "idle" => Ok(AutoSync::Idle),
+        time => chrono::NaiveTime::parse_from_str(time, "%H:%M")
+            .map(|_| AutoSync::Scheduled { time: time.to_string() })
+            .map_err(|_| "expected off, idle or a time like 04:30".to_string()),
+    }
+}
+
+#[derive(Args)]
+struct ManifestArgs {
+    /// Manifest URL, exported .zip package or path to a manifest.json
+    manifest: String,
+
+    /// Trusted publisher key (base64), used in addition to the local trust store
+    #[arg(long = "trusted-key")]
+    trusted_keys: Vec<String>,
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Path or gitignore-style pattern (e.g. `saves/`, `*.log`, `!keep.log`) to leave untouched, relative to the target directory; combined with .sync_exclude.json
+    #[arg(long = "exclude")]
+    excluded_files: Vec<String>,
+
+    /// Compare files by size only
+    #[arg(long)]
+    disable_hash_check: bool,
+
+    /// Treat every existing file as up to date (only with --disable-hash-check)
+    #[arg(long)]
+    disable_size_check: bool,
+
+    /// Ignore the hash index and rehash every local file
+    #[arg(long)]
+    force_rescan: bool,
+
+    /// Turn on an optional file group by id for this run; combined with .sync_groups.json or the group defaults
+    #[arg(long = "enable-group")]
+    enabled_groups: Vec<String>,
+
+    /// Turn off an optional file group by id for this run; its installed files are removed on sync
+    #[arg(long = "disable-group")]
+    disabled_groups: Vec<String>,
+}
+
+#[derive(Args)]
+struct ExportArgs {
+    /// Files and folders to export, they should share the same parent directory
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Where to write the package; a directory with --directory, a manifest.json with --manifest-only
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Write the files into a directory laid out like the download URLs, ready to upload to static hosting
+    #[arg(long, conflicts_with = "manifest_only")]
+    directory: bool,
+
+    /// Only write manifest.json, for files already hosted under the download prefix; compressed folders are not allowed
+    #[arg(long)]
+    manifest_only: bool,
+
+    #[arg(long)]
+    package_name: String,
+
+    #[arg(long = "pack-version")]
+    version: String,
+
+    /// Download prefix; repeat to add mirrors, the first one is the primary
+    #[arg(long = "download-prefix", required = true)]
+    download_prefixes: Vec<String>,
+
+    #[arg(long)]
+    description: Option<String>,
+
+    /// Folder to pack into a single zip that is extracted on sync
+    #[arg(long)]
+    compress: Vec<PathBuf>,
+
+    /// Like --compress, but marked as an update package
+    #[arg(long)]
+    update_package: Vec<PathBuf>,
+
+    /// File or folder whose large files are exported as content-defined chunks
+    #[arg(long)]
+    chunked: Vec<PathBuf>,
+
+    /// Split archives of compressed folders larger than this into numbered parts, e.g. 2G, 500M or a byte count
+    #[arg(long, value_parser = parse_size)]
+    max_part_size: Option<u64>,
+
+    /// Compression for the entries of an exported path, as PATH=METHOD[:LEVEL] with METHOD one of stored, deflate (level 0-9),
+    /// zstd (level -7-22) or bzip2 (level 1-9)
+    #[arg(long, value_parser = parse_compression)]
+    compression: Vec<(PathBuf, Compression)>,
+
+    /// JSON file listing the optional file groups, same format as the manifest `groups` field
+    #[arg(long)]
+    groups: Option<PathBuf>,
+
+    /// Only sync an exported path on matching machines, as PATH=KEY:VALUE[,KEY:VALUE...] with KEY one of os, arch, game, loader
+    #[arg(long = "condition", value_parser = parse_condition)]
+    conditions: Vec<(PathBuf, FileCondition)>,
+
+    /// JSON file with the pre- and post-sync actions, same format as the manifest `hooks` field
+    #[arg(long)]
+    hooks: Option<PathBuf>,
+
+    /// Put an exported path into an optional group, as PATH=GROUP_ID
+    #[arg(long = "group", value_parser = parse_group)]
+    group_assignments: Vec<(PathBuf, String)>,
+
+    /// Path or gitignore-style pattern to leave out, relative to the parent directory of the exported paths
+    #[arg(long = "exclude")]
+    exclusions: Vec<String>,
+
+    /// Previous package to generate delta patches against
+    #[arg(long)]
+    previous_package: Option<String>,
+
+    /// Previous manifest.json or package; only new or changed files go into the output package
+    #[arg(long)]
+    previous_manifest: Option<String>,
+
+    /// Ed25519 signing key file used to sign the manifest
+    #[arg(long)]
+    signing_key: Option<String>,
+
+    #[arg(long)]
+    disable_hash_check: bool,
+
+    #[arg(long)]
+    disable_size_check: bool,
+}
+
+#[derive(Args)]
+struct DiffArgs {
+    #[command(flatten)]
+    source: ManifestArgs,
+
+    /// Local directory to compare
+    target: String,
+
+    #[command(flatten)]
+    check: CheckArgs,
+}
+
+#[derive(Args)]
+struct SyncArgs {
+    #[command(flatten)]
+    source: ManifestArgs,
+
+    /// Local directory to update
+    target: String,
+
+    #[command(flatten)]
+    check: CheckArgs,
+
+    /// Number of simultaneous downloads
+    #[arg(long)]
+    max_concurrent: Option<usize>,
+
+    /// Total bandwidth limit in KB/s
+    #[arg(long)]
+    limit_kbps: Option<u64>,
+
+    /// Bandwidth limit per server in KB/s
+    #[arg(long)]
+    host_limit_kbps: Option<u64>,
+
+    /// Approve the run actions of the manifest hooks with their arguments; without this, unapproved run actions are skipped
+    #[arg(long)]
+    approve_hooks: bool,
+
+    /// Number of snapshots to keep for the directory, 0 disables snapshots
+    #[arg(long)]
+    keep_snapshots: Option<usize>,
+
+    /// Total size of the kept snapshots, e.g. 2G; the newest snapshot is always kept
+    #[arg(long, value_parser = parse_size)]
+    snapshot_max_size: Option<u64>,
+}
+
+#[derive(Args)]
+struct VerifyArgs {
+    #[command(flatten)]
+    source: ManifestArgs,
+
+    /// Also check that this directory matches the manifest
+    #[arg(long)]
+    target: Option<String>,
+
+    #[command(flatten)]
+    check: CheckArgs,
+}
+
+#[derive(Args)]
+struct SnapshotsArgs {
+    /// Synced directory
+    target: String,
+}
+
+#[derive(Args)]
+struct RestoreArgs {
+    /// Synced directory
+    target: String,
+
+    /// Snapshot id as printed by the snapshots command
+    snapshot: String,
+}
+
+// 命令的结果：退出码、一句话总结以及 --json 时附带的数据
+struct Outcome {
+    code: u8,
+    message: String,
+    data: Value,
+}
+
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        let code = if message.contains(paths::UNSAFE_PATH_ERROR) { EXIT_VERIFY_FAILED } else { EXIT_ERROR };
+        Failure { code, message }
+    }
+}
+
+// 进度输出：--json 时每个事件一行写到标准输出，否则把关键事件写到标准错误
+struct TerminalEvents {
+    json: bool,
+}
+
+impl EventSink for TerminalEvents {
+    fn emit_json(&self, event: &str, payload: Value) {
+        if self.json {
+            println!("{}", json!({ "event": event, "payload": payload }));
+            return;
+        }
+        match event {
+            "EXPORT_PROGRESS" => eprintln!(
+                "[{}/{}] {} ({}/{} bytes)",
+                payload["current"], payload["total"], payload["fileName"].as_str().unwrap_or_default(),
+                payload["processedBytes"], payload["totalBytes"]
+            ),
+            "DOWNLOAD_SUCCESS" => eprintln!("updated {}", payload.as_str().unwrap_or_default()),
+            "DOWNLOAD_ERROR" => eprintln!("error: {}", payload.as_str().unwrap_or_default()),
+            "SYNC_CANCELLED" => eprintln!("sync cancelled"),
+            "UPDATE_AVAILABLE" => eprintln!(
+                "update available for {}: {} -> {}",
+                payload["profileName"].as_str().unwrap_or_default(),
+                payload["currentVersion"].as_str().unwrap_or("(never synced)"),
+                payload["latestVersion"].as_str().unwrap_or_default()
+            ),
+            "HOOK_FINISHED" => {
+                let detail = payload["message"].as_str().map(|message| format!(": {}", message)).unwrap_or_default();
+                eprintln!(
+                    "{} {} ({}){}",
+                    payload["phase"].as_str().unwrap_or_default(), payload["action"].as_str().unwrap_or_default(),
+                    payload["status"].as_str().unwrap_or_default(), detail
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let events: Arc<dyn EventSink> = Arc::new(TerminalEvents { json: cli.json });
+    // 先回滚各配置目标目录中被中断的同步
+    profiles::recover_targets();
+
+    let result = match cli.command {
+        Command::Export(args) => run_export(&*events, *args),
+        Command::Diff(args) => run_diff(args, cli.json).await,
+        Command::Sync(args) => run_sync(events.clone(), args).await,
+        Command::Verify(args) => run_verify(args, cli.json).await,
+        Command::Snapshots(args) => run_snapshots(args, cli.json),
+        Command::Restore(args) => run_restore(args),
+        Command::Profile(command) => run_profile(events.clone(), command, cli.json).await,
+    };
+    let outcome = result.unwrap_or_else(|failure| Outcome {
+        code: failure.code,
+        message: failure.message,
+        data: Value::Null,
+    });
+
+    if cli.json {
+        println!("{}", json!({
+            "event": "RESULT",
+            "payload": { "exitCode": outcome.code, "message": outcome.message, "data": outcome.data },
+        }));
+    } else if outcome.code == EXIT_OK || outcome.code == EXIT_OUT_OF_DATE {
+        println!("{}", outcome.message);
+    } else {
+        eprintln!("error: {}", outcome.message);
+    }
+    ExitCode::from(outcome.code)
+}
+
+fn run_export(events: &dyn EventSink, args: ExportArgs) -> Result<Outcome, Failure> {
+    let canonical = |path: &Path| fs::canonicalize(path)
+        .map_err(|e| Failure::from(format!("Failed to read {}: {}", path.to_string_lossy(), e)));
+    let paths = args.paths.iter().map(|p| canonical(p)).collect::<Result<Vec<_>, _>>()?;
+    let compress = args.compress.iter().map(|p| canonical(p)).collect::<Result<Vec<_>, _>>()?;
+    let update_package = args.update_package.iter().map(|p| canonical(p)).collect::<Result<Vec<_>, _>>()?;
+    let chunked = args.chunked.iter().map(|p| canonical(p)).collect::<Result<Vec<_>, _>>()?;
+    let compression = args.compression.iter()
+        .map(|(p, c)| canonical(p).map(|p| (p, *c)))
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    let group_assignments = args.group_assignments.iter()
+        .map(|(p, g)| canonical(p).map(|p| (p, g.clone())))
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    let conditions = args.conditions.iter()
+        .map(|(p, c)| canonical(p).map(|p| (p, c.clone())))
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    let groups = match &args.groups {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.to_string_lossy(), e))?;
+            Some(serde_json::from_str::<Vec<FileGroup>>(&text)
+                .map_err(|e| format!("Invalid groups file {}: {}", path.to_string_lossy(), e))?)
+        }
+        None => None,
+    };
+    let hooks = match &args.hooks {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.to_string_lossy(), e))?;
+            Some(serde_json::from_str::<SyncHooks>(&text)
+                .map_err(|e| format!("Invalid hooks file {}: {}", path.to_string_lossy(), e))?)
+        }
+        None => None,
+    };
+
+    for option in compress.iter().chain(&update_package).chain(&chunked).chain(compression.keys()).chain(group_assignments.keys()).chain(conditions.keys()) {
+        if !paths.contains(option) {
+            return Err(Failure {
+                code: EXIT_USAGE,
+                message: format!("{} is not one of the exported paths", option.to_string_lossy()),
+            });
+        }
+    }
+
+    let files: Vec<FileItem> = paths.iter().map(|path| {
+        let is_directory = path.is_dir();
+        let is_update_package = is_directory && update_package.contains(path);
+        FileItem {
+            path: path.to_string_lossy().into_owned(),
+            name: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            is_directory,
+            size: fs::metadata(path).ok().filter(|_| !is_directory).map(|m| m.len()),
+            selected: true,
+            compress: Some(is_directory && (compress.contains(path) || is_update_package)),
+            is_update_package: Some(is_update_package),
+            exclusions: None,
+            chunked: Some(chunked.contains(path)),
+            compression: compression.get(path).copied(),
+            group: group_assignments.get(path).cloned(),
+            conditions: conditions.get(path).cloned(),
+        }
+    }).collect();
+
+    let settings = ExportSettings {
+        package_name: args.package_name,
+        download_prefix: args.download_prefixes,
+        version: args.version,
+        description: args.description,
+        disable_hash_check: Some(args.disable_hash_check),
+        disable_size_check: Some(args.disable_size_check),
+        previous_package: args.previous_package,
+        previous_manifest: args.previous_manifest,
+        signing_key: args.signing_key,
+        max_part_size: args.max_part_size,
+        groups,
+        hooks,
+        exclusions: args.exclusions,
+        output_mode: if args.directory {
+            OutputMode::Directory
+        } else if args.manifest_only {
+            OutputMode::ManifestOnly
+        } else {
+            OutputMode::Zip
+        },
+    };
+
+    let output = args.output.to_string_lossy().into_owned();
+    let message = export::export_files(events, files, settings, output.clone())?;
+    Ok(Outcome { code: EXIT_OK, message, data: json!({ "output": output }) })
+}
+
+fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let (number, unit) = match text.char_indices().find(|(_, c)| c.is_ascii_alphabetic()) {
+        Some((i, _)) => text.split_at(i),
+        None => (text, ""),
+    };
+    let multiplier: u64 = match unit.to_ascii_uppercase().trim_end_matches(['B', 'I']) {
+        "" => 1,
+        "K" => 1024,
+        "M" => 1024 * 1024,
+        "G" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown size unit: {}", unit)),
+    };
+    let number: u64 = number.trim().parse().map_err(|_| format!("Invalid size: {}", text))?;
+    match number.checked_mul(multiplier) {
+        Some(0) => Err("Size must be greater than zero".to_string()),
+        Some(size) => Ok(size),
+        None => Err(format!("Size too large: {}", text)),
+    }
+}
+
+fn parse_group(spec: &str) -> Result<(PathBuf, String), String> {
+    let (path, group) = spec.rsplit_once('=')
+        .ok_or_else(|| format!("Expected PATH=GROUP_ID, got {}", spec))?;
+    Ok((PathBuf::from(path), group.to_string()))
+}
+
+fn parse_condition(spec: &str) -> Result<(PathBuf, FileCondition), String> {
+    let (path, condition) = spec.split_once('=')
+        .ok_or_else(|| format!("Expected PATH=KEY:VALUE[,KEY:VALUE...], got {}", spec))?;
+    Ok((PathBuf::from(path), condition.parse()?))
+}
+
+fn parse_compression(spec: &str) -> Result<(PathBuf, Compression), String> {
+    let (path, method) = spec.rsplit_once('=')
+        .ok_or_else(|| format!("Expected PATH=METHOD[:LEVEL], got {}", spec))?;
+    Ok((PathBuf::from(path), method.parse()?))
+}
+
+// 读取清单并校验签名和路径，不通过时以 EXIT_VERIFY_FAILED 退出
+async fn load_manifest(source: &ManifestArgs) -> Result<(Manifest, SignatureInfo), Failure> {
+    let text = manifest::read_manifest_source(&source.manifest).await?;
+    let signature = manifest::verify_manifest_text(&text, &source.manifest, Some(source.trusted_keys.clone()))
+        .map_err(|message| Failure { code: EXIT_VERIFY_FAILED, message })?;
+    let manifest = manifest::parse_manifest(&text).map_err(|e| format!("Failed to parse manifest: {}", e))?;
+    sync::validate_manifest_paths(&manifest.files)?;
+    Ok((manifest, signature))
+}
+
+// 目标目录中保存的排除列表加上命令行额外指定的路径
+fn excluded_files(target: &str, check: &CheckArgs) -> Result<Vec<String>, Failure> {
+    let mut excluded = sync::load_exclusion_list(target.to_string())?;
+    excluded.extend(check.excluded_files.iter().cloned());
+    Ok(excluded)
+}
+
+// 目标目录中保存的组选择（没有时为各组默认开关）再按命令行开关调整
+fn enabled_groups(manifest: &Manifest, target: &str, check: &CheckArgs) -> Result<Option<Vec<String>>, Failure> {
+    let saved = sync::load_group_selection(target.to_string())?;
+    if check.enabled_groups.is_empty() && check.disabled_groups.is_empty() {
+        return Ok(saved);
+    }
+    let known: Vec<&str> = manifest.groups.iter().flatten().map(|group| group.id.as_str()).collect();
+    if let Some(unknown) = check.enabled_groups.iter().chain(&check.disabled_groups).find(|id| !known.contains(&id.as_str())) {
+        return Err(Failure {
+            code: EXIT_USAGE,
+            message: format!("Unknown group: {} (available: {})", unknown, known.join(", ")),
+        });
+    }
+    let mut groups = manifest.enabled_groups(saved.as_deref());
+    groups.extend(check.enabled_groups.iter().cloned());
+    for id in &check.disabled_groups {
+        groups.remove(id);
+    }
+    Ok(Some(groups.into_iter().collect()))
+}
+
+fn diff_target(manifest: Manifest, target: &str, check: &CheckArgs) -> Result<Vec<sync::DiffFile>, Failure> {
+    let groups = enabled_groups(&manifest, target, check)?;
+    Ok(sync::calculate_diff(
+        manifest,
+        target.to_string(),
+        excluded_files(target, check)?,
+        groups,
+        check.disable_hash_check,
+        check.disable_size_check,
+        Some(check.force_rescan),
+    )?)
+}
+
+async fn run_diff(args: DiffArgs, json: bool) -> Result<Outcome, Failure> {
+    let (manifest, _) = load_manifest(&args.source).await?;
+    let diff = diff_target(manifest, &args.target, &args.check)?;
+    let platform = Platform::detect(Path::new(&args.target));
+
+    let changed: Vec<&sync::DiffFile> = diff.iter()
+        .filter(|file| !matches!(file.status, FileStatus::Unchanged | FileStatus::Excluded))
+        .collect();
+    if !json {
+        for file in &changed {
+            println!("{:<12} {}", format!("{:?}", file.status), file.path);
+        }
+    }
+    Ok(Outcome {
+        code: EXIT_OK,
+        message: format!("{} of {} file(s) differ from the manifest", changed.len(), diff.len()),
+        data: json!({ "files": diff, "platform": platform }),
+    })
+}
+
+async fn run_verify(args: VerifyArgs, json: bool) -> Result<Outcome, Failure> {
+    let (manifest, signature) = load_manifest(&args.source).await?;
+    let signature_line = match &signature.public_key {
+        Some(key) => format!("Manifest of {} is signed ({:?}) by {}", signature.package_name, signature.status, key),
+        None => format!("Manifest of {} is not signed", signature.package_name),
+    };
+
+    let Some(target) = &args.target else {
+        return Ok(Outcome { code: EXIT_OK, message: signature_line, data: json!({ "signature": signature }) });
+    };
+
+    // 压缩包总是重新下载，无法判断是否最新，不计入不一致
+    let diff = diff_target(manifest, target, &args.check)?;
+    let outdated: Vec<&sync::DiffFile> = diff.iter()
+        .filter(|file| matches!(file.status, FileStatus::New | FileStatus::Modified | FileStatus::Extra))
+        .collect();
+    if !json {
+        for file in &outdated {
+            println!("{:<12} {}", format!("{:?}", file.status), file.path);
+        }
+    }
+
+    let (code, summary) = if outdated.is_empty() {
+        (EXIT_OK, format!("{} is up to date", target))
+    } else {
+        (EXIT_OUT_OF_DATE, format!("{} is out of date: {} file(s) differ", target, outdated.len()))
+    };
+    Ok(Outcome {
+        code,
+        message: format!("{}\n{}", signature_line, summary),
+        data: json!({ "signature": signature, "files": outdated }),
+    })
+}
+
+async fn run_sync(events: Arc<dyn EventSink>, args: SyncArgs) -> Result<Outcome, Failure> {
+    if args.max_concurrent.is_some() || args.limit_kbps.is_some() || args.host_limit_kbps.is_some() {
+        let limits = scheduler::limits();
+        scheduler::override_limits(scheduler::DownloadLimits {
+            max_concurrent: args.max_concurrent.unwrap_or(limits.max_concurrent),
+            global_bytes_per_sec: args.limit_kbps.map_or(limits.global_bytes_per_sec, |kbps| kbps * 1024),
+            per_host_bytes_per_sec: args.host_limit_kbps.map_or(limits.per_host_bytes_per_sec, |kbps| kbps * 1024),
+        });
+    }
+    if args.keep_snapshots.is_some() || args.snapshot_max_size.is_some() {
+        let retention = snapshots::retention();
+        snapshots::override_retention(snapshots::SnapshotRetention {
+            max_snapshots: args.keep_snapshots.unwrap_or(retention.max_snapshots),
+            max_bytes: args.snapshot_max_size.unwrap_or(retention.max_bytes),
+        });
+    }
+
+    let (manifest, _) = load_manifest(&args.source).await?;
+    let package = format!("{} {}", manifest.package_name, manifest.version);
+    let excluded = excluded_files(&args.target, &args.check)?;
+    let groups = enabled_groups(&manifest, &args.target, &args.check)?;
+    if args.approve_hooks {
+        let pending = hooks::pending_runs(&manifest, Path::new(&args.target))?;
+        hooks::approve_runs(Path::new(&args.target), pending.into_iter().map(|run| run.approval).collect())?;
+    }
+
+    let handle = if manifest::is_local_package(&args.source.manifest) {
+        sync::sync_from_local_package(
+            events,
+            args.source.manifest.clone(),
+            args.target.clone(),
+            excluded,
+            groups,
+            Some(args.source.trusted_keys.clone()),
+            args.check.disable_hash_check,
+            args.check.disable_size_check,
+            Some(args.check.force_rescan),
+        ).await?
+    } else {
+        sync::start_download(
+            events,
+            manifest,
+            args.target.clone(),
+            excluded,
+            groups,
+            args.check.disable_hash_check,
+            args.check.disable_size_check,
+            Some(args.check.force_rescan),
+        ).await?
+    };
+
+    wait_for_sync(handle, &args.target, &package).await
+}
+
+// 等待同步结束；Ctrl+C 时取消会话并保留已下载的部分，下次运行可以续传
+async fn wait_for_sync(handle: sync::SyncHandle, target: &str, package: &str) -> Result<Outcome, Failure> {
+    let session_id = handle.session_id.clone();
+    let finished = handle.wait();
+    tokio::pin!(finished);
+    let result = tokio::select! {
+        result = &mut finished => result,
+        _ = tokio::signal::ctrl_c() => {
+            let _ = session::cancel(&session_id, true);
+            finished.await
+        }
+    };
+
+    let report = result?;
+    match report.status {
+        SyncStatus::Completed => Ok(Outcome {
+            code: EXIT_OK,
+            message: format!(
+                "{} is up to date with {} ({} updated, {} deleted, {} bytes transferred in {:.1}s)",
+                target, package, report.succeeded.len(), report.deleted.len(),
+                report.bytes_transferred, report.duration_ms as f64 / 1000.0
+            ),
+            data: json!({ "sessionId": session_id, "package": package, "report": report }),
+        }),
+        SyncStatus::Cancelled => Err(Failure {
+            code: EXIT_INTERRUPTED,
+            message: format!("Sync interrupted, {} left unchanged", target),
+        }),
+        SyncStatus::Failed => Err(Failure::from(report.error_message())),
+    }
+}
+
+fn run_snapshots(args: SnapshotsArgs, json: bool) -> Result<Outcome, Failure> {
+    let snapshots = snapshots::list(Path::new(&args.target))?;
+    if !json {
+        for snapshot in &snapshots {
+            let reason = match &snapshot.reason {
+                SnapshotReason::Sync { package_name, version } => format!("before sync of {} {}", package_name, version),
+                SnapshotReason::Restore { snapshot_id } => format!("before restore to {}", snapshot_id),
+            };
+            println!(
+                "{}  {}  {} ({} backed up, {} created, {} bytes)",
+                snapshot.id, snapshot.created_at, reason, snapshot.files.len(), snapshot.created.len(), snapshot.size
+            );
+        }
+    }
+    Ok(Outcome {
+        code: EXIT_OK,
+        message: format!("{} snapshot(s) of {}", snapshots.len(), args.target),
+        data: json!({ "snapshots": snapshots }),
+    })
+}
+
+fn run_restore(args: RestoreArgs) -> Result<Outcome, Failure> {
+    let changed = snapshots::restore(Path::new(&args.target), &args.snapshot)?;
+    Ok(Outcome {
+        code: EXIT_OK,
+        message: format!("Restored {} to snapshot {} ({} file(s) changed)", args.target, args.snapshot, changed.len()),
+        data: json!({ "files": changed }),
+    })
+}
+
+async fn run_profile(events: Arc<dyn EventSink>, command: ProfileCommand, json: bool) -> Result<Outcome, Failure> {
+    match command {
+        ProfileCommand::List => {
+            let profiles = profiles::list()?;
+            if !json {
+                for profile in &profiles {
+                    let synced = match (&profile.last_synced_version, &profile.last_synced_at) {
+                        (Some(version), Some(at)) => format!("synced {} at {}", version, at),
+                        _ => "never synced".to_string(),
+                    };
+                    println!("{}  {}  {} -> {} ({})", profile.id, profile.name, profile.source, profile.target_dir, synced);
+                }
+            }
+            Ok(Outcome {
+                code: EXIT_OK,
+                message: format!("{} profile(s)", profiles.len()),
+                data: json!({ "profiles": profiles }),
+            })
+        }
+        ProfileCommand::Create(args) => {
+            let profile = profiles::create(SyncProfile {
+                id: String::new(),
+                name: args.name,
+                source: args.source,
+                target_dir: args.target,
+                trusted_keys: args.trusted_keys,
+                enabled_groups: (!args.groups.is_empty()).then_some(args.groups),
+                disable_hash_check: args.disable_hash_check,
+                disable_size_check: args.disable_size_check,
+                force_rescan: args.force_rescan,
+                auto_sync: args.auto_sync,
+                last_synced_version: None,
+                last_synced_at: None,
+            })?;
+            Ok(Outcome {
+                code: EXIT_OK,
+                message: format!("Created profile {} ({})", profile.name, profile.id),
+                data: json!({ "profile": profile }),
+            })
+        }
+        ProfileCommand::Update(args) => {
+            let mut profile = profiles::get(&args.profile)?;
+            profile.name = args.name.unwrap_or(profile.name);
+            profile.source = args.source.unwrap_or(profile.source);
+            profile.target_dir = args.target.unwrap_or(profile.target_dir);
+            profile.trusted_keys = args.trusted_keys.unwrap_or(profile.trusted_keys);
+            if args.saved_groups {
+                profile.enabled_groups = None;
+            } else if let Some(groups) = args.groups {
+                profile.enabled_groups = Some(groups);
+            }
+            profile.disable_hash_check = args.disable_hash_check.unwrap_or(profile.disable_hash_check);
+            profile.disable_size_check = args.disable_size_check.unwrap_or(profile.disable_size_check);
+            profile.force_rescan = args.force_rescan.unwrap_or(profile.force_rescan);
+            profile.auto_sync = args.auto_sync.unwrap_or(profile.auto_sync);
+            let profile = profiles::update(profile)?;
+            Ok(Outcome {
+                code: EXIT_OK,
+                message: format!("Updated profile {}", profile.name),
+                data: json!({ "profile": profile }),
+            })
+        }
+        ProfileCommand::Delete { profile } => {
+            let profile = profiles::get(&profile)?;
+            profiles::delete(&profile.id)?;
+            Ok(Outcome { code: EXIT_OK, message: format!("Deleted profile {}", profile.name), data: Value::Null })
+        }
+        ProfileCommand::Run { profile, approve_hooks } => {
+            let profile = profiles::get(&profile)?;
+            let manifest = profiles::load_manifest(&profile).await?;
+            if approve_hooks {
+                let pending = hooks::pending_runs(&manifest, Path::new(&profile.target_dir))?;
+                hooks::approve_runs(Path::new(&profile.target_dir), pending.into_iter().map(|run| run.approval).collect())?;
+            }
+            let package = format!("{} {}", manifest.package_name, manifest.version);
+            let target = profile.target_dir.clone();
+            let handle = profiles::run(events, profile, manifest, SyncTrigger::Manual).await?;
+            wait_for_sync(handle, &target, &package).await
+        }
+        ProfileCommand::Check => {
+            let statuses = updates::check_all(&*events).await?;
+            if !json {
+                for status in &statuses {
+                    let state = match (&status.error, status.update_available) {
+                        (Some(error), _) => format!("check failed: {}", error),
+                        (None, true) => "update available".to_string(),
+                        (None, false) => "up to date".to_string(),
+                    };
+                    println!(
+                        "{}  {} -> {} ({})",
+                        status.profile_name,
+                        status.current_version.as_deref().unwrap_or("(never synced)"),
+                        status.latest_version.as_deref().unwrap_or("?"),
+                        state
+                    );
+                }
+            }
+            let available = statuses.iter().filter(|status| status.update_available).count();
+            Ok(Outcome {
+                code: EXIT_OK,
+                message: format!("{} of {} profile(s) have updates", available, statuses.len()),
+                data: json!({ "statuses": statuses }),
+            })
+        }
+        ProfileCommand::History { profile, limit } => {
+            let profile_id = profile.map(|profile| profiles::get(&profile)).transpose()?.map(|profile| profile.id);
+            let entries = history::list(profile_id.as_deref(), limit)?;
+            if !json {
+                for entry in entries.iter().rev() {
+                    let event = match &entry.event {
+                        HistoryEvent::UpdateAvailable { version, current_version } => format!(
+                            "update available: {} -> {}", current_version.as_deref().unwrap_or("(never synced)"), version
+                        ),
+                        HistoryEvent::SyncStarted { trigger, version, .. } => format!("{:?} sync of {} started", trigger, version),
+                        HistoryEvent::SyncFinished { trigger, version, status, error, .. } => format!(
+                            "{:?} sync of {} {:?}{}", trigger, version, status,
+                            error.as_ref().map(|error| format!(": {}", error)).unwrap_or_default()
+                        ),
+                        HistoryEvent::Error { error } => format!("error: {}", error),
+                    };
+                    println!("{}  {}  {}", entry.at, entry.profile_name, event);
+                }
+            }
+            Ok(Outcome {
+                code: EXIT_OK,
+                message: format!("{} history entries", entries.len()),
+                data: json!({ "entries": entries }),
+            })
+        }
+        ProfileCommand::Watch { interval } => {
+            updates::override_settings(UpdateCheckSettings { enabled: true, interval_minutes: interval })?;
+            tokio::select! {
+                _ = updates::run_scheduler(events) => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            // 正在进行的自动同步取消后保持目录不变，等它回滚结束再退出
+            session::cancel_all(true);
+            while session::any_running() {
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            Ok(Outcome { code: EXIT_OK, message: "Stopped watching for updates".to_string(), data: Value::Null })
+        }
+    }
+}
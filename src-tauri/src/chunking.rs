@@ -0,0 +1,346 @@
+use fastcdc::v2020::StreamCDC;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::mirrors;
+use crate::paths;
+use crate::session::SyncSession;
+
+// 分块参数需要在导出端和同步端保持一致，否则本地旧文件切出的块无法复用
+pub const MIN_CHUNK_SIZE: u32 = 256 * 1024;
+pub const AVG_CHUNK_SIZE: u32 = 1024 * 1024;
+pub const MAX_CHUNK_SIZE: u32 = 4 * 1024 * 1024;
+
+// 小于该大小的文件即使勾选了分块也按普通文件导出
+pub const MIN_CHUNKED_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+// 缓存本地文件（以文件哈希为键）的分块结果，避免每次同步都重新切块
+const CHUNK_CACHE_FILE_NAME: &str = ".sync_chunk_cache.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FileChunk {
+    pub hash: String,
+    pub size: u64,
+}
+
+// 本地可复用的块所在的位置
+#[derive(Debug, Clone)]
+pub struct ChunkLocation {
+    path: PathBuf,
+    offset: u64,
+    size: u64,
+}
+
+// 块哈希会拼进下载地址，只接受小写十六进制的 SHA-256
+pub fn validate_chunk_hash(hash: &str) -> Result<(), String> {
+    if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return Err(paths::unsafe_path(hash, "is not a valid chunk hash"));
+    }
+    Ok(())
+}
+
+// 块在CDN上按内容寻址存放：<base>/<hash前两位>/<hash>，`hash` 须已通过 validate_chunk_hash
+pub fn chunk_object_path(hash: &str) -> String {
+    format!("{}/{}", &hash[..2.min(hash.len())], hash)
+}
+
+// 按内容定义分块切分文件，每个块连同数据交给 `on_chunk`
+pub fn chunk_file<F>(path: &Path, mut on_chunk: F) -> Result<Vec<FileChunk>, String>
+where
+    F: FnMut(&FileChunk, &[u8]) -> Result<(), String>,
+{
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.to_string_lossy(), e))?;
+    let mut chunks = Vec::new();
+    for result in StreamCDC::new(file, MIN_CHUNK_SIZE, AVG_CHUNK_SIZE, MAX_CHUNK_SIZE) {
+        let data = result.map_err(|e| format!("Failed to chunk {}: {}", path.to_string_lossy(), e))?;
+        let chunk = FileChunk {
+            hash: hex::encode(Sha256::digest(&data.data)),
+            size: data.length as u64,
+        };
+        on_chunk(&chunk, &data.data)?;
+        chunks.push(chunk);
+    }
+    Ok(chunks)
+}
+
+// 对本地已有的文件切块，返回 块哈希 -> 位置。
+// `candidates` 为 (本地路径, 文件哈希)，分块结果按文件哈希缓存在目标目录中。
+pub fn build_local_chunk_sources(target_dir: &Path, candidates: &[(PathBuf, String)]) -> HashMap<String, ChunkLocation> {
+    let cache_path = target_dir.join(CHUNK_CACHE_FILE_NAME);
+    let cache: HashMap<String, Vec<FileChunk>> = fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+
+    let mut sources = HashMap::new();
+    let mut used_chunks = HashMap::new();
+    for (path, file_hash) in candidates {
+        let chunks = match cache.get(file_hash) {
+            Some(chunks) => chunks.clone(),
+            None => match chunk_file(path, |_, _| Ok(())) {
+                Ok(chunks) => chunks,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            },
+        };
+
+        let mut offset = 0;
+        for chunk in &chunks {
+            sources.entry(chunk.hash.clone()).or_insert_with(|| ChunkLocation {
+                path: path.clone(),
+                offset,
+                size: chunk.size,
+            });
+            offset += chunk.size;
+        }
+        used_chunks.insert(file_hash.clone(), chunks);
+    }
+
+    // 只保留本次仍然存在的文件版本，防止缓存无限增长
+    if let Ok(json) = serde_json::to_string(&used_chunks) {
+        let _ = fs::write(&cache_path, json);
+    }
+
+    sources
+}
+
+fn read_chunk_at(path: &Path, offset: u64, size: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut data = vec![0; size as usize];
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// 下载一个块：与整个文件的下载一样按镜像健康状态选择地址，失败或哈希不符时换镜像并退避重试
+async fn fetch_chunk(client: &reqwest::Client, session: &SyncSession, base_urls: &[String], hash: &str) -> Result<Vec<u8>, String> {
+    let max_retries = base_urls.len().max(3);
+    let mut failed_urls: Vec<String> = Vec::new();
+    let mut last_error = None;
+    for attempt in 0..max_retries {
+        if attempt > 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(crate::scheduler::backoff_delay(attempt as u32 - 1)) => {}
+                _ = session.cancelled() => {}
+            }
+            session.checkpoint().await?;
+        }
+
+        let Some(base_url) = mirrors::pick(base_urls, &failed_urls) else { break };
+        let url = format!("{}{}", base_url, chunk_object_path(hash));
+        let started = Instant::now();
+        let fetched = async {
+            let data = client.get(&url).send().await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| e.to_string())?
+                .bytes().await
+                .map_err(|e| e.to_string())?
+                .to_vec();
+            crate::scheduler::throttle(&url, data.len() as u64).await;
+            if hex::encode(Sha256::digest(&data)) != hash {
+                return Err("hash mismatch".to_string());
+            }
+            Ok(data)
+        }.await;
+
+        match fetched {
+            Ok(data) => {
+                mirrors::record_success(&base_url, data.len() as u64, started.elapsed());
+                return Ok(data);
+            }
+            Err(e) => {
+                eprintln!("Failed to download chunk {} from {}: {}. Attempt {}/{}", hash, base_url, e, attempt + 1, max_retries);
+                mirrors::record_failure(&base_url);
+                failed_urls.push(base_url);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(format!("Failed to download chunk {}: {}", hash, last_error.unwrap_or_else(|| "no download source left".to_string())))
+}
+
+// 按清单中的块列表组装文件：本地已有的块直接复制，其余从 `base_urls`（主地址和镜像）下载。
+// 每写入一个块调用一次 `on_progress(已写入字节数)`，返回实际下载的字节数。
+pub async fn assemble_chunked_file<F>(
+    client: &reqwest::Client,
+    session: &SyncSession,
+    base_urls: &[String],
+    chunks: &[FileChunk],
+    local_sources: &HashMap<String, ChunkLocation>,
+    output_path: &Path,
+    mut on_progress: F,
+) -> Result<u64, String>
+where
+    F: FnMut(u64),
+{
+    let mut output = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path)
+        .map_err(|e| format!("Failed to create {}: {}", output_path.to_string_lossy(), e))?;
+
+    // 同一文件内重复出现的块直接从已写入的部分读取
+    let mut written: HashMap<&str, (u64, u64)> = HashMap::new();
+    let mut offset = 0;
+    let mut downloaded = 0;
+
+    for chunk in chunks {
+        session.checkpoint().await?;
+        let local = if let Some(&(at, size)) = written.get(chunk.hash.as_str()) {
+            let data = (|| -> std::io::Result<Vec<u8>> {
+                let mut data = vec![0; size as usize];
+                output.seek(SeekFrom::Start(at))?;
+                output.read_exact(&mut data)?;
+                Ok(data)
+            })();
+            output.seek(SeekFrom::Start(offset)).map_err(|e| e.to_string())?;
+            data.ok()
+        } else {
+            local_sources.get(&chunk.hash)
+                .filter(|location| location.size == chunk.size)
+                .and_then(|location| read_chunk_at(&location.path, location.offset, location.size).ok())
+        };
+
+        // 本地文件可能在切块后被修改过，复用前重新校验
+        let data = match local.filter(|data| hex::encode(Sha256::digest(data)) == chunk.hash) {
+            Some(data) => data,
+            None => {
+                let data = fetch_chunk(client, session, base_urls, &chunk.hash).await?;
+                downloaded += data.len() as u64;
+                data
+            }
+        };
+
+        output.write_all(&data).map_err(|e| e.to_string())?;
+        written.insert(&chunk.hash, (offset, chunk.size));
+        offset += chunk.size;
+        on_progress(offset);
+    }
+
+    output.flush().map_err(|e| e.to_string())?;
+    Ok(downloaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("acgstation-chunking-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 伪随机内容，切块后得到多个块
+    fn sample_data(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as u8
+        }).collect()
+    }
+
+    fn file_hash(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    #[test]
+    fn validates_chunk_hashes() {
+        let hash = file_hash(b"chunk");
+        assert!(validate_chunk_hash(&hash).is_ok());
+        assert_eq!(chunk_object_path(&hash), format!("{}/{}", &hash[..2], hash));
+        for invalid in ["", "../x", &hash.to_uppercase(), &hash[..63], &format!("{}0", hash), &format!("é{}", &hash[2..])] {
+            assert!(validate_chunk_hash(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn caches_chunks_of_local_files() {
+        let dir = temp_dir();
+        let data = sample_data(3 * MAX_CHUNK_SIZE as usize, 1);
+        let path = dir.join("a.bin");
+        fs::write(&path, &data).unwrap();
+        let chunks = chunk_file(&path, |_, _| Ok(())).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.iter().map(|chunk| chunk.size).sum::<u64>(), data.len() as u64);
+
+        let sources = build_local_chunk_sources(&dir, &[(path.clone(), file_hash(&data))]);
+        assert_eq!(sources.len(), chunks.len());
+        let cache: HashMap<String, Vec<FileChunk>> =
+            serde_json::from_str(&fs::read_to_string(dir.join(CHUNK_CACHE_FILE_NAME)).unwrap()).unwrap();
+        assert_eq!(cache.get(&file_hash(&data)), Some(&chunks));
+
+        // 缓存命中时不再读取文件；不再出现的文件版本从缓存中移除
+        fs::remove_file(&path).unwrap();
+        assert_eq!(build_local_chunk_sources(&dir, &[(path.clone(), file_hash(&data))]).len(), chunks.len());
+        assert!(build_local_chunk_sources(&dir, &[]).is_empty());
+        assert_eq!(fs::read_to_string(dir.join(CHUNK_CACHE_FILE_NAME)).unwrap(), "{}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn reassembles_files_from_local_chunks() {
+        let dir = temp_dir();
+        let old = sample_data(3 * MAX_CHUNK_SIZE as usize, 2);
+        let old_path = dir.join("old.bin");
+        fs::write(&old_path, &old).unwrap();
+        let old_chunks = chunk_file(&old_path, |_, _| Ok(())).unwrap();
+
+        // 新文件由旧文件的块重新排列并重复组成，不需要下载任何块
+        let mut new_chunks = old_chunks.clone();
+        new_chunks.reverse();
+        new_chunks.push(old_chunks[0].clone());
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+        for chunk in &old_chunks {
+            offsets.insert(chunk.hash.clone(), offset as usize);
+            offset += chunk.size;
+        }
+        let expected: Vec<u8> = new_chunks.iter()
+            .flat_map(|chunk| {
+                let at = offsets[&chunk.hash];
+                old[at..at + chunk.size as usize].to_vec()
+            })
+            .collect();
+
+        let sources = build_local_chunk_sources(&dir, &[(old_path, file_hash(&old))]);
+        let session = SyncSession::detached(&dir);
+        let output = dir.join("new.bin.part");
+        let mut progress = Vec::new();
+        let downloaded = assemble_chunked_file(
+            crate::scheduler::client(),
+            &session,
+            &[],
+            &new_chunks,
+            &sources,
+            &output,
+            |written| progress.push(written),
+        ).await.unwrap();
+
+        assert_eq!(downloaded, 0);
+        assert_eq!(fs::read(&output).unwrap(), expected);
+        assert_eq!(progress.len(), new_chunks.len());
+        assert_eq!(progress.last().copied(), Some(expected.len() as u64));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn fails_when_a_chunk_is_unavailable() {
+        let dir = temp_dir();
+        let data = sample_data(1024, 3);
+        let chunks = vec![FileChunk { hash: file_hash(&data), size: data.len() as u64 }];
+        let session = SyncSession::detached(&dir);
+        let result = assemble_chunked_file(crate::scheduler::client(), &session, &[], &chunks, &HashMap::new(), &dir.join("a.part"), |_| {}).await;
+        assert!(result.is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fs;
+use std::path::Path;
+
+// 补丁格式：以旧文件全部内容作为 zstd 原始字典压缩新文件（即 `zstd --patch-from`），
+// 未变化的区域会被编码为对旧文件的引用。
+
+// 旧/新文件超过该大小时不生成补丁，保证窗口能覆盖两者（且内存占用可控）
+pub const MAX_PATCH_SOURCE_SIZE: u64 = 512 * 1024 * 1024;
+
+const PATCH_COMPRESSION_LEVEL: i32 = 15;
+
+// zstd 在 64 位平台上允许的最大窗口
+const MAX_WINDOW_LOG: u32 = 31;
+
+fn window_log_for(len: u64) -> u32 {
+    let bits = 64 - len.max(1).saturating_sub(1).leading_zeros();
+    bits.clamp(10, MAX_WINDOW_LOG)
+}
+
+// 生成从旧内容到新内容的补丁，调用方需保证两者都不超过 MAX_PATCH_SOURCE_SIZE
+pub fn create_patch(old: &[u8], new: &[u8]) -> Result<Vec<u8>, String> {
+    let mut compressor = zstd::bulk::Compressor::new(PATCH_COMPRESSION_LEVEL).map_err(|e| e.to_string())?;
+    compressor.set_parameter(zstd::zstd_safe::CParameter::WindowLog(window_log_for((old.len() + new.len()) as u64)))
+        .map_err(|e| e.to_string())?;
+    compressor.set_parameter(zstd::zstd_safe::CParameter::EnableLongDistanceMatching(true))
+        .map_err(|e| e.to_string())?;
+    compressor.set_dictionary(PATCH_COMPRESSION_LEVEL, old).map_err(|e| e.to_string())?;
+    compressor.compress(new).map_err(|e| format!("Failed to create patch: {}", e))
+}
+
+// 用本地旧文件和补丁还原出新文件，写入 `output_path`
+pub fn apply_patch(old_path: &Path, patch_path: &Path, output_path: &Path, expected_size: u64) -> Result<(), String> {
+    if expected_size > MAX_PATCH_SOURCE_SIZE {
+        return Err(format!("Patch target is too large: {} bytes", expected_size));
+    }
+
+    let old = fs::read(old_path).map_err(|e| format!("Failed to read patch source: {}", e))?;
+    let patch = fs::read(patch_path).map_err(|e| format!("Failed to read patch: {}", e))?;
+
+    let mut decompressor = zstd::bulk::Decompressor::with_dictionary(&old).map_err(|e| e.to_string())?;
+    decompressor.set_parameter(zstd::zstd_safe::DParameter::WindowLogMax(MAX_WINDOW_LOG))
+        .map_err(|e| e.to_string())?;
+    let new = decompressor.decompress(&patch, expected_size as usize)
+        .map_err(|e| format!("Failed to apply patch: {}", e))?;
+
+    if new.len() as u64 != expected_size {
+        return Err(format!("Patched file has size {}, expected {}", new.len(), expected_size));
+    }
+
+    fs::write(output_path, new).map_err(|e| format!("Failed to write patched file: {}", e))
+}
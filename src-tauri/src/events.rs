@@ -0,0 +1,16 @@
+use serde::Serialize;
+
+// 进度事件的接收方：图形界面转发给前端窗口，命令行输出到终端
+pub trait EventSink: Send + Sync {
+    fn emit_json(&self, event: &str, payload: serde_json::Value);
+}
+
+impl dyn EventSink + '_ {
+    // 事件名和负载格式与前端监听的一致，例如 DOWNLOAD_PROGRESS、OVERALL_PROGRESS
+    pub fn emit<S: Serialize>(&self, event: &str, payload: S) {
+        match serde_json::to_value(payload) {
+            Ok(value) => self.emit_json(event, value),
+            Err(e) => eprintln!("Failed to serialize {} event: {}", event, e),
+        }
+    }
+}
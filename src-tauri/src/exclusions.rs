@@ -0,0 +1,109 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// 排除规则，语法与 .gitignore 相同：`*`、`?`、`[...]`、`**`，以 `/` 结尾的规则只匹配目录，
+// 以 `/` 开头或中间含 `/` 的规则相对根目录，`!` 开头表示重新包含之前排除的路径。
+// 不含 `*`、`?` 且不以 `!`、`/` 开头或以 `/` 结尾的条目按旧版的精确路径处理，
+// 只匹配该路径本身（是目录时包括其下所有文件），文件名中的 `[` `]` 不会被当作通配符。
+pub struct Exclusions {
+    root: PathBuf,
+    exact: HashSet<String>,
+    patterns: Gitignore,
+}
+
+fn is_pattern(rule: &str) -> bool {
+    rule.contains(['*', '?']) || rule.starts_with(['!', '/']) || rule.ends_with('/')
+}
+
+impl Exclusions {
+    // `root` 为规则相对的目录；旧版导出设置中保存的绝对路径也会转换为相对路径
+    pub fn new(root: &Path, rules: &[String]) -> Result<Exclusions, String> {
+        let mut exact = HashSet::new();
+        // 规则只与相对路径匹配，不让 ignore 再按 root 截取前缀
+        let mut builder = GitignoreBuilder::new("");
+
+        for rule in rules.iter().map(|rule| rule.trim()).filter(|rule| !rule.is_empty() && !rule.starts_with('#')) {
+            if let Ok(relative) = Path::new(rule).strip_prefix(root) {
+                exact.insert(relative.to_string_lossy().replace('\\', "/"));
+            } else if is_pattern(rule) {
+                builder.add_line(None, rule)
+                    .map_err(|e| format!("Invalid exclusion pattern {}: {}", rule, e))?;
+            } else {
+                exact.insert(rule.replace('\\', "/"));
+            }
+        }
+
+        let patterns = builder.build().map_err(|e| format!("Invalid exclusion patterns: {}", e))?;
+        Ok(Exclusions { root: root.to_path_buf(), exact, patterns })
+    }
+
+    // `relative_path` 相对根目录；被排除目录下的所有路径同样视为排除
+    pub fn is_excluded(&self, relative_path: &str, is_dir: bool) -> bool {
+        let relative_path = relative_path.replace('\\', "/");
+        let relative_path = relative_path.trim_matches('/');
+        if relative_path.is_empty() {
+            return false;
+        }
+        if self.exact.contains(relative_path)
+            || relative_path.match_indices('/').any(|(i, _)| self.exact.contains(&relative_path[..i]))
+        {
+            return true;
+        }
+        self.patterns.matched_path_or_any_parents(relative_path, is_dir).is_ignore()
+    }
+
+    // 根目录之外的路径不受规则影响
+    pub fn is_excluded_path(&self, path: &Path, is_dir: bool) -> bool {
+        match path.strip_prefix(&self.root) {
+            Ok(relative) => self.is_excluded(&relative.to_string_lossy(), is_dir),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exclusions(rules: &[&str]) -> Exclusions {
+        let rules: Vec<String> = rules.iter().map(|rule| rule.to_string()).collect();
+        Exclusions::new(Path::new("/game"), &rules).unwrap()
+    }
+
+    #[test]
+    fn matches_gitignore_patterns() {
+        let exclusions = exclusions(&["*.log", "logs/", "/options.txt", "config/**/*.bak", "!keep.log", "# comment"]);
+        assert!(exclusions.is_excluded("latest.log", false));
+        assert!(exclusions.is_excluded("crash/old.log", false));
+        assert!(!exclusions.is_excluded("keep.log", false));
+        assert!(exclusions.is_excluded("logs", true));
+        assert!(exclusions.is_excluded("logs/latest.txt", false));
+        assert!(!exclusions.is_excluded("logs", false));
+        assert!(exclusions.is_excluded("options.txt", false));
+        assert!(!exclusions.is_excluded("saves/options.txt", false));
+        assert!(exclusions.is_excluded("config/a/b/c.bak", false));
+        assert!(!exclusions.is_excluded("config/c.toml", false));
+    }
+
+    #[test]
+    fn matches_exact_paths_and_their_children() {
+        let exclusions = exclusions(&["saves", "mods/[1.20] pack.jar", "/game/config/local.toml"]);
+        assert!(exclusions.is_excluded("saves", true));
+        assert!(exclusions.is_excluded("saves/world/level.dat", false));
+        assert!(!exclusions.is_excluded("saves2/level.dat", false));
+        // 精确路径中的方括号不是通配符
+        assert!(exclusions.is_excluded("mods/[1.20] pack.jar", false));
+        assert!(!exclusions.is_excluded("mods/1 pack.jar", false));
+        // 旧版保存的绝对路径
+        assert!(exclusions.is_excluded("config\\local.toml", false));
+    }
+
+    #[test]
+    fn ignores_paths_outside_root() {
+        let exclusions = exclusions(&["*.log"]);
+        assert!(exclusions.is_excluded_path(Path::new("/game/latest.log"), false));
+        assert!(!exclusions.is_excluded_path(Path::new("/other/latest.log"), false));
+        assert!(!exclusions.is_excluded("", true));
+    }
+}
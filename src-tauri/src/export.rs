@@ -0,0 +1,1383 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+use zip::{write::FileOptions, ZipArchive, ZipWriter};
+
+use crate::events::EventSink;
+use crate::exclusions::Exclusions;
+use crate::hash_index::calculate_file_hash;
+use crate::hooks::{HookAction, SyncHooks};
+use crate::platform::FileCondition;
+use crate::manifest::{self, read_manifest_text_from_zip, FileGroup, FilePart, FilePatch, Manifest, ManifestFile};
+use crate::{chunking, delta, paths, signing};
+
+// 单个文件不超过此大小时在线程池中预先压缩到内存
+const MAX_PRECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+// 每批并行处理时预压缩数据占用内存的上限
+const MAX_BUFFERED_BYTES: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileItem {
+    pub path: String,
+    pub name: String,
+    #[serde(rename = "isDirectory")]
+    pub is_directory: bool,
+    pub size: Option<u64>,
+    pub selected: bool,
+    pub compress: Option<bool>,
+    #[serde(rename = "isUpdatePackage")]
+    pub is_update_package: Option<bool>, // 是否为压缩包更新（减少服务器请求）
+    pub exclusions: Option<Vec<String>>,
+    pub chunked: Option<bool>, // 大文件按内容分块导出，同步时只下载变化的块
+    pub compression: Option<Compression>, // 包中条目的压缩方式，未设置时文件和压缩文件夹使用 Deflate，不压缩的文件夹直接存储
+    pub group: Option<String>, // 所属可选文件组的 id，对应 ExportSettings.groups
+    pub conditions: Option<FileCondition>, // 只在满足条件（系统、架构、游戏版本、加载器）的机器上同步
+}
+
+// 包中条目的压缩方式，例如 {"method": "zstd", "level": 19}；`level` 省略时使用默认级别
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "method")]
+pub enum Compression {
+    Stored,
+    Deflate { level: Option<i32> },
+    Zstd { level: Option<i32> },
+    Bzip2 { level: Option<i32> },
+}
+
+impl Compression {
+    fn validate(&self) -> Result<(), String> {
+        let (name, level, range) = match *self {
+            Compression::Stored => return Ok(()),
+            Compression::Deflate { level } => ("Deflate", level, 0..=9),
+            Compression::Zstd { level } => ("Zstd", level, -7..=22),
+            Compression::Bzip2 { level } => ("Bzip2", level, 1..=9),
+        };
+        match level {
+            Some(level) if !range.contains(&level) => Err(format!(
+                "{} compression level must be between {} and {}, got {}",
+                name, range.start(), range.end(), level
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn method(&self) -> zip::CompressionMethod {
+        match self {
+            Compression::Stored => zip::CompressionMethod::Stored,
+            Compression::Deflate { .. } => zip::CompressionMethod::Deflated,
+            Compression::Zstd { .. } => zip::CompressionMethod::Zstd,
+            Compression::Bzip2 { .. } => zip::CompressionMethod::Bzip2,
+        }
+    }
+
+    fn level(&self) -> Option<i32> {
+        match *self {
+            Compression::Stored => None,
+            Compression::Deflate { level } | Compression::Zstd { level } | Compression::Bzip2 { level } => level,
+        }
+    }
+}
+
+// 命令行中的写法：`stored`、`deflate`、`zstd:19`、`bzip2:9`
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Compression, String> {
+        let (method, level) = match text.split_once(':') {
+            Some((method, level)) => {
+                let level = level.trim().parse::<i32>().map_err(|_| format!("Invalid compression level: {}", level))?;
+                (method, Some(level))
+            }
+            None => (text, None),
+        };
+        let compression = match method.trim().to_ascii_lowercase().as_str() {
+            "stored" | "store" | "none" if level.is_none() => Compression::Stored,
+            "deflate" | "deflated" => Compression::Deflate { level },
+            "zstd" => Compression::Zstd { level },
+            "bzip2" => Compression::Bzip2 { level },
+            _ => return Err(format!("Unknown compression method: {}", text)),
+        };
+        compression.validate()?;
+        Ok(compression)
+    }
+}
+
+// 本身已经压缩过的格式再压缩几乎没有收益，直接存储
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "jar", "zip", "7z", "rar", "gz", "tgz", "xz", "bz2", "zst", "lz4",
+    "png", "jpg", "jpeg", "gif", "webp", "avif",
+    "ogg", "oga", "opus", "mp3", "m4a", "aac", "flac",
+    "mp4", "m4v", "webm", "mkv", "avi", "mov",
+    "woff", "woff2",
+];
+
+fn is_compressed_format(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str()))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportSettings {
+    #[serde(rename = "packageName")]
+    pub package_name: String,
+    // 可以填写多个前缀（数组，或按换行/逗号分隔），第一个为主地址，其余作为镜像
+    #[serde(rename = "downloadPrefix", deserialize_with = "deserialize_download_prefixes")]
+    pub download_prefix: Vec<String>,
+    pub version: String,
+    pub description: Option<String>,
+    #[serde(rename = "disableHashCheck")]
+    pub disable_hash_check: Option<bool>,
+    #[serde(rename = "disableSizeCheck")]
+    pub disable_size_check: Option<bool>,
+    #[serde(rename = "previousPackage")]
+    pub previous_package: Option<String>, // 上一版本的导出包，用于生成差量补丁
+    #[serde(rename = "signingKey")]
+    pub signing_key: Option<String>, // Ed25519 私钥文件，用于给清单签名
+    #[serde(rename = "previousManifest", default)]
+    pub previous_manifest: Option<String>, // 上一版本的 manifest.json 或导出包，只导出新增或变化的文件
+    #[serde(rename = "outputMode", default)]
+    pub output_mode: OutputMode,
+    #[serde(rename = "maxPartSize", default)]
+    pub max_part_size: Option<u64>, // 压缩文件夹生成的压缩包超过此大小（字节）时切分为多个分卷
+    #[serde(default)]
+    pub groups: Option<Vec<FileGroup>>, // 玩家可以自行开关的可选文件组
+    #[serde(default)]
+    pub hooks: Option<SyncHooks>, // 同步前后在玩家目录中执行的动作
+    #[serde(default)]
+    pub exclusions: Vec<String>, // 对所有导出项生效的排除规则，相对导出路径的父目录
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    // 单个zip包，保存路径为zip文件
+    #[default]
+    Zip,
+    // 按下载地址排布的目录，可直接上传到静态托管；保存路径为目录，manifest.json 位于根目录
+    Directory,
+    // 文件已托管在下载前缀下，只生成清单；保存路径为 manifest.json
+    ManifestOnly,
+}
+
+fn deserialize_download_prefixes<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Prefixes {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let prefixes = match Prefixes::deserialize(deserializer)? {
+        Prefixes::One(text) => text.split(['\n', ',']).map(str::to_string).collect(),
+        Prefixes::Many(list) => list,
+    };
+    Ok(prefixes.iter()
+        .map(|prefix| prefix.trim().to_string())
+        .filter(|prefix| !prefix.is_empty())
+        .collect())
+}
+
+// 增量导出时的上一版本：路径、类型和哈希都相同的文件已经在CDN上，只写入清单，不再放进上传包
+struct PreviousRelease {
+    version: String,
+    files: HashMap<String, ManifestFile>,
+}
+
+impl PreviousRelease {
+    fn load(source: &Path) -> Result<PreviousRelease, String> {
+        let previous = manifest::parse_manifest(&manifest::read_manifest_file(source)?)
+            .map_err(|e| format!("Failed to parse previous manifest: {}", e))?;
+        if previous.files.iter().any(|f| f.hash == "DISABLED") {
+            return Err("Previous manifest was exported without hashes, incremental export is not possible".to_string());
+        }
+        Ok(PreviousRelease {
+            version: previous.version,
+            files: previous.files.into_iter().map(|f| (f.relative_path.clone(), f)).collect(),
+        })
+    }
+
+    fn is_unchanged(&self, relative_path: &str, file_type: &str, hash: &str) -> bool {
+        self.files.get(relative_path).is_some_and(|f| f.file_type == file_type && f.hash == hash)
+    }
+
+    // 上一版本已上传的内容块
+    fn chunk_hashes(&self) -> impl Iterator<Item = String> + '_ {
+        self.files.values()
+            .flat_map(|f| f.chunks.as_deref().unwrap_or_default())
+            .map(|chunk| chunk.hash.clone())
+    }
+}
+
+// 导出内容写入的位置。zip 条目与目录中的文件路径相同，都是相对下载前缀的地址
+enum ExportOutput {
+    Zip(ZipWriter<File>),
+    Directory(PathBuf),
+    ManifestOnly,
+}
+
+impl ExportOutput {
+    fn create(mode: OutputMode, save_path: &Path) -> Result<ExportOutput, String> {
+        match mode {
+            OutputMode::Zip => {
+                let zip_file = fs::File::create(save_path)
+                    .map_err(|e| format!("Failed to create zip file: {}", e))?;
+                Ok(ExportOutput::Zip(ZipWriter::new(zip_file)))
+            }
+            OutputMode::Directory => {
+                fs::create_dir_all(save_path)
+                    .map_err(|e| format!("Failed to create output directory: {}", e))?;
+                Ok(ExportOutput::Directory(save_path.to_path_buf()))
+            }
+            OutputMode::ManifestOnly => Ok(ExportOutput::ManifestOnly),
+        }
+    }
+
+    fn write_entry(&mut self, name: &str, options: FileOptions, reader: &mut dyn Read) -> Result<(), String> {
+        match self {
+            ExportOutput::Zip(zip) => {
+                zip.start_file(name, options)
+                    .map_err(|e| format!("Failed to start zip entry {}: {}", name, e))?;
+                std::io::copy(reader, zip).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+            }
+            ExportOutput::Directory(root) => {
+                let path = paths::resolve_in(root, name)?;
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| format!("Failed to create directory {}: {}", parent.to_string_lossy(), e))?;
+                }
+                let mut file = fs::File::create(&path)
+                    .map_err(|e| format!("Failed to create {}: {}", path.to_string_lossy(), e))?;
+                std::io::copy(reader, &mut file).map_err(|e| format!("Failed to write {}: {}", name, e))?;
+            }
+            ExportOutput::ManifestOnly => {}
+        }
+        Ok(())
+    }
+
+    fn write_file(&mut self, name: &str, options: FileOptions, source: &Path) -> Result<(), String> {
+        if let ExportOutput::ManifestOnly = self {
+            return Ok(());
+        }
+        let mut file = fs::File::open(source)
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        self.write_entry(name, options, &mut file)
+    }
+
+    fn finish(self) -> Result<(), String> {
+        if let ExportOutput::Zip(mut zip) = self {
+            zip.finish().map_err(|e| format!("Failed to finish zip: {}", e))?;
+        }
+        Ok(())
+    }
+}
+
+// 一次导出中逐个文件处理时共用的选项，在线程池中共享
+#[derive(Clone, Copy)]
+struct ExportContext<'a> {
+    disable_hash_check: bool,
+    // 增量导出时的上一版本
+    previous: Option<&'a PreviousRelease>,
+    // 压缩文件夹超过此大小时切分为分卷
+    max_part_size: Option<u64>,
+    progress: &'a ProgressTracker<'a>,
+}
+
+// 增量导出的变更摘要，写入上传包的 changes.json
+#[derive(Serialize)]
+struct ChangeSummary {
+    #[serde(rename = "previousVersion")]
+    previous_version: String,
+    version: String,
+    added: Vec<String>,
+    modified: Vec<String>,
+    removed: Vec<String>,
+    unchanged: usize,
+}
+
+impl ChangeSummary {
+    fn new(previous: &PreviousRelease, version: &str, exported_files: &[ManifestFile]) -> ChangeSummary {
+        let mut summary = ChangeSummary {
+            previous_version: previous.version.clone(),
+            version: version.to_string(),
+            added: Vec::new(),
+            modified: Vec::new(),
+            removed: Vec::new(),
+            unchanged: 0,
+        };
+        let current: HashSet<&str> = exported_files.iter().map(|f| f.relative_path.as_str()).collect();
+        for file in exported_files {
+            match previous.files.get(&file.relative_path) {
+                None => summary.added.push(file.relative_path.clone()),
+                Some(_) if previous.is_unchanged(&file.relative_path, &file.file_type, &file.hash) => summary.unchanged += 1,
+                Some(_) => summary.modified.push(file.relative_path.clone()),
+            }
+        }
+        summary.removed = previous.files.keys().filter(|path| !current.contains(path.as_str())).cloned().collect();
+        summary.removed.sort();
+        summary
+    }
+}
+
+
+// 将文件按内容分块写入 chunks/ 目录，已写入过的块（同一次导出中重复的块）直接跳过
+fn add_chunks(
+    output: &mut ExportOutput,
+    file_path: &Path,
+    written_chunks: &mut HashSet<String>,
+) -> Result<Vec<chunking::FileChunk>, String> {
+    chunking::chunk_file(file_path, |chunk, data| {
+        if written_chunks.insert(chunk.hash.clone()) {
+            let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+            output.write_entry(&format!("chunks/{}", chunking::chunk_object_path(&chunk.hash)), options, &mut &data[..])?;
+        }
+        Ok(())
+    })
+}
+
+fn chunked_exported_file(file_path: &Path, relative_path_str: String, hash: String, size: u64, chunks: Vec<chunking::FileChunk>) -> ManifestFile {
+    ManifestFile {
+        name: file_path.file_name().unwrap().to_string_lossy().into_owned(),
+        download_url: "{download_prefix}chunks/".to_string(),
+        relative_path: relative_path_str,
+        hash,
+        size,
+        file_type: "chunked".to_string(),
+        auto_extract: None,
+        patches: None,
+        chunks: Some(chunks),
+        mirrors: None,
+        parts: None,
+        group: None,
+        conditions: None,
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ExportProgress {
+    total: usize,
+    current: usize,
+    #[serde(rename = "fileName")]
+    file_name: String,
+    #[serde(rename = "totalBytes")]
+    total_bytes: u64,
+    #[serde(rename = "processedBytes")]
+    processed_bytes: u64,
+}
+
+// 按文件统计导出进度，并行处理的文件完成时各自累加
+struct ProgressTracker<'a> {
+    events: &'a dyn EventSink,
+    total: usize,
+    total_bytes: u64,
+    current: AtomicUsize,
+    processed_bytes: AtomicU64,
+}
+
+impl ProgressTracker<'_> {
+    fn file_done(&self, file_name: &str, size: u64) {
+        let current = self.current.fetch_add(1, Ordering::Relaxed) + 1;
+        let processed_bytes = self.processed_bytes.fetch_add(size, Ordering::Relaxed) + size;
+        self.events.emit("EXPORT_PROGRESS", &ExportProgress {
+            total: self.total,
+            current,
+            file_name: file_name.to_string(),
+            total_bytes: self.total_bytes,
+            processed_bytes,
+        });
+    }
+}
+
+// 导出计划中的一个文件
+struct PlannedFile {
+    path: PathBuf,
+    relative_path: String,
+    size: u64,
+    compression: Compression,
+    // 压缩文件夹中的条目使用固定的修改时间，内容不变时压缩包的哈希也不变
+    fixed_time: bool,
+    chunked: bool,
+}
+
+impl PlannedFile {
+    fn new(path: &Path, base_path: &Path, compression: Compression, fixed_time: bool, chunked: bool) -> Result<PlannedFile, String> {
+        let relative_path = path.strip_prefix(base_path)
+            .map_err(|e| format!("Failed to get relative path: {}", e))?;
+        let size = fs::metadata(path)
+            .map_err(|e| format!("Failed to get file metadata: {}", e))?
+            .len();
+        Ok(PlannedFile {
+            path: path.to_path_buf(),
+            relative_path: relative_path.to_string_lossy().replace('\\', "/"),
+            size,
+            compression: if is_compressed_format(path) { Compression::Stored } else { compression },
+            fixed_time,
+            chunked: chunked && size >= chunking::MIN_CHUNKED_FILE_SIZE,
+        })
+    }
+
+    fn options(&self) -> FileOptions {
+        let options = FileOptions::default()
+            .compression_method(self.compression.method())
+            .compression_level(self.compression.level());
+        if self.fixed_time {
+            options.last_modified_time(zip::DateTime::default())
+        } else {
+            options
+        }
+    }
+
+    // 只有写入 ZIP 且需要压缩的文件才在线程池中预先压缩，过大的文件在写入时再压缩以限制内存占用
+    fn should_precompress(&self) -> bool {
+        self.compression != Compression::Stored && !self.chunked && self.size <= MAX_PRECOMPRESSED_SIZE
+    }
+}
+
+// 先按选择顺序规划出所有文件，再分批并行计算哈希、压缩，最后按原顺序写入
+enum ExportJob {
+    File(PlannedFile),
+    // 压缩为单个zip的文件夹，其中的文件相对文件夹本身
+    Archive {
+        name: String,
+        is_update_package: bool,
+        files: Vec<ExportJob>,
+    },
+}
+
+impl ExportJob {
+    fn file_count(&self) -> usize {
+        match self {
+            ExportJob::File(_) => 1,
+            ExportJob::Archive { files, .. } => files.iter().map(ExportJob::file_count).sum(),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match self {
+            ExportJob::File(file) => file.size,
+            ExportJob::Archive { files, .. } => files.iter().map(ExportJob::size).sum(),
+        }
+    }
+
+    // 预压缩结果在写入前占用的内存
+    fn buffered_size(&self) -> u64 {
+        match self {
+            ExportJob::File(file) if file.should_precompress() => file.size,
+            _ => 0,
+        }
+    }
+}
+
+// 压缩文件夹生成的临时zip。同名文件夹和同时进行的导出各自使用不同的文件，丢弃时删除，出错时也不会遗留
+struct TempArchive(PathBuf);
+
+impl TempArchive {
+    fn create() -> Result<(TempArchive, File), String> {
+        let path = std::env::temp_dir().join(format!("acgstation-export-{}.zip", uuid::Uuid::new_v4()));
+        let file = fs::File::create(&path).map_err(|e| format!("Failed to create temp zip: {}", e))?;
+        Ok((TempArchive(path), file))
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempArchive {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// 并行处理的结果
+enum PreparedJob<'a> {
+    File {
+        file: &'a PlannedFile,
+        hash: String,
+        unchanged: bool,
+        precompressed: Option<Vec<u8>>,
+    },
+    Archive {
+        name: &'a str,
+        is_update_package: bool,
+        temp_zip: TempArchive,
+        hash: String,
+        size: u64,
+    },
+}
+
+// 被排除的目录整体跳过，不再进入；按文件名排序，内容不变时生成的压缩包也不变
+fn plan_directory(
+    dir_path: &Path,
+    base_path: &Path,
+    compression: Compression,
+    fixed_time: bool,
+    chunked: bool,
+    exclusions: &Exclusions,
+) -> Result<Vec<ExportJob>, String> {
+    let mut jobs = Vec::new();
+    let walker = WalkDir::new(dir_path).sort_by_file_name().into_iter()
+        .filter_entry(|entry| !exclusions.is_excluded_path(entry.path(), entry.file_type().is_dir()));
+    for entry in walker {
+        let entry = entry.map_err(|e| format!("Error walking directory: {}", e))?;
+        if entry.path().is_file() {
+            jobs.push(ExportJob::File(PlannedFile::new(entry.path(), base_path, compression, fixed_time, chunked)?));
+        }
+    }
+    Ok(jobs)
+}
+
+// 在线程池中把单个文件压缩为只含一个条目的zip，写入时直接复制压缩后的数据
+fn precompress_file(file: &PlannedFile) -> Result<Vec<u8>, String> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file(&file.relative_path, file.options())
+        .map_err(|e| format!("Failed to start zip entry {}: {}", file.relative_path, e))?;
+    let mut source = fs::File::open(&file.path)
+        .map_err(|e| format!("Failed to open file: {}", e))?;
+    std::io::copy(&mut source, &mut zip)
+        .map_err(|e| format!("Failed to compress {}: {}", file.relative_path, e))?;
+    let buffer = zip.finish().map_err(|e| format!("Failed to compress {}: {}", file.relative_path, e))?;
+    Ok(buffer.into_inner())
+}
+
+fn copy_precompressed(zip: &mut ZipWriter<File>, name: &str, data: Vec<u8>) -> Result<(), String> {
+    let mut archive = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| format!("Failed to read compressed {}: {}", name, e))?;
+    let entry = archive.by_index_raw(0)
+        .map_err(|e| format!("Failed to read compressed {}: {}", name, e))?;
+    zip.raw_copy_file_rename(entry, name)
+        .map_err(|e| format!("Failed to write {}: {}", name, e))
+}
+
+// 计算哈希、判断是否与上一版本相同并预先压缩；压缩文件夹在这里整体生成临时zip
+fn prepare_job<'a>(job: &'a ExportJob, context: &ExportContext, precompress: bool) -> Result<PreparedJob<'a>, String> {
+    match job {
+        ExportJob::File(file) => {
+            let hash = if context.disable_hash_check { "DISABLED".to_string() } else { calculate_file_hash(&file.path).map_err(|e| e.to_string())? };
+            // 分块文件按块去重，不与上一版本整体比较
+            let unchanged = !file.chunked && context.previous.is_some_and(|previous| previous.is_unchanged(&file.relative_path, "file", &hash));
+            let precompressed = if precompress && !unchanged && file.should_precompress() {
+                Some(precompress_file(file)?)
+            } else {
+                None
+            };
+            context.progress.file_done(&file.relative_path, file.size);
+            Ok(PreparedJob::File { file, hash, unchanged, precompressed })
+        }
+        ExportJob::Archive { name, is_update_package, files } => {
+            let (temp_zip, temp_zip_file) = TempArchive::create()?;
+
+            // 压缩文件夹内的路径相对文件夹本身，不与上一版本逐个比较
+            let nested_context = ExportContext { previous: None, ..*context };
+            let mut output = ExportOutput::Zip(ZipWriter::new(temp_zip_file));
+            export_jobs(&mut output, files, &nested_context, &mut HashSet::new())?;
+            output.finish()?;
+
+            // 计算压缩文件的hash
+            let hash = if context.disable_hash_check { "DISABLED".to_string() } else { calculate_file_hash(temp_zip.path()).map_err(|e| e.to_string())? };
+            let size = fs::metadata(temp_zip.path())
+                .map_err(|e| format!("Failed to get temp zip metadata: {}", e))?
+                .len();
+            Ok(PreparedJob::Archive { name, is_update_package: *is_update_package, temp_zip, hash, size })
+        }
+    }
+}
+
+fn write_job(
+    output: &mut ExportOutput,
+    prepared: PreparedJob,
+    context: &ExportContext,
+    written_chunks: &mut HashSet<String>,
+) -> Result<ManifestFile, String> {
+    match prepared {
+        PreparedJob::File { file, hash, unchanged, precompressed } => {
+            if file.chunked {
+                let chunks = add_chunks(output, &file.path, written_chunks)?;
+                return Ok(chunked_exported_file(&file.path, file.relative_path.clone(), hash, file.size, chunks));
+            }
+
+            // 写入导出目标（增量导出时跳过与上一版本相同的文件）
+            if !unchanged {
+                match (precompressed, &mut *output) {
+                    (Some(data), ExportOutput::Zip(zip)) => copy_precompressed(zip, &file.relative_path, data)?,
+                    _ => output.write_file(&file.relative_path, file.options(), &file.path)?,
+                }
+            }
+
+            Ok(ManifestFile {
+                name: file.path.file_name().unwrap().to_string_lossy().into_owned(),
+                download_url: format!("{{download_prefix}}{}", file.relative_path),
+                relative_path: file.relative_path.clone(),
+                hash,
+                size: file.size,
+                file_type: "file".to_string(),
+                auto_extract: None,
+                patches: None,
+                chunks: None,
+                mirrors: None,
+                parts: None,
+                group: None,
+                conditions: None,
+            })
+        }
+        PreparedJob::Archive { name, is_update_package, temp_zip, hash, size } => {
+            // 根据是否为更新包设置不同的类型
+            let file_type = if is_update_package { "update_package" } else { "zip" };
+
+            // 将子zip写入导出目标，超过分卷大小时按分卷写入
+            let written = (|| {
+                let parts = match context.max_part_size.filter(|max_part_size| size > *max_part_size) {
+                    Some(max_part_size) => Some(plan_parts(temp_zip.path(), name, size, max_part_size, context.disable_hash_check)?),
+                    None => None,
+                };
+                let unchanged = context.previous.is_some_and(|previous| {
+                    previous.is_unchanged(name, file_type, &hash) && part_sizes(&previous.files[name].parts) == part_sizes(&parts)
+                });
+                if !unchanged {
+                    match &parts {
+                        Some(parts) => write_parts(output, name, temp_zip.path(), parts)?,
+                        None => output.write_file(name, FileOptions::default(), temp_zip.path())?,
+                    }
+                }
+                Ok::<_, String>(parts)
+            })();
+            // 清理临时文件
+            drop(temp_zip);
+            let parts = written?;
+
+            Ok(ManifestFile {
+                name: name.to_string(),
+                download_url: format!("{{download_prefix}}{}", name),
+                relative_path: name.to_string(),
+                hash,
+                size,
+                file_type: file_type.to_string(),
+                auto_extract: Some(true),
+                patches: None,
+                chunks: None,
+                mirrors: None,
+                parts,
+                group: None,
+                conditions: None,
+            })
+        }
+    }
+}
+
+// 按分卷大小切分压缩包并计算每个分卷的哈希
+fn plan_parts(archive_path: &Path, name: &str, size: u64, max_part_size: u64, disable_hash_check: bool) -> Result<Vec<FilePart>, String> {
+    let mut archive = fs::File::open(archive_path)
+        .map_err(|e| format!("Failed to open temp zip: {}", e))?;
+    let mut parts = Vec::new();
+    let mut offset = 0;
+    while offset < size {
+        let part_size = max_part_size.min(size - offset);
+        let hash = if disable_hash_check {
+            "DISABLED".to_string()
+        } else {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut (&mut archive).take(part_size), &mut hasher)
+                .map_err(|e| format!("Failed to hash part of {}: {}", name, e))?;
+            hex::encode(hasher.finalize())
+        };
+        parts.push(FilePart {
+            download_url: format!("{{download_prefix}}{}{}", name, manifest::part_suffix(parts.len())),
+            hash,
+            size: part_size,
+        });
+        offset += part_size;
+    }
+    Ok(parts)
+}
+
+fn write_parts(output: &mut ExportOutput, name: &str, archive_path: &Path, parts: &[FilePart]) -> Result<(), String> {
+    let mut archive = fs::File::open(archive_path)
+        .map_err(|e| format!("Failed to open temp zip: {}", e))?;
+    // 压缩包本身已经压缩过，分卷直接存储
+    let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (index, part) in parts.iter().enumerate() {
+        let part_name = format!("{}{}", name, manifest::part_suffix(index));
+        archive.seek(SeekFrom::Start(parts[..index].iter().map(|p| p.size).sum()))
+            .map_err(|e| format!("Failed to read temp zip: {}", e))?;
+        output.write_entry(&part_name, options, &mut (&mut archive).take(part.size))?;
+    }
+    Ok(())
+}
+
+fn part_sizes(parts: &Option<Vec<FilePart>>) -> Option<Vec<u64>> {
+    parts.as_ref().map(|parts| parts.iter().map(|p| p.size).collect())
+}
+
+// 分批并行处理并按原顺序写入，每批预压缩的数据量不超过 MAX_BUFFERED_BYTES
+fn export_jobs(
+    output: &mut ExportOutput,
+    jobs: &[ExportJob],
+    context: &ExportContext,
+    written_chunks: &mut HashSet<String>,
+) -> Result<Vec<ManifestFile>, String> {
+    let precompress = matches!(output, ExportOutput::Zip(_));
+    let mut exported_files = Vec::with_capacity(jobs.len());
+    let mut remaining = jobs;
+
+    while !remaining.is_empty() {
+        let mut batch_len = 0;
+        let mut buffered = 0;
+        while batch_len < remaining.len() && (batch_len == 0 || buffered + remaining[batch_len].buffered_size() <= MAX_BUFFERED_BYTES) {
+            buffered += remaining[batch_len].buffered_size();
+            batch_len += 1;
+        }
+        let (batch, rest) = remaining.split_at(batch_len);
+        remaining = rest;
+
+        let prepared: Vec<PreparedJob> = batch.par_iter()
+            .map(|job| prepare_job(job, context, precompress))
+            .collect::<Result<_, String>>()?;
+        for prepared in prepared {
+            exported_files.push(write_job(output, prepared, context, written_chunks)?);
+        }
+    }
+
+    Ok(exported_files)
+}
+
+// 对比上一版本导出包中的同名文件，为内容变化的普通文件生成补丁并写入 patches/ 目录
+fn add_patches(
+    output: &mut ExportOutput,
+    previous_package: &Path,
+    base_path: &Path,
+    exported_files: &mut [ManifestFile],
+) -> Result<(), String> {
+    let previous_manifest = manifest::parse_manifest(&read_manifest_text_from_zip(previous_package)?)
+        .map_err(|e| format!("Failed to parse previous manifest: {}", e))?;
+    let previous_files: HashMap<&str, &ManifestFile> = previous_manifest.files.iter()
+        .filter(|f| f.file_type == "file" && f.hash != "DISABLED")
+        .map(|f| (f.relative_path.as_str(), f))
+        .collect();
+
+    let previous_zip = File::open(previous_package).map_err(|e| format!("Failed to open previous package: {}", e))?;
+    let mut previous_archive = ZipArchive::new(previous_zip).map_err(|e| format!("Failed to read previous package: {}", e))?;
+
+    for exported in exported_files.iter_mut().filter(|f| f.file_type == "file") {
+        let previous = match previous_files.get(exported.relative_path.as_str()) {
+            Some(previous) if previous.hash != exported.hash => *previous,
+            _ => continue,
+        };
+        if previous.size > delta::MAX_PATCH_SOURCE_SIZE || exported.size > delta::MAX_PATCH_SOURCE_SIZE {
+            continue;
+        }
+
+        let mut old = Vec::with_capacity(previous.size as usize);
+        match previous_archive.by_name(&previous.relative_path) {
+            Ok(mut entry) => entry.read_to_end(&mut old).map_err(|e| e.to_string())?,
+            Err(_) => continue, // 旧包中没有原始文件（例如已托管在CDN上），无法生成补丁
+        };
+        let new = fs::read(base_path.join(&exported.relative_path))
+            .map_err(|e| format!("Failed to read {}: {}", exported.relative_path, e))?;
+
+        let patch = delta::create_patch(&old, &new)?;
+        // 补丁不足以明显节省流量时直接下载完整文件
+        if patch.len() as u64 * 2 >= exported.size {
+            continue;
+        }
+
+        let patch_path = format!("patches/{}.{}.patch", exported.relative_path, &previous.hash[..16.min(previous.hash.len())]);
+        output.write_entry(&patch_path, FileOptions::default().compression_method(zip::CompressionMethod::Stored), &mut &patch[..])?;
+
+        exported.patches = Some(vec![FilePatch {
+            from_hash: previous.hash.clone(),
+            download_url: format!("{{download_prefix}}{}", patch_path),
+            hash: hex::encode(Sha256::digest(&patch)),
+            size: patch.len() as u64,
+        }]);
+    }
+
+    Ok(())
+}
+
+// 组 id 不能重复，导出项只能引用已定义的组
+fn validate_groups(groups: &[FileGroup], files: &[FileItem]) -> Result<(), String> {
+    let mut ids = HashSet::new();
+    for group in groups {
+        if group.id.trim().is_empty() {
+            return Err(format!("Group \"{}\" has an empty id", group.name));
+        }
+        if !ids.insert(group.id.as_str()) {
+            return Err(format!("Duplicate group id: {}", group.id));
+        }
+    }
+    for file in files.iter().filter(|f| f.selected) {
+        if let Some(group) = file.group.as_deref().filter(|group| !ids.contains(group)) {
+            return Err(format!("{} is assigned to undefined group: {}", file.name, group));
+        }
+    }
+    Ok(())
+}
+
+// 动作中的路径必须是目标目录内的相对路径；运行的程序必须随包导出，同步时按清单哈希校验
+fn validate_hooks(manifest: &Manifest) -> Result<(), String> {
+    let Some(hooks) = &manifest.hooks else { return Ok(()) };
+    for action in hooks.pre_sync.iter().chain(&hooks.post_sync) {
+        match action {
+            HookAction::Delete { pattern } if pattern.trim().is_empty() => {
+                return Err("Delete action has an empty pattern".to_string());
+            }
+            HookAction::Delete { .. } => {}
+            HookAction::Rename { from, to } => {
+                paths::validate_relative_path(from)?;
+                paths::validate_relative_path(to)?;
+            }
+            HookAction::WriteFile { path, .. } => {
+                paths::validate_relative_path(path)?;
+            }
+            HookAction::Run { executable, .. } => {
+                if manifest.disable_hash_check.unwrap_or(false) {
+                    return Err("Run actions require hash checks to be enabled".to_string());
+                }
+                let normalized = paths::validate_relative_path(executable)?;
+                let exported = manifest.files.iter().any(|file| {
+                    file.relative_path == normalized && (file.file_type == "file" || file.file_type == "chunked")
+                });
+                if !exported {
+                    return Err(format!("{} is run by a hook but is not exported as a file", executable));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// 带组或条件的导出项在包中对应的路径：压缩文件夹为压缩包名，其余为相对路径，其下的文件都继承该项的设置
+fn item_roots<'a>(files: &'a [FileItem], base_path: &Path) -> Result<Vec<(String, &'a FileItem)>, String> {
+    let mut roots = Vec::new();
+    for file_item in files.iter().filter(|f| f.selected && (f.group.is_some() || f.conditions.is_some())) {
+        let file_path = Path::new(&file_item.path);
+        let root = if file_item.is_directory && file_item.compress.unwrap_or(false) {
+            format!("{}.zip", file_path.file_name().unwrap().to_string_lossy())
+        } else {
+            file_path.strip_prefix(base_path)
+                .map_err(|_| format!("{} is outside of the export base directory", file_item.path))?
+                .to_string_lossy()
+                .replace('\\', "/")
+        };
+        roots.push((root, file_item));
+    }
+    Ok(roots)
+}
+
+fn item_of<'a>(item_roots: &[(String, &'a FileItem)], relative_path: &str) -> Option<&'a FileItem> {
+    item_roots.iter()
+        .find(|(root, _)| {
+            relative_path == root || relative_path.strip_prefix(root.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        })
+        .map(|(_, item)| *item)
+}
+
+pub fn export_files(
+    events: &dyn EventSink,
+    files: Vec<FileItem>,
+    settings: ExportSettings,
+    save_path_str: String
+) -> Result<String, String> {
+    if files.is_empty() {
+        return Err("No files selected for export".to_string());
+    }
+    
+    let save_path = PathBuf::from(save_path_str);
+
+    // 先加载签名密钥，避免打包完成后才发现密钥不可用
+    let signing_key = match settings.signing_key.as_deref().filter(|p| !p.is_empty()) {
+        Some(key_path) => Some(signing::load_key_file(Path::new(key_path))?),
+        None => None,
+    };
+
+    // 增量导出：只把新增或变化的文件放进上传包，清单仍然完整
+    let previous = match settings.previous_manifest.as_deref().filter(|p| !p.is_empty()) {
+        Some(_) if settings.disable_hash_check.unwrap_or(false) => {
+            return Err("Incremental export requires hash checks to be enabled".to_string());
+        }
+        Some(source) => Some(PreviousRelease::load(Path::new(source))?),
+        None => None,
+    };
+
+    if settings.max_part_size == Some(0) {
+        return Err("Maximum part size must be greater than zero".to_string());
+    }
+
+    let groups = settings.groups.clone().filter(|groups| !groups.is_empty());
+    validate_groups(groups.as_deref().unwrap_or_default(), &files)?;
+    let hooks = settings.hooks.clone().filter(|hooks| !hooks.pre_sync.is_empty() || !hooks.post_sync.is_empty());
+
+    // 只生成清单时没有地方存放补丁
+    let has_previous_package = settings.previous_package.as_deref().is_some_and(|p| !p.is_empty());
+    if settings.output_mode == OutputMode::ManifestOnly && has_previous_package {
+        return Err("Delta patches cannot be generated for a manifest-only export".to_string());
+    }
+    
+    let mut output = ExportOutput::create(settings.output_mode, &save_path)?;
+    // 上一版本已上传的内容块不再写入
+    let mut written_chunks: HashSet<String> = previous.iter().flat_map(PreviousRelease::chunk_hashes).collect();
+
+    // 获取基础路径（第一个文件的父目录）
+    let first_file_path = Path::new(&files[0].path);
+    let base_path = first_file_path.parent().unwrap_or(first_file_path);
+    
+    let disable_hash_check = settings.disable_hash_check.unwrap_or(false);
+    
+    let base_path_for_exclusions = Path::new(&files.iter().find(|f| f.selected).unwrap().path).parent().unwrap();
+    let exclusion_rules: Vec<String> = files.iter()
+        .filter(|f| f.selected && f.exclusions.is_some())
+        .flat_map(|f| f.exclusions.as_ref().unwrap().clone())
+        .chain(settings.exclusions.iter().cloned())
+        .collect();
+    let exclusions = Exclusions::new(base_path_for_exclusions, &exclusion_rules)?;
+
+    // 规划要导出的文件
+    let mut jobs = Vec::new();
+    for file_item in files.iter().filter(|f| f.selected) {
+        let file_path = Path::new(&file_item.path);
+        let chunked = file_item.chunked.unwrap_or(false);
+        if let Some(compression) = &file_item.compression {
+            compression.validate()?;
+        }
+        let compression = file_item.compression.unwrap_or(Compression::Deflate { level: None });
+        
+        if file_item.is_directory {
+            let should_compress = file_item.compress.unwrap_or(false);
+            if should_compress {
+                let folder_name = file_path.file_name().unwrap().to_string_lossy();
+                jobs.push(ExportJob::Archive {
+                    name: format!("{}.zip", folder_name),
+                    is_update_package: file_item.is_update_package.unwrap_or(false),
+                    files: plan_directory(file_path, file_path, compression, true, false, &exclusions)?,
+                });
+            } else {
+                // 直接添加文件夹内容
+                let compression = file_item.compression.unwrap_or(Compression::Stored);
+                jobs.extend(plan_directory(file_path, base_path, compression, false, chunked, &exclusions)?);
+            }
+        } else {
+            jobs.push(ExportJob::File(PlannedFile::new(file_path, base_path, compression, false, chunked)?));
+        }
+    }
+
+    // 压缩包在本地重新打包，哈希和分卷与已托管的文件对不上
+    if settings.output_mode == OutputMode::ManifestOnly && jobs.iter().any(|job| matches!(job, ExportJob::Archive { .. })) {
+        return Err("Compressed folders cannot be used in a manifest-only export; their archives are rebuilt locally and would not match the hosted files".to_string());
+    }
+
+    let progress = ProgressTracker {
+        events,
+        total: jobs.iter().map(ExportJob::file_count).sum(),
+        total_bytes: jobs.iter().map(ExportJob::size).sum(),
+        current: AtomicUsize::new(0),
+        processed_bytes: AtomicU64::new(0),
+    };
+    let context = ExportContext {
+        disable_hash_check,
+        previous: previous.as_ref(),
+        max_part_size: settings.max_part_size,
+        progress: &progress,
+    };
+    let mut exported_files = export_jobs(&mut output, &jobs, &context, &mut written_chunks)?;
+
+    // 针对上一版本生成差量补丁
+    if let Some(previous_package) = settings.previous_package.as_deref().filter(|p| !p.is_empty()) {
+        if disable_hash_check {
+            return Err("Delta patches require hash checks to be enabled".to_string());
+        }
+        add_patches(&mut output, Path::new(previous_package), base_path, &mut exported_files)?;
+    }
+    
+    // 第一个前缀为主地址；其余前缀只写入包级 mirrors，同步时由 mirrors::candidate_urls 替换出每个文件的备用地址
+    let primary_prefix = settings.download_prefix.first().cloned().unwrap_or_default();
+
+    let changes = previous.as_ref().map(|previous| ChangeSummary::new(previous, &settings.version, &exported_files));
+    let item_roots = item_roots(&files, base_path)?;
+
+    // 创建清单文件
+    let mut manifest = Manifest {
+        schema_version: manifest::SCHEMA_VERSION,
+        package_name: settings.package_name.clone(),
+        version: settings.version,
+        description: settings.description,
+        disable_hash_check: settings.disable_hash_check,
+        disable_size_check: settings.disable_size_check,
+        mirrors: (settings.download_prefix.len() > 1).then(|| settings.download_prefix.clone()),
+        files: exported_files.iter().map(|f| ManifestFile {
+            name: f.name.clone(),
+            download_url: f.download_url.replace("{download_prefix}", &primary_prefix),
+            mirrors: None,
+            relative_path: f.relative_path.clone(),
+            hash: f.hash.clone(),
+            size: f.size,
+            file_type: f.file_type.clone(),
+            auto_extract: f.auto_extract,
+            chunks: f.chunks.clone(),
+            patches: f.patches.as_ref().map(|patches| patches.iter().map(|p| FilePatch {
+                download_url: p.download_url.replace("{download_prefix}", &primary_prefix),
+                ..p.clone()
+            }).collect()),
+            parts: f.parts.as_ref().map(|parts| parts.iter().map(|p| FilePart {
+                download_url: p.download_url.replace("{download_prefix}", &primary_prefix),
+                ..p.clone()
+            }).collect()),
+            group: item_of(&item_roots, &f.relative_path).and_then(|item| item.group.clone()),
+            conditions: item_of(&item_roots, &f.relative_path)
+                .and_then(|item| item.conditions.clone())
+                .filter(|conditions| !conditions.is_empty()),
+        }).collect(),
+        created_at: Some(chrono::Utc::now().to_rfc3339()),
+        groups,
+        hooks,
+    };
+    manifest.schema_version = manifest.required_schema_version();
+    validate_hooks(&manifest)?;
+    
+    let mut manifest_value = serde_json::to_value(&manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    if let Some(signing_key) = &signing_key {
+        signing::sign_manifest(&mut manifest_value, signing_key)?;
+    }
+    let manifest_json = serde_json::to_string_pretty(&manifest_value)
+        .map_err(|e| format!("Failed to serialize manifest: {}", e))?;
+    
+    if let ExportOutput::ManifestOnly = output {
+        fs::write(&save_path, manifest_json.as_bytes())
+            .map_err(|e| format!("Failed to write manifest: {}", e))?;
+    }
+    output.write_entry("manifest.json", FileOptions::default(), &mut manifest_json.as_bytes())?;
+
+    if let Some(changes) = &changes {
+        let changes_json = serde_json::to_string_pretty(changes)
+            .map_err(|e| format!("Failed to serialize change summary: {}", e))?;
+        output.write_entry("changes.json", FileOptions::default(), &mut changes_json.as_bytes())?;
+    }
+    
+    output.finish()?;
+    
+    match changes {
+        Some(changes) => Ok(format!(
+            "Incremental export against {} completed successfully! {} added, {} modified, {} removed, {} unchanged. Saved to: {}",
+            changes.previous_version, changes.added.len(), changes.modified.len(), changes.removed.len(), changes.unchanged,
+            save_path.to_string_lossy()
+        )),
+        None => Ok(format!("Export completed successfully! Saved to: {}", save_path.to_string_lossy())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit_json(&self, _event: &str, _payload: serde_json::Value) {}
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("acgstation-export-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_files(root: &Path, files: &[(&str, &[u8])]) {
+        for (path, data) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+    }
+
+    // 在 `value` 上添加或覆盖 `extra` 中的字段
+    fn merged(mut value: serde_json::Value, extra: serde_json::Value) -> serde_json::Value {
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        value
+    }
+
+    // `source` 下的一个导出项，`options` 为额外的导出选项
+    fn file_item(source: &Path, name: &str, options: serde_json::Value) -> FileItem {
+        let path = source.join(name);
+        let item = json!({
+            "path": path.to_string_lossy(),
+            "name": name,
+            "isDirectory": path.is_dir(),
+            "selected": true,
+        });
+        serde_json::from_value(merged(item, options)).unwrap()
+    }
+
+    fn export(files: Vec<FileItem>, settings: serde_json::Value, save_path: &Path) -> Result<String, String> {
+        let defaults = json!({
+            "packageName": "pack",
+            "downloadPrefix": "https://cdn.example.com/pack/",
+            "version": "1.0",
+        });
+        let settings = serde_json::from_value(merged(defaults, settings)).unwrap();
+        export_files(&NoEvents, files, settings, save_path.to_string_lossy().into_owned())
+    }
+
+    // zip 包中的所有条目及其内容
+    fn read_zip(path: &Path) -> BTreeMap<String, Vec<u8>> {
+        let mut archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        (0..archive.len())
+            .map(|index| {
+                let mut entry = archive.by_index(index).unwrap();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (entry.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    fn parse_manifest(data: &[u8]) -> Manifest {
+        manifest::parse_manifest(std::str::from_utf8(data).unwrap()).unwrap()
+    }
+
+    fn relative_paths(manifest: &Manifest) -> Vec<&str> {
+        let mut paths: Vec<&str> = manifest.files.iter().map(|f| f.relative_path.as_str()).collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn parses_compression_specs() {
+        assert_eq!("stored".parse::<Compression>().unwrap(), Compression::Stored);
+        assert_eq!("Deflate".parse::<Compression>().unwrap(), Compression::Deflate { level: None });
+        assert_eq!("zstd:19".parse::<Compression>().unwrap(), Compression::Zstd { level: Some(19) });
+        assert_eq!("zstd:-7".parse::<Compression>().unwrap(), Compression::Zstd { level: Some(-7) });
+        assert_eq!("bzip2: 9".parse::<Compression>().unwrap(), Compression::Bzip2 { level: Some(9) });
+
+        assert!("stored:1".parse::<Compression>().is_err());
+        assert!("lzma".parse::<Compression>().is_err());
+        assert!("zstd:fast".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn validates_compression_levels() {
+        assert!(Compression::Deflate { level: Some(9) }.validate().is_ok());
+        assert!(Compression::Deflate { level: Some(10) }.validate().is_err());
+        assert!(Compression::Zstd { level: Some(22) }.validate().is_ok());
+        assert!(Compression::Zstd { level: Some(23) }.validate().is_err());
+        assert!(Compression::Zstd { level: Some(-8) }.validate().is_err());
+        assert!(Compression::Bzip2 { level: Some(1) }.validate().is_ok());
+        assert!(Compression::Bzip2 { level: Some(0) }.validate().is_err());
+        assert!(Compression::Bzip2 { level: Some(10) }.validate().is_err());
+        assert!(Compression::Bzip2 { level: None }.validate().is_ok());
+    }
+
+    #[test]
+    fn serializes_compression_with_method_tag() {
+        let json = serde_json::to_value(Compression::Zstd { level: Some(3) }).unwrap();
+        assert_eq!(json, serde_json::json!({"method": "zstd", "level": 3}));
+        let parsed: Compression = serde_json::from_value(serde_json::json!({"method": "stored"})).unwrap();
+        assert_eq!(parsed, Compression::Stored);
+    }
+
+    #[test]
+    fn exports_only_changed_files_against_a_previous_manifest() {
+        let dir = temp_dir();
+        let source = dir.join("source");
+        let (v1, v2) = (dir.join("v1.zip"), dir.join("v2.zip"));
+        write_files(&source, &[("mods/a.jar", b"a"), ("mods/b.jar", b"b"), ("config/c.toml", b"c")]);
+        export(vec![file_item(&source, "mods", json!({})), file_item(&source, "config", json!({}))], json!({}), &v1).unwrap();
+
+        write_files(&source, &[("mods/b.jar", b"b2"), ("mods/d.jar", b"d")]);
+        let message = export(vec![file_item(&source, "mods", json!({}))], json!({"version": "2.0", "previousManifest": v1}), &v2).unwrap();
+        assert!(message.contains("1 added, 1 modified, 1 removed, 1 unchanged"), "{}", message);
+
+        // 上传包只含变化的文件，清单仍然列出全部文件
+        let entries = read_zip(&v2);
+        assert_eq!(entries.keys().collect::<Vec<_>>(), ["changes.json", "manifest.json", "mods/b.jar", "mods/d.jar"]);
+        let manifest = parse_manifest(&entries["manifest.json"]);
+        assert_eq!(relative_paths(&manifest), ["mods/a.jar", "mods/b.jar", "mods/d.jar"]);
+        let unchanged = manifest.files.iter().find(|f| f.relative_path == "mods/a.jar").unwrap();
+        assert_eq!(unchanged.download_url, "https://cdn.example.com/pack/mods/a.jar");
+        let changes: serde_json::Value = serde_json::from_slice(&entries["changes.json"]).unwrap();
+        assert_eq!(changes, json!({
+            "previousVersion": "1.0",
+            "version": "2.0",
+            "added": ["mods/d.jar"],
+            "modified": ["mods/b.jar"],
+            "removed": ["config/c.toml"],
+            "unchanged": 1,
+        }));
+
+        // 上一版本没有哈希时无法判断哪些文件未变化
+        let unhashed = dir.join("unhashed.zip");
+        export(vec![file_item(&source, "mods", json!({}))], json!({"disableHashCheck": true}), &unhashed).unwrap();
+        let error = export(vec![file_item(&source, "mods", json!({}))], json!({"previousManifest": unhashed}), &dir.join("v3.zip")).unwrap_err();
+        assert!(error.contains("without hashes"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exports_a_directory_tree_matching_the_download_urls() {
+        let dir = temp_dir();
+        let (source, out) = (dir.join("source"), dir.join("out"));
+        write_files(&source, &[("mods/a.jar", b"a"), ("mods/lib/b.jar", b"b"), ("shaderpacks/pack/shader.txt", b"s")]);
+        let files = vec![file_item(&source, "mods", json!({})), file_item(&source, "shaderpacks", json!({"compress": true}))];
+        export(files, json!({"outputMode": "directory"}), &out).unwrap();
+
+        // 每个下载地址去掉前缀后都对应目录中的一个文件，内容与清单中的哈希一致
+        let manifest = parse_manifest(&fs::read(out.join("manifest.json")).unwrap());
+        assert_eq!(relative_paths(&manifest), ["mods/a.jar", "mods/lib/b.jar", "shaderpacks.zip"]);
+        for file in &manifest.files {
+            let hosted = out.join(file.download_url.strip_prefix("https://cdn.example.com/pack/").unwrap());
+            assert_eq!(calculate_file_hash(&hosted).unwrap(), file.hash, "{}", file.relative_path);
+        }
+        assert_eq!(fs::read(out.join("mods/lib/b.jar")).unwrap(), b"b");
+        assert!(read_zip(&out.join("shaderpacks.zip")).contains_key("pack/shader.txt"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exports_only_the_manifest_for_hosted_files() {
+        let dir = temp_dir();
+        let (source, out) = (dir.join("source"), dir.join("out"));
+        write_files(&source, &[("mods/a.jar", b"a"), ("shaderpacks/pack/shader.txt", b"s")]);
+        fs::create_dir_all(&out).unwrap();
+        let save_path = out.join("manifest.json");
+        export(vec![file_item(&source, "mods", json!({}))], json!({"outputMode": "manifest_only"}), &save_path).unwrap();
+
+        // 只写入清单本身，地址指向已托管在前缀下的文件
+        assert_eq!(fs::read_dir(&out).unwrap().count(), 1);
+        let manifest = parse_manifest(&fs::read(&save_path).unwrap());
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.files[0].download_url, "https://cdn.example.com/pack/mods/a.jar");
+        assert_eq!(manifest.files[0].hash, calculate_file_hash(&source.join("mods/a.jar")).unwrap());
+
+        // 压缩文件夹在本地重新打包，与已托管的文件对不上；补丁也没有地方存放
+        let folder = file_item(&source, "shaderpacks", json!({"compress": true}));
+        let error = export(vec![folder], json!({"outputMode": "manifest_only"}), &save_path).unwrap_err();
+        assert!(error.contains("manifest-only"), "{}", error);
+        let patched = json!({"outputMode": "manifest_only", "previousPackage": dir.join("v1.zip")});
+        let error = export(vec![file_item(&source, "mods", json!({}))], patched, &save_path).unwrap_err();
+        assert!(error.contains("manifest-only"), "{}", error);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exports_files_in_plan_order_with_their_compression() {
+        let dir = temp_dir();
+        let source = dir.join("source");
+        let mods: Vec<(String, Vec<u8>)> = (0..40)
+            .map(|i| (format!("mods/{:02}.txt", i), format!("mod {} ", i).repeat(i * 50 + 1).into_bytes()))
+            .collect();
+        for (path, data) in &mods {
+            write_files(&source, &[(path, data)]);
+        }
+        write_files(&source, &[("config/c.toml", b"key = 1"), ("big.log", &[b'x'; 100_000]), ("resourcepacks/pack/a.png", b"png")]);
+        let files = || vec![
+            file_item(&source, "mods", json!({"compression": {"method": "deflate", "level": 9}})),
+            file_item(&source, "config", json!({"compression": {"method": "zstd"}})),
+            file_item(&source, "big.log", json!({"compression": {"method": "bzip2"}})),
+            file_item(&source, "resourcepacks", json!({"compress": true})),
+        ];
+        let (first, second) = (dir.join("first.zip"), dir.join("second.zip"));
+        export(files(), json!({}), &first).unwrap();
+
+        // 并行处理后仍按选择顺序和文件名顺序写入清单
+        let entries = read_zip(&first);
+        let manifest = parse_manifest(&entries["manifest.json"]);
+        let mut expected: Vec<&str> = mods.iter().map(|(path, _)| path.as_str()).collect();
+        expected.extend(["config/c.toml", "big.log", "resourcepacks.zip"]);
+        assert_eq!(manifest.files.iter().map(|f| f.relative_path.as_str()).collect::<Vec<_>>(), expected);
+        for file in &manifest.files {
+            let data = &entries[&file.relative_path];
+            assert_eq!(file.size, data.len() as u64, "{}", file.relative_path);
+            assert_eq!(hex::encode(Sha256::digest(data)), file.hash, "{}", file.relative_path);
+        }
+        for (path, data) in &mods {
+            assert_eq!(&entries[path], data);
+        }
+
+        let mut archive = ZipArchive::new(File::open(&first).unwrap()).unwrap();
+        assert_eq!(archive.by_name("mods/00.txt").unwrap().compression(), zip::CompressionMethod::Deflated);
+        assert_eq!(archive.by_name("config/c.toml").unwrap().compression(), zip::CompressionMethod::Zstd);
+        assert_eq!(archive.by_name("big.log").unwrap().compression(), zip::CompressionMethod::Bzip2);
+
+        // 文件夹压缩包的内容不变时哈希也不变，玩家不会重复下载
+        export(files(), json!({}), &second).unwrap();
+        let archive_hash = |path: &Path| {
+            let manifest = parse_manifest(&read_zip(path)["manifest.json"]);
+            manifest.files.iter().find(|f| f.relative_path == "resourcepacks.zip").unwrap().hash.clone()
+        };
+        assert_eq!(archive_hash(&first), archive_hash(&second));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn splits_archives_at_part_boundaries() {
+        let dir = temp_dir();
+        let archive = dir.join("archive.zip");
+        let data: Vec<u8> = (0..10).collect();
+        fs::write(&archive, &data).unwrap();
+        let sizes = |max_part_size| -> Vec<u64> {
+            plan_parts(&archive, "a.zip", 10, max_part_size, false).unwrap().iter().map(|p| p.size).collect()
+        };
+        assert_eq!(sizes(4), [4, 4, 2]);
+        assert_eq!(sizes(5), [5, 5]);
+        assert_eq!(sizes(10), [10]);
+
+        let parts = plan_parts(&archive, "a.zip", 10, 4, false).unwrap();
+        assert_eq!(parts[2].download_url, "{download_prefix}a.zip.003");
+        assert_eq!(parts[2].hash, hex::encode(Sha256::digest(&data[8..])));
+        assert!(plan_parts(&archive, "a.zip", 10, 4, true).unwrap().iter().all(|p| p.hash == "DISABLED"));
+
+        // 按顺序拼接各分卷得到原来的压缩包
+        let out = dir.join("out");
+        let mut output = ExportOutput::create(OutputMode::Directory, &out).unwrap();
+        write_parts(&mut output, "a.zip", &archive, &parts).unwrap();
+        let joined: Vec<u8> = (0..parts.len())
+            .flat_map(|index| fs::read(out.join(format!("a.zip{}", manifest::part_suffix(index)))).unwrap())
+            .collect();
+        assert_eq!(joined, data);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exports_large_folder_archives_as_parts() {
+        let dir = temp_dir();
+        let source = dir.join("source");
+        // 哈希值几乎无法压缩，压缩包仍然大于分卷大小
+        let noise: Vec<u8> = (0..2_000u32).flat_map(|i| Sha256::digest(i.to_le_bytes())).collect();
+        write_files(&source, &[("shaderpacks/noise.bin", &noise), ("mods/a.jar", b"a")]);
+        let package = dir.join("pack.zip");
+        let files = vec![file_item(&source, "shaderpacks", json!({"compress": true})), file_item(&source, "mods", json!({}))];
+        export(files, json!({"maxPartSize": 20_000}), &package).unwrap();
+
+        let entries = read_zip(&package);
+        let manifest = parse_manifest(&entries["manifest.json"]);
+        let archive = manifest.files.iter().find(|f| f.relative_path == "shaderpacks.zip").unwrap();
+        let parts = archive.parts.as_deref().unwrap();
+        assert!(parts.len() >= 3);
+        assert!(!entries.contains_key("shaderpacks.zip"));
+        let joined: Vec<u8> = parts.iter().enumerate()
+            .flat_map(|(index, part)| {
+                let data = entries[&format!("shaderpacks.zip{}", manifest::part_suffix(index))].clone();
+                assert_eq!(hex::encode(Sha256::digest(&data)), part.hash);
+                data
+            })
+            .collect();
+        assert_eq!(hex::encode(Sha256::digest(&joined)), archive.hash);
+        assert_eq!(parts.iter().map(|p| p.size).sum::<u64>(), archive.size);
+
+        // 小于分卷大小的文件不切分
+        let mod_file = manifest.files.iter().find(|f| f.relative_path == "mods/a.jar").unwrap();
+        assert!(mod_file.parts.is_none());
+        assert!(export(vec![file_item(&source, "mods", json!({}))], json!({"maxPartSize": 0}), &package).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
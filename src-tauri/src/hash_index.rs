@@ -0,0 +1,235 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 与 .sync_exclude.json 放在同一目录下
+pub const INDEX_FILE_NAME: &str = ".sync_index.json";
+
+// 索引格式变化时递增，旧索引会被整体丢弃
+const INDEX_VERSION: u32 = 1;
+
+// 修改时间距离扫描开始不足该秒数的文件不写入索引，
+// 避免同一秒内再次修改但大小不变的文件被误判为未变化
+const RACY_WINDOW_SECS: u64 = 2;
+
+pub fn calculate_file_hash(path: &Path) -> Result<String, std::io::Error> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct IndexEntry {
+    size: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct HashIndex {
+    version: u32,
+    // key 为相对目标目录、使用 `/` 分隔的路径
+    entries: HashMap<String, IndexEntry>,
+}
+
+fn index_path(target_dir: &Path) -> PathBuf {
+    target_dir.join(INDEX_FILE_NAME)
+}
+
+fn relative_key(target_dir: &Path, path: &Path) -> Option<String> {
+    path.strip_prefix(target_dir)
+        .ok()
+        .map(|rel| rel.to_string_lossy().replace('\\', "/"))
+}
+
+fn modified_time(metadata: &fs::Metadata) -> Option<(u64, u32)> {
+    let since_epoch = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some((since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+impl HashIndex {
+    // 索引缺失、损坏或版本不符时返回空索引，相当于完整重新扫描
+    pub fn load(target_dir: &Path) -> HashIndex {
+        let loaded = fs::read_to_string(index_path(target_dir))
+            .ok()
+            .and_then(|text| serde_json::from_str::<HashIndex>(&text).ok());
+
+        match loaded {
+            Some(index) if index.version == INDEX_VERSION => index,
+            _ => HashIndex { version: INDEX_VERSION, entries: HashMap::new() },
+        }
+    }
+
+    pub fn save(&self, target_dir: &Path) -> Result<(), String> {
+        let path = index_path(target_dir);
+        // 同一目录上可能同时在计算差异，各自写入自己的临时文件
+        let temp_path = target_dir.join(format!("{}.{}.tmp", INDEX_FILE_NAME, uuid::Uuid::new_v4()));
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(&temp_path, json).map_err(|e| format!("Failed to write hash index: {}", e))?;
+        fs::rename(&temp_path, &path).map_err(|e| {
+            let _ = fs::remove_file(&temp_path);
+            format!("Failed to replace hash index: {}", e)
+        })
+    }
+
+    fn lookup(&self, key: &str, size: u64, mtime: (u64, u32)) -> Option<&str> {
+        self.entries.get(key)
+            .filter(|entry| entry.size == size && (entry.mtime_secs, entry.mtime_nanos) == mtime)
+            .map(|entry| entry.hash.as_str())
+    }
+}
+
+// 扫描目录并返回 路径 -> SHA256，大小与修改时间都未变化的文件直接复用索引中的哈希。
+// `force_rescan` 为 true 时忽略已有索引，重新计算所有文件。
+pub fn scan_with_index(
+    target_dir: &Path,
+    scan_dirs: &[PathBuf],
+    force_rescan: bool,
+) -> HashMap<PathBuf, String> {
+    let previous = if force_rescan {
+        HashIndex { version: INDEX_VERSION, entries: HashMap::new() }
+    } else {
+        HashIndex::load(target_dir)
+    };
+
+    let scan_started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let scanned: Vec<(PathBuf, Option<String>, Option<IndexEntry>, String)> = scan_dirs.par_iter()
+        .filter(|dir| dir.exists() && dir.is_dir())
+        .flat_map(|dir| {
+            walkdir::WalkDir::new(dir).into_iter()
+                .filter_map(Result::ok)
+                .par_bridge()
+                .filter(|entry| entry.file_type().is_file())
+                .filter_map(|entry| {
+                    let path = entry.path().to_path_buf();
+                    let key = relative_key(target_dir, &path);
+                    let metadata = entry.metadata().ok();
+                    let stamp = metadata.as_ref().and_then(|m| modified_time(m).map(|t| (m.len(), t)));
+
+                    let cached = match (&key, stamp) {
+                        (Some(key), Some((size, mtime))) => previous.lookup(key, size, mtime).map(str::to_string),
+                        _ => None,
+                    };
+                    let hash = match cached {
+                        Some(hash) => hash,
+                        None => calculate_file_hash(&path).ok()?,
+                    };
+
+                    let entry = stamp
+                        .filter(|(_, (secs, _))| secs.saturating_add(RACY_WINDOW_SECS) < scan_started)
+                        .map(|(size, (mtime_secs, mtime_nanos))| IndexEntry {
+                            size,
+                            mtime_secs,
+                            mtime_nanos,
+                            hash: hash.clone(),
+                        });
+                    Some((path, key, entry, hash))
+                })
+        })
+        .collect();
+
+    // 保留不在本次扫描范围内的条目（同一目标目录可能同步多个包），
+    // 扫描范围内已删除的文件则随之失效
+    let mut entries: HashMap<String, IndexEntry> = previous.entries.into_iter()
+        .filter(|(key, _)| !scan_dirs.iter().any(|dir| target_dir.join(key).starts_with(dir)))
+        .collect();
+
+    let mut local_files = HashMap::with_capacity(scanned.len());
+    for (path, key, entry, hash) in scanned {
+        if let (Some(key), Some(entry)) = (key, entry) {
+            entries.insert(key, entry);
+        }
+        local_files.insert(path, hash);
+    }
+
+    let index = HashIndex { version: INDEX_VERSION, entries };
+    if let Err(e) = index.save(target_dir) {
+        eprintln!("{}", e);
+    }
+
+    local_files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    // 修改时间早于竞态窗口，扫描结果才会写入索引
+    fn write_old_file(path: &Path, content: &str, age: Duration) {
+        fs::write(path, content).unwrap();
+        File::options().write(true).open(path).unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    fn hash_of(content: &str) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    #[test]
+    fn reuses_hashes_until_size_or_mtime_changes() {
+        let target = std::env::temp_dir().join(format!("acgstation-index-{}", uuid::Uuid::new_v4()));
+        let mods = target.join("mods");
+        fs::create_dir_all(&mods).unwrap();
+        let path = mods.join("a.jar");
+        let scan_dirs = [mods.clone()];
+        let hour = Duration::from_secs(3600);
+        write_old_file(&path, "aaaa", hour);
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+
+        assert_eq!(scan_with_index(&target, &scan_dirs, false)[&path], hash_of("aaaa"));
+        assert!(HashIndex::load(&target).entries.contains_key("mods/a.jar"));
+
+        // 大小和修改时间都没变时直接使用索引中的哈希
+        fs::write(&path, "bbbb").unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+        assert_eq!(scan_with_index(&target, &scan_dirs, false)[&path], hash_of("aaaa"));
+        assert_eq!(scan_with_index(&target, &scan_dirs, true)[&path], hash_of("bbbb"));
+
+        write_old_file(&path, "cccc", hour * 2);
+        assert_eq!(scan_with_index(&target, &scan_dirs, false)[&path], hash_of("cccc"));
+        File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() - hour * 2).unwrap();
+        fs::write(&path, "ddddd").unwrap();
+        assert_eq!(scan_with_index(&target, &scan_dirs, false)[&path], hash_of("ddddd"));
+
+        // 刚修改过的文件不写入索引；删除的文件从索引中移除，临时文件不残留
+        assert!(!HashIndex::load(&target).entries.contains_key("mods/a.jar"));
+        fs::remove_file(&path).unwrap();
+        assert!(scan_with_index(&target, &scan_dirs, false).is_empty());
+        let leftovers: Vec<_> = fs::read_dir(&target).unwrap()
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".tmp"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+        let _ = fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn keeps_entries_outside_the_scanned_dirs() {
+        let target = std::env::temp_dir().join(format!("acgstation-index-{}", uuid::Uuid::new_v4()));
+        for dir in ["mods", "config"] {
+            fs::create_dir_all(target.join(dir)).unwrap();
+            write_old_file(&target.join(dir).join("a.txt"), dir, Duration::from_secs(3600));
+        }
+        scan_with_index(&target, &[target.join("mods"), target.join("config")], false);
+        fs::remove_dir_all(target.join("config")).unwrap();
+        scan_with_index(&target, &[target.join("mods")], false);
+
+        let index = HashIndex::load(&target);
+        assert!(index.entries.contains_key("mods/a.txt"));
+        assert!(index.entries.contains_key("config/a.txt"));
+        let _ = fs::remove_dir_all(&target);
+    }
+}
@@ -0,0 +1,106 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::paths;
+use crate::report::SyncStatus;
+
+// 同步记录保存在应用数据目录，按配置记录检查更新和同步的经过
+const HISTORY_FILE_NAME: &str = "sync_history.json";
+const MAX_KEPT_ENTRIES: usize = 500;
+
+static HISTORY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// 发起同步的方式
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncTrigger {
+    Manual,
+    Scheduled,
+    Idle,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    // 检查到与上次同步不同的清单版本
+    UpdateAvailable {
+        version: String,
+        #[serde(rename = "currentVersion")]
+        current_version: Option<String>,
+    },
+    SyncStarted {
+        trigger: SyncTrigger,
+        version: String,
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    SyncFinished {
+        trigger: SyncTrigger,
+        version: String,
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        status: SyncStatus,
+        error: Option<String>,
+    },
+    // 检查更新或开始自动同步失败
+    Error {
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub at: String,
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    #[serde(rename = "profileName")]
+    pub profile_name: String,
+    pub event: HistoryEvent,
+}
+
+fn history_path() -> Result<PathBuf, String> {
+    Ok(paths::app_data_dir()?.join(HISTORY_FILE_NAME))
+}
+
+fn load_entries() -> Result<Vec<HistoryEntry>, String> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+}
+
+// 追加一条记录，超出上限时丢弃最早的记录；写入失败只打印，不影响同步
+pub fn record(profile_id: &str, profile_name: &str, event: HistoryEvent) {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    let result = load_entries().and_then(|mut entries| {
+        entries.push(HistoryEntry {
+            at: chrono::Utc::now().to_rfc3339(),
+            profile_id: profile_id.to_string(),
+            profile_name: profile_name.to_string(),
+            event,
+        });
+        let excess = entries.len().saturating_sub(MAX_KEPT_ENTRIES);
+        entries.drain(..excess);
+        let json = serde_json::to_string_pretty(&entries).map_err(|e| e.to_string())?;
+        fs::write(history_path()?, json).map_err(|e| e.to_string())
+    });
+    if let Err(e) = result {
+        eprintln!("Failed to record sync history: {}", e);
+    }
+}
+
+// 最近的记录在前；指定配置时只返回该配置的记录
+pub fn list(profile_id: Option<&str>, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+    let _lock = HISTORY_LOCK.lock().unwrap();
+    Ok(load_entries()?
+        .into_iter()
+        .rev()
+        .filter(|entry| profile_id.is_none_or(|id| entry.profile_id == id))
+        .take(limit)
+        .collect())
+}
@@ -0,0 +1,526 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+use crate::events::EventSink;
+use crate::exclusions::Exclusions;
+use crate::hash_index::calculate_file_hash;
+use crate::manifest::Manifest;
+use crate::paths;
+use crate::platform::Platform;
+use crate::transaction::SyncTransaction;
+
+// 玩家确认过的运行动作（按可执行文件哈希和参数记录），与 .sync_exclude.json 放在同一目录下
+const APPROVALS_FILE_NAME: &str = ".sync_hooks.json";
+// 运行的程序超过该时间仍未退出时结束它
+const RUN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// 清单中声明的同步前后动作，按顺序执行，只能作用于目标目录内
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SyncHooks {
+    // 所有文件下载校验完成、写入目标目录之前
+    #[serde(rename = "preSync", default, skip_serializing_if = "Vec::is_empty")]
+    pub pre_sync: Vec<HookAction>,
+    // 同步成功提交之后
+    #[serde(rename = "postSync", default, skip_serializing_if = "Vec::is_empty")]
+    pub post_sync: Vec<HookAction>,
+}
+
+// 路径都相对目标目录；模板和参数中的 {targetDir}、{packageName}、{version}、{os}、{arch}、
+// {gameVersion}、{loader} 在执行时替换
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "camelCase")]
+pub enum HookAction {
+    // 删除匹配的文件和目录，语法与排除规则相同
+    Delete { pattern: String },
+    Rename { from: String, to: String },
+    WriteFile { path: String, template: String },
+    // 只能运行清单中的文件，本地哈希与清单一致且玩家确认过这组参数后才会执行；程序自己做的改动不会记入快照
+    Run {
+        executable: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum HookPhase {
+    #[serde(rename = "preSync")]
+    PreSync,
+    #[serde(rename = "postSync")]
+    PostSync,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HookStatus {
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+// 单个动作的执行结果，记录在同步结果的 hooks 中
+#[derive(Debug, Serialize, Clone)]
+pub struct HookResult {
+    pub phase: HookPhase,
+    pub action: String,
+    pub status: HookStatus,
+    pub message: Option<String>,
+}
+
+// 需要玩家确认的运行动作
+#[derive(Debug, Serialize, Clone)]
+pub struct PendingRun {
+    pub executable: String,
+    pub args: Vec<String>,
+    pub hash: String,
+    // 确认时记录的值，见 approval_key
+    pub approval: String,
+}
+
+pub struct HookContext<'a> {
+    pub target_dir: &'a Path,
+    pub manifest: &'a Manifest,
+    pub platform: &'a Platform,
+    pub exclusions: &'a Exclusions,
+}
+
+impl HookAction {
+    fn describe(&self) -> String {
+        match self {
+            HookAction::Delete { pattern } => format!("delete {}", pattern),
+            HookAction::Rename { from, to } => format!("rename {} -> {}", from, to),
+            HookAction::WriteFile { path, .. } => format!("write {}", path),
+            HookAction::Run { executable, args } if args.is_empty() => format!("run {}", executable),
+            HookAction::Run { executable, args } => format!("run {} {}", executable, args.join(" ")),
+        }
+    }
+}
+
+impl HookContext<'_> {
+    fn render(&self, template: &str) -> String {
+        let target_dir = std::path::absolute(self.target_dir).unwrap_or_else(|_| self.target_dir.to_path_buf());
+        let target_dir = target_dir.to_string_lossy().replace('\\', "/");
+        template
+            .replace("{targetDir}", &target_dir)
+            .replace("{packageName}", &self.manifest.package_name)
+            .replace("{version}", &self.manifest.version)
+            .replace("{os}", &self.platform.os)
+            .replace("{arch}", &self.platform.arch)
+            .replace("{gameVersion}", self.platform.game_version.as_deref().unwrap_or_default())
+            .replace("{loader}", self.platform.loader.as_deref().unwrap_or_default())
+    }
+
+    // 校验后的目标目录内路径；同步器自己的文件和被玩家排除的路径不允许改动
+    fn resolve(&self, relative: &str) -> Result<PathBuf, String> {
+        let normalized = paths::validate_relative_path(relative)?;
+        if self.exclusions.is_excluded(&normalized, false) {
+            return Err(format!("{} is excluded by the player", relative));
+        }
+        paths::resolve_in(self.target_dir, &normalized)
+    }
+}
+
+// 改动之前把路径当前的状态记入本次同步的快照，恢复快照时可以撤销动作的改动
+fn preserve(context: &HookContext, transaction: &mut SyncTransaction, path: &Path) -> Result<(), String> {
+    let relative = path.strip_prefix(context.target_dir).map_err(|e| e.to_string())?;
+    transaction.preserve(&relative.to_string_lossy().replace('\\', "/"))
+}
+
+// 依次执行某个阶段的动作，改动的文件记入 `transaction` 的快照；单个动作失败不影响后续动作和同步本身
+pub fn run_phase(
+    events: &dyn EventSink,
+    phase: HookPhase,
+    actions: &[HookAction],
+    context: &HookContext,
+    transaction: &mut SyncTransaction,
+) -> Vec<HookResult> {
+    let approved = load_approvals(context.target_dir).unwrap_or_default();
+    actions.iter()
+        .map(|action| {
+            let (status, message) = match run_action(action, context, transaction, &approved) {
+                Ok(None) => (HookStatus::Succeeded, None),
+                Ok(Some(reason)) => (HookStatus::Skipped, Some(reason)),
+                Err(e) => (HookStatus::Failed, Some(e)),
+            };
+            let result = HookResult { phase, action: action.describe(), status, message };
+            events.emit("HOOK_FINISHED", &result);
+            result
+        })
+        .collect()
+}
+
+// 成功时返回 None，跳过时返回原因
+fn run_action(
+    action: &HookAction,
+    context: &HookContext,
+    transaction: &mut SyncTransaction,
+    approved: &[String],
+) -> Result<Option<String>, String> {
+    match action {
+        HookAction::Delete { pattern } => delete_matching(context, transaction, pattern).map(|count| {
+            (count == 0).then(|| "Nothing matched".to_string())
+        }),
+        HookAction::Rename { from, to } => {
+            let from_path = context.resolve(from)?;
+            let to_path = context.resolve(to)?;
+            if !from_path.exists() {
+                return Ok(Some(format!("{} does not exist", from)));
+            }
+            if to_path.is_dir() {
+                return Err(format!("{} already exists", to));
+            }
+            preserve(context, transaction, &from_path)?;
+            preserve(context, transaction, &to_path)?;
+            if let Some(parent) = to_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            if to_path.is_file() {
+                fs::remove_file(&to_path).map_err(|e| format!("Failed to replace {}: {}", to, e))?;
+            }
+            fs::rename(&from_path, &to_path).map_err(|e| format!("Failed to rename {}: {}", from, e))?;
+            Ok(None)
+        }
+        HookAction::WriteFile { path, template } => {
+            let file_path = context.resolve(path)?;
+            preserve(context, transaction, &file_path)?;
+            if let Some(parent) = file_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::write(&file_path, context.render(template)).map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            Ok(None)
+        }
+        HookAction::Run { executable, args } => {
+            let (path, hash) = verified_executable(context, executable)?;
+            if !approved.contains(&approval_key(&hash, args)) {
+                return Ok(Some("Not approved by the player".to_string()));
+            }
+            let args: Vec<String> = args.iter().map(|arg| context.render(arg)).collect();
+            run_executable(&path, &args, context.target_dir)
+                .map_err(|e| format!("{}: {}", executable, e))?;
+            Ok(None)
+        }
+    }
+}
+
+fn delete_matching(context: &HookContext, transaction: &mut SyncTransaction, pattern: &str) -> Result<usize, String> {
+    let matcher = Exclusions::new(context.target_dir, &[pattern.to_string()])?;
+    let mut matched = Vec::new();
+    let mut walker = WalkDir::new(context.target_dir).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else { continue };
+        let is_dir = entry.file_type().is_dir();
+        let Ok(relative) = entry.path().strip_prefix(context.target_dir) else { continue };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        // 同步器自己的文件和玩家排除的路径整体跳过
+        if relative.starts_with(paths::INTERNAL_PREFIX) || context.exclusions.is_excluded(&relative, is_dir) {
+            if is_dir {
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if matcher.is_excluded(&relative, is_dir) {
+            if is_dir {
+                walker.skip_current_dir();
+            }
+            matched.push((entry.into_path(), is_dir));
+        }
+    }
+
+    for (path, is_dir) in &matched {
+        preserve(context, transaction, path)?;
+        let removed = if *is_dir { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        removed.map_err(|e| format!("Failed to delete {}: {}", path.to_string_lossy(), e))?;
+    }
+    Ok(matched.len())
+}
+
+// 可执行文件必须是清单中的普通文件，且本地内容与清单哈希一致
+fn verified_executable(context: &HookContext, executable: &str) -> Result<(PathBuf, String), String> {
+    let normalized = paths::validate_relative_path(executable)?;
+    let file = context.manifest.files.iter()
+        .find(|file| file.relative_path.replace('\\', "/") == normalized && (file.file_type == "file" || file.file_type == "chunked"))
+        .ok_or_else(|| format!("{} is not a file of this pack", executable))?;
+    let path = paths::resolve_in(context.target_dir, &normalized)?;
+    let local_hash = calculate_file_hash(&path).map_err(|e| format!("Failed to read {}: {}", executable, e))?;
+    if local_hash != file.hash {
+        return Err(format!("{} does not match the manifest hash", executable));
+    }
+    Ok((path, file.hash.clone()))
+}
+
+fn run_executable(path: &Path, args: &[String], working_dir: &Path) -> Result<(), String> {
+    // 下载的文件没有执行权限
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).map_err(|e| e.to_string())?;
+    }
+
+    // 工作目录切换到目标目录后相对路径会失效
+    let path = std::path::absolute(path).map_err(|e| e.to_string())?;
+    let mut child = Command::new(path)
+        .args(args)
+        .current_dir(working_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to start: {}", e))?;
+    let started = Instant::now();
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) if status.success() => return Ok(()),
+            Some(status) => return Err(format!("Exited with {}", status)),
+            None if started.elapsed() > RUN_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("Timed out after {} seconds", RUN_TIMEOUT.as_secs()));
+            }
+            None => std::thread::sleep(Duration::from_millis(100)),
+        }
+    }
+}
+
+// 确认针对可执行文件的内容和替换前的参数，任一变化都需要重新确认
+fn approval_key(hash: &str, args: &[String]) -> String {
+    let json = serde_json::to_vec(&(hash, args)).unwrap_or_default();
+    hex::encode(Sha256::digest(json))
+}
+
+// 清单中尚未被玩家确认的运行动作；可执行文件还没下载或与清单不一致时同样列出，以清单哈希为准
+pub fn pending_runs(manifest: &Manifest, target_dir: &Path) -> Result<Vec<PendingRun>, String> {
+    let approved = load_approvals(target_dir)?;
+    let Some(hooks) = &manifest.hooks else { return Ok(Vec::new()) };
+    let mut pending = Vec::new();
+    for action in hooks.pre_sync.iter().chain(&hooks.post_sync) {
+        let HookAction::Run { executable, args } = action else { continue };
+        let normalized = paths::validate_relative_path(executable)?;
+        let Some(file) = manifest.files.iter().find(|file| file.relative_path.replace('\\', "/") == normalized) else {
+            continue;
+        };
+        let approval = approval_key(&file.hash, args);
+        if !approved.contains(&approval) && !pending.iter().any(|run: &PendingRun| run.approval == approval) {
+            pending.push(PendingRun {
+                executable: executable.clone(),
+                args: args.clone(),
+                hash: file.hash.clone(),
+                approval,
+            });
+        }
+    }
+    Ok(pending)
+}
+
+fn load_approvals(target_dir: &Path) -> Result<Vec<String>, String> {
+    let config_path = target_dir.join(APPROVALS_FILE_NAME);
+    if !config_path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = File::open(config_path).map_err(|e| e.to_string())?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| e.to_string())
+}
+
+// 记录玩家确认的运行动作，参数为 PendingRun 的 approval
+pub fn approve_runs(target_dir: &Path, approvals: Vec<String>) -> Result<(), String> {
+    let mut approved = load_approvals(target_dir)?;
+    for approval in approvals {
+        if !approved.contains(&approval) {
+            approved.push(approval);
+        }
+    }
+    let file = File::create(target_dir.join(APPROVALS_FILE_NAME)).map_err(|e| e.to_string())?;
+    serde_json::to_writer(BufWriter::new(file), &approved).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshots::SnapshotReason;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit_json(&self, _event: &str, _payload: serde_json::Value) {}
+    }
+
+    struct Fixture {
+        target: PathBuf,
+        manifest: Manifest,
+        platform: Platform,
+        exclusions: Exclusions,
+    }
+
+    impl Fixture {
+        fn new(files: serde_json::Value) -> Fixture {
+            let target = std::env::temp_dir().join(format!("acgstation-hooks-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(target.join("saves")).unwrap();
+            let manifest = crate::manifest::parse_manifest(&serde_json::json!({
+                "schemaVersion": 6,
+                "packageName": "pack",
+                "version": "1.0",
+                "files": files,
+            }).to_string()).unwrap();
+            let platform = Platform { os: "linux".to_string(), arch: "x86_64".to_string(), game_version: None, loader: None };
+            let exclusions = Exclusions::new(&target, &["saves/".to_string()]).unwrap();
+            Fixture { target, manifest, platform, exclusions }
+        }
+
+        fn write(&self, path: &str, content: &str) {
+            let path = self.target.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+
+        fn read(&self, path: &str) -> Option<String> {
+            fs::read_to_string(self.target.join(path)).ok()
+        }
+
+        fn begin(&self) -> SyncTransaction {
+            SyncTransaction::begin(&self.target, SnapshotReason::Restore { snapshot_id: "test".to_string() }).unwrap()
+        }
+
+        fn run(&self, transaction: &mut SyncTransaction, actions: &[HookAction]) -> Vec<HookResult> {
+            let context = HookContext {
+                target_dir: &self.target,
+                manifest: &self.manifest,
+                platform: &self.platform,
+                exclusions: &self.exclusions,
+            };
+            run_phase(&NoEvents, HookPhase::PreSync, actions, &context, transaction)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.target);
+        }
+    }
+
+    fn write_file(path: &str, template: &str) -> HookAction {
+        HookAction::WriteFile { path: path.to_string(), template: template.to_string() }
+    }
+
+    fn statuses(results: &[HookResult]) -> Vec<HookStatus> {
+        results.iter().map(|result| result.status).collect()
+    }
+
+    #[test]
+    fn delete_skips_internal_and_excluded_paths() {
+        let fixture = Fixture::new(serde_json::json!([]));
+        fixture.write(".sync_hooks.json", "[]");
+        fixture.write("saves/world.dat", "w");
+        fixture.write("config/a.dat", "a");
+        let mut transaction = fixture.begin();
+
+        let results = fixture.run(&mut transaction, &[
+            HookAction::Delete { pattern: "*".to_string() },
+            HookAction::Delete { pattern: "*.dat".to_string() },
+        ]);
+        transaction.abort(false);
+
+        assert_eq!(statuses(&results), vec![HookStatus::Succeeded, HookStatus::Skipped]);
+        assert_eq!(results[1].message.as_deref(), Some("Nothing matched"));
+        assert!(fixture.target.join(".sync_hooks.json").exists());
+        assert!(fixture.target.join("saves/world.dat").exists());
+    }
+
+    #[test]
+    fn rejects_paths_outside_the_sandbox() {
+        let fixture = Fixture::new(serde_json::json!([]));
+        fixture.write("a.txt", "a");
+        let outside = std::env::temp_dir().join(format!("acgstation-hooks-outside-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&outside).unwrap();
+        let mut actions = vec![
+            HookAction::Rename { from: "a.txt".to_string(), to: "../a.txt".to_string() },
+            HookAction::Rename { from: "a.txt".to_string(), to: "/tmp/a.txt".to_string() },
+            write_file("../escape.txt", "x"),
+            write_file(".sync_journal.json", "x"),
+            write_file("saves/level.dat", "x"),
+        ];
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, fixture.target.join("link")).unwrap();
+            actions.push(write_file("link/escape.txt", "x"));
+        }
+        let mut transaction = fixture.begin();
+
+        let results = fixture.run(&mut transaction, &actions);
+        transaction.abort(false);
+
+        assert!(results.iter().all(|result| result.status == HookStatus::Failed), "{:?}", results);
+        assert_eq!(fixture.read("a.txt").as_deref(), Some("a"));
+        assert!(!outside.join("escape.txt").exists());
+        assert!(!fixture.target.join("saves/level.dat").exists());
+        let _ = fs::remove_dir_all(&outside);
+    }
+
+    #[test]
+    fn runs_only_approved_actions_with_the_same_args() {
+        let script = "#!/bin/sh\necho \"$1\" > ran.txt\n";
+        let hash = hex::encode(Sha256::digest(script));
+        let mut fixture = Fixture::new(serde_json::json!([
+            {"name": "tool.sh", "downloadUrl": "https://example.com/tool.sh", "relativePath": "tool.sh", "hash": hash, "size": script.len(), "type": "file"}
+        ]));
+        fixture.write("tool.sh", script);
+        let run = |arg: &str| HookAction::Run { executable: "tool.sh".to_string(), args: vec![arg.to_string()] };
+        fixture.manifest.hooks = Some(SyncHooks { pre_sync: vec![run("a")], post_sync: Vec::new() });
+
+        let mut transaction = fixture.begin();
+        let results = fixture.run(&mut transaction, &[run("a")]);
+        assert_eq!(statuses(&results), vec![HookStatus::Skipped]);
+        assert_eq!(results[0].message.as_deref(), Some("Not approved by the player"));
+
+        let pending = pending_runs(&fixture.manifest, &fixture.target).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].hash, hash);
+        approve_runs(&fixture.target, pending.into_iter().map(|run| run.approval).collect()).unwrap();
+        assert!(pending_runs(&fixture.manifest, &fixture.target).unwrap().is_empty());
+
+        // 参数变化后需要重新确认
+        fixture.manifest.hooks = Some(SyncHooks { pre_sync: vec![run("b")], post_sync: Vec::new() });
+        assert_eq!(pending_runs(&fixture.manifest, &fixture.target).unwrap().len(), 1);
+        let results = fixture.run(&mut transaction, &[run("b")]);
+        assert_eq!(statuses(&results), vec![HookStatus::Skipped]);
+        assert!(fixture.read("ran.txt").is_none());
+
+        #[cfg(unix)]
+        {
+            let results = fixture.run(&mut transaction, &[run("a")]);
+            assert_eq!(statuses(&results), vec![HookStatus::Succeeded], "{:?}", results);
+            assert_eq!(fixture.read("ran.txt").as_deref(), Some("a\n"));
+        }
+        transaction.abort(false);
+    }
+
+    #[test]
+    fn failed_commit_undoes_pre_sync_changes() {
+        let fixture = Fixture::new(serde_json::json!([]));
+        fixture.write("a.txt", "a0");
+        fixture.write("b.txt", "b0");
+        fixture.write("old/x.txt", "x0");
+        let mut transaction = fixture.begin();
+
+        let results = fixture.run(&mut transaction, &[
+            write_file("a.txt", "{packageName} {version}"),
+            write_file("new/c.txt", "c1"),
+            HookAction::Rename { from: "b.txt".to_string(), to: "moved/b.txt".to_string() },
+            HookAction::Delete { pattern: "old/".to_string() },
+        ]);
+        assert!(results.iter().all(|result| result.status == HookStatus::Succeeded), "{:?}", results);
+        assert_eq!(fixture.read("a.txt").as_deref(), Some("pack 1.0"));
+
+        // 提交失败时由 commit_with_hooks 放弃事务
+        transaction.abort(false);
+        assert_eq!(fixture.read("a.txt").as_deref(), Some("a0"));
+        assert_eq!(fixture.read("b.txt").as_deref(), Some("b0"));
+        assert_eq!(fixture.read("old/x.txt").as_deref(), Some("x0"));
+        assert!(fixture.read("new/c.txt").is_none());
+        assert!(fixture.read("moved/b.txt").is_none());
+    }
+}
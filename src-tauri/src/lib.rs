@@ -0,0 +1,25 @@
+// 导出与同步引擎，图形界面（main.rs）和命令行（bin/acgstation-sync-cli.rs）共用
+pub mod events;
+pub mod manifest;
+pub mod export;
+pub mod sync;
+pub mod paths;
+pub mod exclusions;
+pub mod hash_index;
+pub mod transaction;
+pub mod delta;
+pub mod chunking;
+pub mod mirrors;
+pub mod signing;
+pub mod scheduler;
+pub mod session;
+pub mod report;
+pub mod platform;
+pub mod hooks;
+pub mod snapshots;
+pub mod profiles;
+pub mod history;
+pub mod updates;
+
+#[cfg(test)]
+mod test_server;
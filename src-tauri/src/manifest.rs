@@ -0,0 +1,386 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use crate::hooks::SyncHooks;
+use crate::platform::{FileCondition, Platform};
+use crate::{chunking, scheduler, signing};
+
+// 清单格式版本。1 为没有 schemaVersion 字段的旧版清单（导出端写 package_name、download_url 等
+// snake_case 键名），2 起键名与规范一致并写入 schemaVersion，3 增加分卷压缩包（parts），
+// 4 增加可选文件组（groups），5 增加按平台和游戏版本生效的文件（conditions），
+// 6 增加同步前后动作（hooks）。格式变化时递增，并在 migrate 中补上迁移步骤
+pub const SCHEMA_VERSION: u32 = 6;
+
+// 分卷压缩包的一个分卷，按顺序拼接后即为整个压缩包
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilePart {
+    #[serde(rename = "downloadUrl")]
+    pub download_url: String,
+    pub hash: String,
+    pub size: u64,
+}
+
+// 分卷依次命名为 `<relativePath>.001`、`.002`……，下载地址同样在压缩包地址后追加序号
+pub fn part_suffix(index: usize) -> String {
+    format!(".{:03}", index + 1)
+}
+
+// 玩家可以自行开关的一组可选文件，例如光影、高清材质
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FileGroup {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "enabledByDefault", default)]
+    pub enabled_by_default: bool,
+}
+
+// 从某个旧版本（以旧文件哈希标识）升级到当前文件的二进制补丁
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FilePatch {
+    #[serde(rename = "fromHash")]
+    pub from_hash: String,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: String,
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestFile {
+    pub name: String,
+    #[serde(rename = "downloadUrl")]
+    pub download_url: String,
+    #[serde(rename = "relativePath")]
+    pub relative_path: String,
+    pub hash: String,
+    pub size: u64,
+    #[serde(rename = "type")]
+    pub file_type: String, // "file", "zip", "update_package", "chunked"
+    #[serde(rename = "autoExtract")]
+    pub auto_extract: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patches: Option<Vec<FilePatch>>,
+    // fileType 为 "chunked" 时的块列表，downloadUrl 为块存放的根地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<Vec<chunking::FileChunk>>,
+    // 备用下载地址，主地址失败时按测得的速度依次切换
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<Vec<String>>,
+    // 压缩包超过分卷大小时切分出的分卷，hash 和 size 仍为整个压缩包的值
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parts: Option<Vec<FilePart>>,
+    // 所属可选文件组的 id，未设置的文件总是同步
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    // 只在满足条件的机器上同步，其余机器既不下载也不清理
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conditions: Option<FileCondition>,
+}
+
+impl ManifestFile {
+    pub fn applies_to(&self, platform: &Platform) -> bool {
+        self.conditions.as_ref().is_none_or(|conditions| conditions.matches(platform))
+    }
+}
+
+// 导出端写出、同步端读取共用的清单结构，读取时先经过 parse_manifest 迁移到当前版本
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "packageName")]
+    pub package_name: String,
+    pub version: String,
+    pub description: Option<String>,
+    #[serde(rename = "disableHashCheck")]
+    pub disable_hash_check: Option<bool>,
+    #[serde(rename = "disableSizeCheck")]
+    pub disable_size_check: Option<bool>,
+    // 包级镜像前缀，文件的 downloadUrl 以其中某个前缀开头时可替换为其他前缀
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<Vec<String>>,
+    #[serde(rename = "createdAt", default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub groups: Option<Vec<FileGroup>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<SyncHooks>,
+    pub files: Vec<ManifestFile>,
+}
+
+impl Manifest {
+    // 导出时写入能读取该清单的最低版本，没有用到新特性的包旧版同步器仍然可以同步
+    pub fn required_schema_version(&self) -> u32 {
+        if self.hooks.is_some() {
+            6
+        } else if self.files.iter().any(|f| f.conditions.is_some()) {
+            5
+        } else if self.groups.is_some() {
+            4
+        } else if self.files.iter().any(|f| f.parts.is_some()) {
+            3
+        } else {
+            2
+        }
+    }
+
+    // 玩家启用的组；没有保存过选择时使用各组的默认开关，选择中已不存在的组忽略
+    pub fn enabled_groups(&self, selection: Option<&[String]>) -> HashSet<String> {
+        self.groups.iter().flatten()
+            .filter(|group| match selection {
+                Some(selection) => selection.contains(&group.id),
+                None => group.enabled_by_default,
+            })
+            .map(|group| group.id.clone())
+            .collect()
+    }
+
+    // 文件是否属于未启用的组；引用了未声明组的文件视为必需文件
+    pub fn is_disabled(&self, file: &ManifestFile, enabled_groups: &HashSet<String>) -> bool {
+        file.group.as_ref().is_some_and(|group| {
+            !enabled_groups.contains(group) && self.groups.iter().flatten().any(|g| &g.id == group)
+        })
+    }
+}
+
+// 解析清单文本，旧版本先迁移到当前版本；比当前版本新的清单直接拒绝
+pub fn parse_manifest(text: &str) -> Result<Manifest, String> {
+    let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid manifest JSON: {}", e))?;
+    let value = migrate(value)?;
+    serde_json::from_value(value).map_err(|e| format!("Invalid manifest: {}", e))
+}
+
+fn schema_version_of(manifest: &Value) -> Result<u64, String> {
+    match manifest.get("schemaVersion") {
+        None => Ok(1),
+        Some(version) => version.as_u64()
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| format!("Invalid manifest schemaVersion {}", version)),
+    }
+}
+
+pub fn migrate(mut manifest: Value) -> Result<Value, String> {
+    if !manifest.is_object() {
+        return Err("Manifest is not a JSON object".to_string());
+    }
+    let version = schema_version_of(&manifest)?;
+    if version > SCHEMA_VERSION as u64 {
+        return Err(format!(
+            "Manifest schemaVersion {} is newer than the supported version {}, please update the app to sync this pack",
+            version, SCHEMA_VERSION
+        ));
+    }
+    if version < 2 {
+        migrate_v1_to_v2(&mut manifest);
+    }
+    if version < 6 {
+        // 版本 3 到 6 只新增了可选的 parts、groups、conditions、hooks 字段，旧清单无需改动
+        manifest["schemaVersion"] = Value::from(6);
+    }
+    Ok(manifest)
+}
+
+// 把 `aliases` 中第一个存在的键改名为 `key`，已有 `key` 时保持不变
+fn rename_key(object: &mut Map<String, Value>, key: &str, aliases: &[&str]) {
+    for alias in aliases {
+        match object.remove(*alias) {
+            Some(value) if !object.contains_key(key) => {
+                object.insert(key.to_string(), value);
+            }
+            _ => {}
+        }
+    }
+}
+
+// 版本 1：导出端与读取端靠别名互认，键名在 snake_case 和 camelCase 之间混用
+fn migrate_v1_to_v2(manifest: &mut Value) {
+    let Some(root) = manifest.as_object_mut() else { return };
+    rename_key(root, "packageName", &["package_name"]);
+    rename_key(root, "createdAt", &["created_at"]);
+
+    for file in root.get_mut("files").and_then(Value::as_array_mut).into_iter().flatten() {
+        let Some(file) = file.as_object_mut() else { continue };
+        rename_key(file, "downloadUrl", &["download_url", "url"]);
+        rename_key(file, "relativePath", &["relative_path"]);
+        rename_key(file, "type", &["fileType", "file_type"]);
+        rename_key(file, "autoExtract", &["auto_extract"]);
+
+        for patch in file.get_mut("patches").and_then(Value::as_array_mut).into_iter().flatten() {
+            let Some(patch) = patch.as_object_mut() else { continue };
+            rename_key(patch, "fromHash", &["from_hash"]);
+            rename_key(patch, "downloadUrl", &["download_url", "url"]);
+        }
+    }
+    root.insert("schemaVersion".to_string(), Value::from(2));
+}
+
+pub fn read_manifest_text_from_zip(zip_path: &Path) -> Result<String, String> {
+    let file = File::open(zip_path).map_err(|e| e.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+    let mut manifest_file = archive.by_name("manifest.json").map_err(|e| e.to_string())?;
+
+    let mut contents = String::new();
+    manifest_file.read_to_string(&mut contents).map_err(|e| e.to_string())?;
+
+    Ok(contents)
+}
+
+// 用清单来源信任的公钥（本地配置 + 同步市场或同步配置提供的）校验清单签名，失败时拒绝使用该清单
+pub fn verify_manifest_text(text: &str, source: &str, trusted_keys: Option<Vec<String>>) -> Result<signing::SignatureInfo, String> {
+    let mut keys = signing::trusted_keys_for(source)?;
+    for key in trusted_keys.unwrap_or_default() {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    signing::verify_manifest(text, &keys)
+}
+
+// 校验签名后再解析，前端传来的清单文本同样要经过这一步
+pub fn parse_verified_manifest(text: &str, source: &str, trusted_keys: Option<Vec<String>>) -> Result<Manifest, String> {
+    verify_manifest_text(text, source, trusted_keys)?;
+    parse_manifest(text)
+}
+
+pub async fn fetch_manifest_text(url: &str) -> Result<String, String> {
+    scheduler::client().get(url)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?
+        .error_for_status()
+        .map_err(|e| e.to_string())?
+        .text()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// 带 If-None-Match / If-Modified-Since 的清单请求结果
+pub enum ManifestFetch {
+    NotModified,
+    Modified {
+        text: String,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+}
+
+// 按上次响应的 ETag 和 Last-Modified 发送条件请求，服务器返回 304 时不下载清单
+pub async fn fetch_manifest_if_changed(url: &str, etag: Option<&str>, last_modified: Option<&str>) -> Result<ManifestFetch, String> {
+    use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+    let mut request = scheduler::client().get(url);
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified {
+        request = request.header(IF_MODIFIED_SINCE, last_modified);
+    }
+    let response = request.send().await.map_err(|e| e.to_string())?;
+    if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(ManifestFetch::NotModified);
+    }
+    let response = response.error_for_status().map_err(|e| e.to_string())?;
+    let header = |name| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let text = response.text().await.map_err(|e| e.to_string())?;
+    Ok(ManifestFetch::Modified { text, etag, last_modified })
+}
+
+// 本地的导出 zip 包或 manifest.json
+pub fn read_manifest_file(path: &Path) -> Result<String, String> {
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+        return read_manifest_text_from_zip(path);
+    }
+    fs::read_to_string(path).map_err(|e| format!("Failed to read manifest {}: {}", path.to_string_lossy(), e))
+}
+
+// 本地 zip 包直接从包内安装，其余来源按清单下载
+pub fn is_local_package(source: &str) -> bool {
+    !source.starts_with("http://") && !source.starts_with("https://") && source.to_lowercase().ends_with(".zip")
+}
+
+// 清单来源可以是 http(s) 地址、导出的 zip 包或本地 manifest.json，读取后不做签名校验
+pub async fn read_manifest_source(source: &str) -> Result<String, String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return fetch_manifest_text(source).await;
+    }
+    read_manifest_file(Path::new(source))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn minimal_manifest() -> Manifest {
+        parse_manifest(r#"{"schemaVersion": 2, "packageName": "pack", "version": "1.0", "files": [
+            {"name": "a.jar", "downloadUrl": "https://example.com/a.jar", "relativePath": "mods/a.jar",
+             "hash": "h", "size": 1, "type": "file", "autoExtract": false}
+        ]}"#).unwrap()
+    }
+
+    #[test]
+    fn migrates_v1_keys() {
+        let migrated = migrate(json!({
+            "package_name": "pack",
+            "version": "1.0",
+            "created_at": "2024-01-01T00:00:00Z",
+            "files": [{
+                "name": "a.jar",
+                "url": "https://example.com/a.jar",
+                "relative_path": "mods/a.jar",
+                "hash": "h",
+                "size": 1,
+                "file_type": "file",
+                "auto_extract": false,
+                "patches": [{"from_hash": "old", "download_url": "https://example.com/a.patch", "hash": "p", "size": 1}]
+            }]
+        })).unwrap();
+        assert_eq!(migrated["schemaVersion"], SCHEMA_VERSION);
+        assert_eq!(migrated["packageName"], "pack");
+        assert_eq!(migrated["createdAt"], "2024-01-01T00:00:00Z");
+        let file = &migrated["files"][0];
+        assert_eq!(file["downloadUrl"], "https://example.com/a.jar");
+        assert_eq!(file["relativePath"], "mods/a.jar");
+        assert_eq!(file["type"], "file");
+        assert_eq!(file["patches"][0]["fromHash"], "old");
+        assert!(serde_json::from_value::<Manifest>(migrated).is_ok());
+    }
+
+    #[test]
+    fn keeps_existing_camel_case_keys() {
+        let migrated = migrate(json!({"packageName": "new", "package_name": "old", "version": "1.0", "files": []})).unwrap();
+        assert_eq!(migrated["packageName"], "new");
+        assert!(migrated.get("package_name").is_none());
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        assert!(migrate(json!({"schemaVersion": SCHEMA_VERSION + 1, "files": []})).is_err());
+        assert!(migrate(json!({"schemaVersion": 0, "files": []})).is_err());
+        assert!(migrate(json!({"schemaVersion": "2", "files": []})).is_err());
+        assert!(migrate(json!([])).is_err());
+    }
+
+    #[test]
+    fn requires_the_lowest_schema_for_used_features() {
+        let mut manifest = minimal_manifest();
+        assert_eq!(manifest.required_schema_version(), 2);
+        manifest.files[0].parts = Some(Vec::new());
+        assert_eq!(manifest.required_schema_version(), 3);
+        manifest.groups = Some(Vec::new());
+        assert_eq!(manifest.required_schema_version(), 4);
+        manifest.files[0].conditions = Some(FileCondition { loader: Some(vec!["fabric".to_string()]), ..Default::default() });
+        assert_eq!(manifest.required_schema_version(), 5);
+        manifest.hooks = Some(SyncHooks::default());
+        assert_eq!(manifest.required_schema_version(), 6);
+        assert!(manifest.required_schema_version() <= SCHEMA_VERSION);
+    }
+}
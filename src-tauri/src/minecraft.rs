@@ -4,7 +4,6 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use tauri::AppHandle;
-use reqwest;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MinecraftVersion {
@@ -43,12 +42,6 @@ pub struct ModLoader {
     pub mc_version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct DownloadSource {
-    pub name: String,
-    pub base_url: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LaunchOptions {
     pub version: String,
@@ -0,0 +1,177 @@
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// 连续失败达到该次数后，主机在冷却时间内排到最后
+const MAX_CONSECUTIVE_FAILURES: u32 = 2;
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(60);
+
+// 传输量太小的请求测不出有效速度，不计入吞吐
+const MIN_MEASURED_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Default)]
+struct HostHealth {
+    consecutive_failures: u32,
+    last_failure: Option<Instant>,
+    bytes: u64,
+    secs: f64,
+}
+
+impl HostHealth {
+    fn is_cooling_down(&self) -> bool {
+        self.consecutive_failures >= MAX_CONSECUTIVE_FAILURES
+            && self.last_failure.is_some_and(|at| at.elapsed() < FAILURE_COOLDOWN)
+    }
+
+    fn throughput(&self) -> Option<f64> {
+        (self.bytes >= MIN_MEASURED_BYTES && self.secs > 0.0).then(|| self.bytes as f64 / self.secs)
+    }
+}
+
+// 按主机记录的健康状态，整个应用运行期间共享
+static HOST_HEALTH: Lazy<Mutex<HashMap<String, HostHealth>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn host_of(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|host| match u.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }))
+        .unwrap_or_else(|| url.to_string())
+}
+
+// 文件的全部候选地址：主地址、文件级镜像，以及把主地址的包级前缀替换为其他镜像前缀得到的地址
+pub fn candidate_urls(primary: &str, file_mirrors: &[String], package_mirrors: &[String]) -> Vec<String> {
+    let mut urls = vec![primary.to_string()];
+    urls.extend(file_mirrors.iter().cloned());
+
+    if let Some(rest) = package_mirrors.iter().find_map(|prefix| primary.strip_prefix(prefix.as_str())) {
+        urls.extend(package_mirrors.iter().map(|prefix| format!("{}{}", prefix, rest)));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    urls.retain(|url| !url.is_empty() && seen.insert(url.clone()));
+    urls
+}
+
+// 按健康状态排序候选地址：冷却中的排最后；尚未测速的排在前面以便测速，
+// 已测速的按吞吐从高到低；其余保持清单中的顺序
+pub fn rank(urls: &[String]) -> Vec<String> {
+    let health = HOST_HEALTH.lock().unwrap();
+    let mut ranked: Vec<(usize, &String)> = urls.iter().enumerate().collect();
+    ranked.sort_by(|(a_index, a), (b_index, b)| {
+        let a_health = health.get(&host_of(a));
+        let b_health = health.get(&host_of(b));
+        let a_cooling = a_health.is_some_and(HostHealth::is_cooling_down);
+        let b_cooling = b_health.is_some_and(HostHealth::is_cooling_down);
+        let a_speed = a_health.and_then(HostHealth::throughput);
+        let b_speed = b_health.and_then(HostHealth::throughput);
+
+        a_cooling.cmp(&b_cooling)
+            .then_with(|| match (a_speed, b_speed) {
+                (Some(a), Some(b)) => b.total_cmp(&a),
+                (None, Some(_)) => std::cmp::Ordering::Less,
+                (Some(_), None) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            })
+            .then(a_index.cmp(b_index))
+    });
+    ranked.into_iter().map(|(_, url)| url.clone()).collect()
+}
+
+// 选出下一个要尝试的地址：优先选择本文件还没失败过的最佳镜像，全部失败过则从最佳镜像重新开始
+pub fn pick(urls: &[String], failed: &[String]) -> Option<String> {
+    let ranked = rank(urls);
+    ranked.iter()
+        .find(|url| !failed.contains(url))
+        .or_else(|| ranked.first())
+        .cloned()
+}
+
+pub fn record_success(url: &str, bytes: u64, elapsed: Duration) {
+    let mut health = HOST_HEALTH.lock().unwrap();
+    let entry = health.entry(host_of(url)).or_default();
+    entry.consecutive_failures = 0;
+    entry.last_failure = None;
+    if bytes >= MIN_MEASURED_BYTES {
+        entry.bytes += bytes;
+        entry.secs += elapsed.as_secs_f64();
+    }
+}
+
+pub fn record_failure(url: &str) {
+    let mut health = HOST_HEALTH.lock().unwrap();
+    let entry = health.entry(host_of(url)).or_default();
+    entry.consecutive_failures += 1;
+    entry.last_failure = Some(Instant::now());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 主机健康状态是全局共享的，每个测试使用各自的主机名
+    fn urls(hosts: &[&str]) -> Vec<String> {
+        hosts.iter().map(|host| format!("https://{}/pack/mods/a.jar", host)).collect()
+    }
+
+    #[test]
+    fn builds_candidates_from_package_mirrors() {
+        let package_mirrors = vec!["https://a.example/pack/".to_string(), "https://b.example/pack/".to_string()];
+        let file_mirrors = vec!["https://c.example/a.jar".to_string()];
+        assert_eq!(
+            candidate_urls("https://a.example/pack/mods/a.jar", &file_mirrors, &package_mirrors),
+            vec![
+                "https://a.example/pack/mods/a.jar".to_string(),
+                "https://c.example/a.jar".to_string(),
+                "https://b.example/pack/mods/a.jar".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_manifest_order_without_health() {
+        let candidates = urls(&["order-a.example", "order-b.example", "order-c.example"]);
+        assert_eq!(rank(&candidates), candidates);
+    }
+
+    #[test]
+    fn ranks_untested_then_fastest_then_cooling_down() {
+        let candidates = urls(&["rank-slow.example", "rank-down.example", "rank-new.example", "rank-fast.example"]);
+        record_success(&candidates[0], MIN_MEASURED_BYTES, Duration::from_secs(4));
+        record_success(&candidates[3], MIN_MEASURED_BYTES, Duration::from_secs(1));
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            record_failure(&candidates[1]);
+        }
+
+        assert_eq!(rank(&candidates), vec![
+            candidates[2].clone(),
+            candidates[3].clone(),
+            candidates[0].clone(),
+            candidates[1].clone(),
+        ]);
+    }
+
+    #[test]
+    fn success_ends_cooldown() {
+        let candidates = urls(&["recover-a.example", "recover-b.example"]);
+        for _ in 0..MAX_CONSECUTIVE_FAILURES {
+            record_failure(&candidates[0]);
+        }
+        assert_eq!(rank(&candidates)[0], candidates[1]);
+
+        record_success(&candidates[0], 0, Duration::ZERO);
+        assert_eq!(rank(&candidates), candidates);
+    }
+
+    #[test]
+    fn picks_best_mirror_not_yet_failed() {
+        let candidates = urls(&["pick-a.example", "pick-b.example"]);
+        assert_eq!(pick(&candidates, &[]), Some(candidates[0].clone()));
+        assert_eq!(pick(&candidates, &candidates[..1]), Some(candidates[1].clone()));
+        assert_eq!(pick(&candidates, &candidates), Some(candidates[0].clone()));
+        assert_eq!(pick(&[], &[]), None);
+    }
+}
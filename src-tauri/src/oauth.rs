@@ -27,7 +27,7 @@ static OAUTH_CONFIG: Lazy<OAuthConfig> = Lazy::new(|| OAuthConfig {
 });
 
 #[derive(Serialize, Deserialize, Debug)]
-struct TokenResponse {
+pub(crate) struct TokenResponse {
     access_token: String,
     refresh_token: String,
     expires_in: u64,
@@ -189,7 +189,7 @@ pub async fn validate_token(access_token: String) -> Result<UserInfo, String> {
     }
 }
 
-pub async fn exchange_code_for_token(code: String) -> Result<TokenResponse, Box<dyn std::error::Error + Send + Sync>> {
+pub(crate) async fn exchange_code_for_token(code: String) -> Result<TokenResponse, Box<dyn std::error::Error + Send + Sync>> {
     println!("[OAuth] Exchanging code for access token...");
     let client = reqwest::Client::new();
     let mut params = HashMap::new();
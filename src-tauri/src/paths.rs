@@ -0,0 +1,189 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+// 清单和压缩包里的路径都不可信，写入或删除本地文件之前统一在这里校验。
+// 恶意包统一以该前缀报错，便于界面和命令行识别。
+pub const UNSAFE_PATH_ERROR: &str = "Unsafe path in package";
+
+// 同步器自己的文件（索引、暂存区、日志、快照等）都以此开头，清单和动作不能改动
+pub const INTERNAL_PREFIX: &str = ".sync_";
+
+// 应用数据目录的名称，与 Tauri 的 app_data_dir 一致
+const APP_IDENTIFIER: &str = "com.acgstation.sync";
+
+// Windows 保留的设备名，带扩展名（如 NUL.txt）同样会被当作设备打开
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn unsafe_path(path: &str, reason: &str) -> String {
+    format!("{}: {:?} {}", UNSAFE_PATH_ERROR, path, reason)
+}
+
+fn is_reserved_name(component: &str) -> bool {
+    let stem = component.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES.iter().any(|name| name.eq_ignore_ascii_case(stem))
+}
+
+// 校验相对路径并统一为 `/` 分隔的形式。拒绝绝对路径、盘符、`..`、
+// 保留设备名，以及在 Windows 上会被截断或解释为数据流的文件名；
+// 目标目录下以 `.sync_` 开头的文件属于同步器自己，同样拒绝
+pub fn validate_relative_path(path: &str) -> Result<String, String> {
+    if path.is_empty() {
+        return Err(unsafe_path(path, "is empty"));
+    }
+    if path.contains('\0') {
+        return Err(unsafe_path(path, "contains a NUL byte"));
+    }
+    let normalized = path.replace('\\', "/");
+    if normalized.starts_with('/') {
+        return Err(unsafe_path(path, "is absolute"));
+    }
+    // 只有 Windows 会把 `:` 解释为盘符或数据流，其他系统上是文件名中的普通字符
+    let has_prefix = Path::new(&normalized).components().any(|component| matches!(component, Component::Prefix(_)));
+    if has_prefix || (cfg!(windows) && normalized.contains(':')) {
+        return Err(unsafe_path(path, "contains a drive prefix or stream name"));
+    }
+
+    let mut components = Vec::new();
+    for component in normalized.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => return Err(unsafe_path(path, "points outside the target directory")),
+            _ if is_reserved_name(component) => return Err(unsafe_path(path, "uses a reserved device name")),
+            _ if component.ends_with('.') || component.ends_with(' ') => {
+                return Err(unsafe_path(path, "has a component ending with a dot or space"));
+            }
+            _ => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(unsafe_path(path, "does not name a file"));
+    }
+    // Windows 上文件名不区分大小写
+    if components[0].to_ascii_lowercase().starts_with(INTERNAL_PREFIX) {
+        return Err(unsafe_path(path, "is managed by the sync app"));
+    }
+    Ok(components.join("/"))
+}
+
+// 校验后拼接到 `root` 下，并确认已存在的部分（包括上级目录）没有通过符号链接指向 `root` 之外
+pub fn resolve_in(root: &Path, relative: &str) -> Result<PathBuf, String> {
+    let path = root.join(validate_relative_path(relative)?);
+    let canonical_root = match fs::canonicalize(root) {
+        Ok(root) => root,
+        Err(_) => return Ok(path), // 目标目录还不存在，不可能存在链接
+    };
+
+    let existing = path.ancestors()
+        .take_while(|ancestor| ancestor.starts_with(root) && *ancestor != root)
+        .find(|ancestor| fs::symlink_metadata(ancestor).is_ok());
+    if let Some(existing) = existing {
+        let resolved = fs::canonicalize(existing)
+            .map_err(|e| format!("Failed to resolve {}: {}", existing.to_string_lossy(), e))?;
+        if !resolved.starts_with(&canonical_root) {
+            return Err(unsafe_path(relative, "escapes the target directory through a symlink"));
+        }
+    }
+    Ok(path)
+}
+
+// 图形界面和命令行共用的数据目录（信任的公钥、同步配置等），不存在时创建
+pub fn app_data_dir() -> Result<PathBuf, String> {
+    // 单元测试使用临时目录，不动真实的设置和配置
+    #[cfg(test)]
+    let data_dir = Some(std::env::temp_dir().join(format!("acgstation-tests-{}", std::process::id())));
+    #[cfg(not(test))]
+    let data_dir = dirs::data_dir();
+    let dir = data_dir.ok_or("Failed to get app data dir")?.join(APP_IDENTIFIER);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
+// 应用数据目录中的设置文件，图形界面、命令行和后台自动同步共用；不存在或无法读取时使用默认值
+pub fn load_settings<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    let loaded = app_data_dir().and_then(|dir| {
+        let path = dir.join(file_name);
+        if !path.exists() {
+            return Ok(T::default());
+        }
+        let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+    });
+    loaded.unwrap_or_else(|e| {
+        eprintln!("Failed to load {}, using defaults: {}", file_name, e);
+        T::default()
+    })
+}
+
+pub fn save_settings<T: Serialize>(file_name: &str, settings: &T) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    fs::write(app_data_dir()?.join(file_name), json).map_err(|e| format!("Failed to save {}: {}", file_name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_safe_paths() {
+        assert_eq!(validate_relative_path("mods/a.jar").unwrap(), "mods/a.jar");
+        assert_eq!(validate_relative_path("config\\sub\\b.toml").unwrap(), "config/sub/b.toml");
+        assert_eq!(validate_relative_path("./mods//c.jar").unwrap(), "mods/c.jar");
+        assert_eq!(validate_relative_path("mods/.sync_note.txt").unwrap(), "mods/.sync_note.txt");
+    }
+
+    #[test]
+    fn rejects_unsafe_paths() {
+        for path in [
+            "",
+            ".",
+            "/etc/passwd",
+            "\\\\server\\share",
+            "../outside",
+            "mods/../../outside",
+            "NUL",
+            "mods/com1.txt",
+            "mods/trailing.",
+            "mods/trailing ",
+            "a\0b",
+            ".sync_index.json",
+            ".SYNC_staging/files/a",
+        ] {
+            let error = validate_relative_path(path).unwrap_err();
+            assert!(error.starts_with(UNSAFE_PATH_ERROR), "{:?}: {}", path, error);
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn rejects_drive_prefixes_and_streams() {
+        for path in ["C:/Windows/win.ini", "C:a.txt", "mods/a.jar:stream", "mods/a.jar::$DATA"] {
+            let error = validate_relative_path(path).unwrap_err();
+            assert!(error.starts_with(UNSAFE_PATH_ERROR), "{:?}: {}", path, error);
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn allows_colons_in_file_names() {
+        assert_eq!(validate_relative_path("mods/a.jar:stream").unwrap(), "mods/a.jar:stream");
+        assert_eq!(validate_relative_path("logs\\12:30.log").unwrap(), "logs/12:30.log");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_out_of_root() {
+        let root = std::env::temp_dir().join(format!("acgstation-paths-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(root.join("mods")).unwrap();
+        std::os::unix::fs::symlink(std::env::temp_dir(), root.join("escape")).unwrap();
+
+        assert_eq!(resolve_in(&root, "mods/a.jar").unwrap(), root.join("mods/a.jar"));
+        assert!(resolve_in(&root, "escape/a.jar").is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
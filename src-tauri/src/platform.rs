@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+// 清单文件的适用条件，设置了的每一项都满足时才在本机同步该文件，例如
+// {"os": ["windows"], "arch": ["x86_64"]}、{"loader": ["fabric"], "gameVersion": ["1.20.*"]}
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct FileCondition {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<Vec<String>>,
+    #[serde(rename = "gameVersion", default, skip_serializing_if = "Option::is_none")]
+    pub game_version: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loader: Option<Vec<String>>,
+}
+
+// 运行同步的机器和目标游戏版本
+#[derive(Debug, Serialize, Clone)]
+pub struct Platform {
+    pub os: String,
+    pub arch: String,
+    #[serde(rename = "gameVersion")]
+    pub game_version: Option<String>,
+    pub loader: Option<String>,
+}
+
+// 常见别名统一为 Rust 的 OS / ARCH 常量的写法
+fn normalize_os(os: &str) -> String {
+    match os.trim().to_ascii_lowercase().as_str() {
+        "win" | "win32" | "win64" => "windows".to_string(),
+        "mac" | "osx" | "darwin" => "macos".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn normalize_arch(arch: &str) -> String {
+    match arch.trim().to_ascii_lowercase().as_str() {
+        "x64" | "amd64" => "x86_64".to_string(),
+        "arm64" => "aarch64".to_string(),
+        "x32" | "i386" | "i686" => "x86".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// `1.20.*` 匹配 1.20 及其所有小版本，其余按完整版本号比较
+fn version_matches(pattern: &str, version: &str) -> bool {
+    match pattern.trim().strip_suffix(".*") {
+        Some(prefix) => version == prefix || version.strip_prefix(prefix).is_some_and(|rest| rest.starts_with('.')),
+        None => pattern.trim() == version,
+    }
+}
+
+impl FileCondition {
+    pub fn is_empty(&self) -> bool {
+        *self == FileCondition::default()
+    }
+
+    // 游戏版本或加载器没能识别时，依赖它们的条件视为不满足
+    pub fn matches(&self, platform: &Platform) -> bool {
+        let os_ok = self.os.as_ref().is_none_or(|list| list.iter().any(|os| normalize_os(os) == platform.os));
+        let arch_ok = self.arch.as_ref().is_none_or(|list| list.iter().any(|arch| normalize_arch(arch) == platform.arch));
+        let game_ok = self.game_version.as_ref().is_none_or(|list| {
+            platform.game_version.as_deref().is_some_and(|version| list.iter().any(|pattern| version_matches(pattern, version)))
+        });
+        let loader_ok = self.loader.as_ref().is_none_or(|list| {
+            platform.loader.as_deref().is_some_and(|loader| list.iter().any(|l| l.trim().eq_ignore_ascii_case(loader)))
+        });
+        os_ok && arch_ok && game_ok && loader_ok
+    }
+}
+
+// 命令行中的写法：`os:windows,arch:x86_64`、`loader:fabric,game:1.20.*`，同一键可以出现多次
+impl std::str::FromStr for FileCondition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<FileCondition, String> {
+        let mut condition = FileCondition::default();
+        for item in text.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (key, value) = item.split_once(':')
+                .ok_or_else(|| format!("Expected KEY:VALUE in condition, got {}", item))?;
+            let list = match key.trim().to_ascii_lowercase().as_str() {
+                "os" => &mut condition.os,
+                "arch" => &mut condition.arch,
+                "game" | "gameversion" => &mut condition.game_version,
+                "loader" => &mut condition.loader,
+                _ => return Err(format!("Unknown condition key: {} (expected os, arch, game or loader)", key)),
+            };
+            list.get_or_insert_with(Vec::new).push(value.trim().to_string());
+        }
+        if condition.is_empty() {
+            return Err("Condition is empty".to_string());
+        }
+        Ok(condition)
+    }
+}
+
+impl Platform {
+    // 当前机器的系统和架构，游戏版本和加载器从目标目录的版本 json 识别
+    pub fn detect(target_dir: &Path) -> Platform {
+        let (game_version, loader) = match read_version_json(target_dir) {
+            Some(version) => (game_version_of(&version), Some(loader_of(&version))),
+            None => (None, None),
+        };
+        Platform {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            game_version,
+            loader,
+        }
+    }
+}
+
+// 同步目标一般是 .minecraft/versions/<名称>，其中的 <名称>.json 记录了版本信息
+fn read_version_json(target_dir: &Path) -> Option<Value> {
+    let name = target_dir.file_name()?.to_string_lossy().into_owned();
+    let text = fs::read_to_string(target_dir.join(format!("{}.json", name))).ok()?;
+    serde_json::from_str(&text).ok()
+}
+
+// 安装加载器后原版号在 inheritsFrom 中；启动器合并后的 json 会写 clientVersion 或 patches
+fn game_version_of(version: &Value) -> Option<String> {
+    let text = |value: &Value| value.as_str().map(str::to_string);
+    version.get("inheritsFrom").and_then(text)
+        .or_else(|| version.get("clientVersion").and_then(text))
+        .or_else(|| {
+            version.get("patches")?.as_array()?.iter()
+                .find(|patch| patch.get("id").and_then(Value::as_str) == Some("game"))
+                .and_then(|patch| patch.get("version")).and_then(text)
+        })
+        .or_else(|| version.get("id").and_then(text))
+}
+
+// 按依赖库识别加载器，没有任何加载器时为 vanilla
+fn loader_of(version: &Value) -> String {
+    let libraries: Vec<&str> = version.get("libraries").and_then(Value::as_array).into_iter().flatten()
+        .filter_map(|library| library.get("name").and_then(Value::as_str))
+        .collect();
+    let has = |prefix: &str| libraries.iter().any(|name| name.starts_with(prefix));
+    let loader = if has("net.neoforged") {
+        "neoforge"
+    } else if has("net.minecraftforge") {
+        "forge"
+    } else if has("org.quiltmc:quilt-loader") {
+        "quilt"
+    } else if has("net.fabricmc:fabric-loader") {
+        "fabric"
+    } else {
+        "vanilla"
+    };
+    loader.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn platform(game_version: Option<&str>, loader: Option<&str>) -> Platform {
+        Platform {
+            os: "windows".to_string(),
+            arch: "x86_64".to_string(),
+            game_version: game_version.map(str::to_string),
+            loader: loader.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn matches_version_patterns() {
+        assert!(version_matches("1.20.*", "1.20"));
+        assert!(version_matches("1.20.*", "1.20.1"));
+        assert!(!version_matches("1.20.*", "1.2"));
+        assert!(!version_matches("1.20.*", "1.200"));
+        assert!(!version_matches("1.20.*", "1.21"));
+        assert!(version_matches(" 1.20.1 ", "1.20.1"));
+        assert!(!version_matches("1.20", "1.20.1"));
+    }
+
+    #[test]
+    fn matches_conditions_with_aliases() {
+        let condition: FileCondition = "os:win64,arch:amd64,game:1.20.*,loader:Fabric".parse().unwrap();
+        assert!(condition.matches(&platform(Some("1.20.1"), Some("fabric"))));
+        assert!(!condition.matches(&platform(Some("1.19.4"), Some("fabric"))));
+        assert!(!condition.matches(&platform(Some("1.20.1"), Some("forge"))));
+        // 没能识别游戏版本或加载器时不满足依赖它们的条件
+        assert!(!condition.matches(&platform(None, None)));
+
+        let os_only: FileCondition = "os:mac,os:windows".parse().unwrap();
+        assert_eq!(os_only.os, Some(vec!["mac".to_string(), "windows".to_string()]));
+        assert!(os_only.matches(&platform(None, None)));
+    }
+
+    #[test]
+    fn rejects_invalid_condition_specs() {
+        assert!("".parse::<FileCondition>().is_err());
+        assert!("os".parse::<FileCondition>().is_err());
+        assert!("shader:iris".parse::<FileCondition>().is_err());
+    }
+
+    #[test]
+    fn detects_game_version_and_loader() {
+        let fabric = serde_json::json!({
+            "id": "1.20.1-fabric",
+            "inheritsFrom": "1.20.1",
+            "libraries": [{"name": "net.fabricmc:fabric-loader:0.15.0"}]
+        });
+        assert_eq!(game_version_of(&fabric).as_deref(), Some("1.20.1"));
+        assert_eq!(loader_of(&fabric), "fabric");
+
+        let merged = serde_json::json!({
+            "id": "My Pack",
+            "patches": [{"id": "game", "version": "1.19.2"}],
+            "libraries": [{"name": "net.minecraftforge:forge:43.2.0"}]
+        });
+        assert_eq!(game_version_of(&merged).as_deref(), Some("1.19.2"));
+        assert_eq!(loader_of(&merged), "forge");
+
+        let vanilla = serde_json::json!({"id": "1.21"});
+        assert_eq!(game_version_of(&vanilla).as_deref(), Some("1.21"));
+        assert_eq!(loader_of(&vanilla), "vanilla");
+    }
+}
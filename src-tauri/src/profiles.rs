@@ -0,0 +1,324 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::events::EventSink;
+use crate::history::{self, HistoryEvent, SyncTrigger};
+use crate::manifest::{self, Manifest};
+use crate::paths;
+use crate::report::SyncStatus;
+use crate::sync::{self, SyncHandle};
+use crate::transaction;
+
+// 所有同步配置保存在应用数据目录的同一个文件中
+const PROFILES_FILE_NAME: &str = "sync_profiles.json";
+
+// 读改写整个文件，同一进程内的并发修改需要串行
+static PROFILES_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// 检查到新版本后自动同步的时机，需要开启后台检查更新
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum AutoSync {
+    #[default]
+    Off,
+    // 每天在本地时间 time（HH:MM）同步
+    Scheduled { time: String },
+    // 游戏未运行且电脑空闲时同步
+    Idle,
+}
+
+// 记住的一次同步：来源、目标目录和选项，排除列表仍保存在目标目录的 .sync_exclude.json
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncProfile {
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    // 清单 URL、导出的 zip 包或 manifest.json 的路径
+    pub source: String,
+    #[serde(rename = "targetDir")]
+    pub target_dir: String,
+    // 除本地信任的公钥外额外信任的发布者公钥，例如同步市场条目中的 publicKeys
+    #[serde(rename = "trustedKeys", default)]
+    pub trusted_keys: Vec<String>,
+    // 启用的可选文件组，None 时使用目标目录中保存的选择
+    #[serde(rename = "enabledGroups", default)]
+    pub enabled_groups: Option<Vec<String>>,
+    #[serde(rename = "disableHashCheck", default)]
+    pub disable_hash_check: bool,
+    #[serde(rename = "disableSizeCheck", default)]
+    pub disable_size_check: bool,
+    #[serde(rename = "forceRescan", default)]
+    pub force_rescan: bool,
+    #[serde(rename = "autoSync", default)]
+    pub auto_sync: AutoSync,
+    // 最近一次同步成功的清单版本，由同步器维护
+    #[serde(rename = "lastSyncedVersion", default)]
+    pub last_synced_version: Option<String>,
+    #[serde(rename = "lastSyncedAt", default)]
+    pub last_synced_at: Option<String>,
+}
+
+fn profiles_path() -> Result<PathBuf, String> {
+    Ok(paths::app_data_dir()?.join(PROFILES_FILE_NAME))
+}
+
+fn load_profiles() -> Result<Vec<SyncProfile>, String> {
+    let path = profiles_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+}
+
+fn save_profiles(profiles: &[SyncProfile]) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(profiles).map_err(|e| e.to_string())?;
+    fs::write(profiles_path()?, json).map_err(|e| e.to_string())
+}
+
+fn validate(profile: &SyncProfile, profiles: &[SyncProfile]) -> Result<(), String> {
+    if profile.name.trim().is_empty() {
+        return Err("Profile name is empty".to_string());
+    }
+    if profile.source.trim().is_empty() {
+        return Err(format!("Profile {} has no source", profile.name));
+    }
+    if profile.target_dir.trim().is_empty() {
+        return Err(format!("Profile {} has no target directory", profile.name));
+    }
+    if let AutoSync::Scheduled { time } = &profile.auto_sync {
+        chrono::NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| format!("Invalid auto sync time {}, expected HH:MM", time))?;
+    }
+    if profiles.iter().any(|other| other.id != profile.id && other.name == profile.name) {
+        return Err(format!("A profile named {} already exists", profile.name));
+    }
+    Ok(())
+}
+
+pub fn list() -> Result<Vec<SyncProfile>, String> {
+    let _lock = PROFILES_LOCK.lock().unwrap();
+    load_profiles()
+}
+
+// 按 id 或名称查找
+pub fn get(id_or_name: &str) -> Result<SyncProfile, String> {
+    list()?.into_iter()
+        .find(|profile| profile.id == id_or_name || profile.name == id_or_name)
+        .ok_or_else(|| format!("Profile not found: {}", id_or_name))
+}
+
+// 新建配置，id 由同步器生成，同步记录清空
+pub fn create(mut profile: SyncProfile) -> Result<SyncProfile, String> {
+    let _lock = PROFILES_LOCK.lock().unwrap();
+    let mut profiles = load_profiles()?;
+    profile.id = uuid::Uuid::new_v4().to_string();
+    profile.last_synced_version = None;
+    profile.last_synced_at = None;
+    validate(&profile, &profiles)?;
+    profiles.push(profile.clone());
+    save_profiles(&profiles)?;
+    Ok(profile)
+}
+
+// 修改配置的来源和选项，同步记录保持不变
+pub fn update(mut profile: SyncProfile) -> Result<SyncProfile, String> {
+    let _lock = PROFILES_LOCK.lock().unwrap();
+    let mut profiles = load_profiles()?;
+    validate(&profile, &profiles)?;
+    let existing = profiles.iter_mut().find(|existing| existing.id == profile.id)
+        .ok_or_else(|| format!("Profile not found: {}", profile.id))?;
+    profile.last_synced_version = existing.last_synced_version.take();
+    profile.last_synced_at = existing.last_synced_at.take();
+    *existing = profile.clone();
+    save_profiles(&profiles)?;
+    Ok(profile)
+}
+
+pub fn delete(id: &str) -> Result<(), String> {
+    let _lock = PROFILES_LOCK.lock().unwrap();
+    let mut profiles = load_profiles()?;
+    let count = profiles.len();
+    profiles.retain(|profile| profile.id != id);
+    if profiles.len() == count {
+        return Err(format!("Profile not found: {}", id));
+    }
+    save_profiles(&profiles)
+}
+
+fn record_synced(id: &str, version: String) -> Result<(), String> {
+    let _lock = PROFILES_LOCK.lock().unwrap();
+    let mut profiles = load_profiles()?;
+    // 同步期间配置被删除时不再记录
+    let Some(profile) = profiles.iter_mut().find(|profile| profile.id == id) else { return Ok(()) };
+    profile.last_synced_version = Some(version);
+    profile.last_synced_at = Some(chrono::Utc::now().to_rfc3339());
+    save_profiles(&profiles)
+}
+
+// 启动时回滚所有配置目标目录中被中断的同步
+pub fn recover_targets() {
+    match list() {
+        Ok(profiles) => {
+            let target_dirs: Vec<&str> = profiles.iter().map(|profile| profile.target_dir.as_str()).collect();
+            transaction::recover_all(&target_dirs);
+        }
+        Err(e) => eprintln!("Failed to load sync profiles: {}", e),
+    }
+}
+
+// 按配置读取并校验清单
+pub async fn load_manifest(profile: &SyncProfile) -> Result<Manifest, String> {
+    let text = manifest::read_manifest_source(&profile.source).await?;
+    let manifest = manifest::parse_verified_manifest(&text, &profile.source, Some(profile.trusted_keys.clone()))?;
+    sync::validate_manifest_paths(&manifest.files)?;
+    Ok(manifest)
+}
+
+// 按配置和读取到的清单开始同步，成功完成后记录同步的版本；开始和结束都写入同步记录
+pub async fn run(events: Arc<dyn EventSink>, profile: SyncProfile, manifest: Manifest, trigger: SyncTrigger) -> Result<SyncHandle, String> {
+    let version = manifest.version.clone();
+    let excluded = sync::load_exclusion_list(profile.target_dir.clone())?;
+    let enabled_groups = match profile.enabled_groups.clone() {
+        Some(groups) => Some(groups),
+        None => sync::load_group_selection(profile.target_dir.clone())?,
+    };
+
+    let handle = if manifest::is_local_package(&profile.source) {
+        sync::sync_from_local_package(
+            events,
+            profile.source.clone(),
+            profile.target_dir.clone(),
+            excluded,
+            enabled_groups,
+            Some(profile.trusted_keys.clone()),
+            profile.disable_hash_check,
+            profile.disable_size_check,
+            Some(profile.force_rescan),
+        ).await?
+    } else {
+        sync::start_download(
+            events,
+            manifest,
+            profile.target_dir.clone(),
+            excluded,
+            enabled_groups,
+            profile.disable_hash_check,
+            profile.disable_size_check,
+            Some(profile.force_rescan),
+        ).await?
+    };
+
+    let SyncHandle { session_id, task } = handle;
+    history::record(&profile.id, &profile.name, HistoryEvent::SyncStarted {
+        trigger,
+        version: version.clone(),
+        session_id: session_id.clone(),
+    });
+    let task = tokio::spawn(async move {
+        let report = match task.await {
+            Ok(report) => report,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        if report.status == SyncStatus::Completed {
+            if let Err(e) = record_synced(&profile.id, version.clone()) {
+                eprintln!("Failed to record sync of profile {}: {}", profile.name, e);
+            }
+        }
+        history::record(&profile.id, &profile.name, HistoryEvent::SyncFinished {
+            trigger,
+            version,
+            session_id: report.session_id.clone(),
+            status: report.status,
+            error: (report.status != SyncStatus::Completed).then(|| report.error_message()),
+        });
+        report
+    });
+    Ok(SyncHandle { session_id, task })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试共用同一个配置文件，名称各不相同
+    fn profile(name: &str) -> SyncProfile {
+        SyncProfile {
+            id: String::new(),
+            name: format!("{}-{}", name, uuid::Uuid::new_v4()),
+            source: "https://example.com/manifest.json".to_string(),
+            target_dir: "/games/pack".to_string(),
+            trusted_keys: Vec::new(),
+            enabled_groups: None,
+            disable_hash_check: false,
+            disable_size_check: false,
+            force_rescan: false,
+            auto_sync: AutoSync::Off,
+            last_synced_version: Some("0.9".to_string()),
+            last_synced_at: None,
+        }
+    }
+
+    #[test]
+    fn creates_updates_and_finds_profiles() {
+        let created = create(profile("round-trip")).unwrap();
+        assert!(!created.id.is_empty());
+        assert_eq!(created.last_synced_version, None);
+
+        record_synced(&created.id, "1.0".to_string()).unwrap();
+        let mut changed = created.clone();
+        changed.source = "/packs/pack.zip".to_string();
+        changed.disable_hash_check = true;
+        changed.force_rescan = true;
+        changed.auto_sync = AutoSync::Scheduled { time: "04:30".to_string() };
+        let updated = update(changed).unwrap();
+        // 同步记录由同步器维护，更新配置时保留
+        assert_eq!(updated.last_synced_version.as_deref(), Some("1.0"));
+
+        let by_id = get(&created.id).unwrap();
+        let by_name = get(&created.name).unwrap();
+        for found in [&by_id, &by_name] {
+            assert_eq!(found.id, created.id);
+            assert_eq!(found.source, "/packs/pack.zip");
+            assert!(found.disable_hash_check && found.force_rescan && !found.disable_size_check);
+            assert_eq!(found.auto_sync, AutoSync::Scheduled { time: "04:30".to_string() });
+            assert_eq!(found.last_synced_version.as_deref(), Some("1.0"));
+        }
+
+        delete(&created.id).unwrap();
+        assert!(get(&created.id).is_err());
+        assert!(delete(&created.id).is_err());
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let first = create(profile("duplicate")).unwrap();
+        let mut second = profile("duplicate");
+        second.name = first.name.clone();
+        assert!(create(second).is_err());
+
+        let other = create(profile("duplicate")).unwrap();
+        let mut renamed = other.clone();
+        renamed.name = first.name.clone();
+        assert!(update(renamed).is_err());
+        // 保存自己的名称不算重复
+        assert!(update(other.clone()).is_ok());
+
+        delete(&first.id).unwrap();
+        delete(&other.id).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        let mut scheduled = profile("invalid");
+        scheduled.auto_sync = AutoSync::Scheduled { time: "25:00".to_string() };
+        assert!(create(scheduled).is_err());
+        let mut unnamed = profile("invalid");
+        unnamed.name = " ".to_string();
+        assert!(create(unnamed).is_err());
+        assert!(update(profile("missing")).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use crate::events::EventSink;
+use crate::hooks::HookResult;
+use crate::session::{self, SyncSession};
+
+// 保留最近几次同步的结果以供查询
+const MAX_KEPT_REPORTS: usize = 20;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+    // 全部文件已写入目标目录
+    Completed,
+    // 有文件失败或提交失败，目标目录保持不变
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct FailedFile {
+    pub path: String,
+    pub error: String,
+}
+
+// 一次同步的最终结果，随 SYNC_FINISHED 事件发送。
+// `succeeded` 为下载并校验通过的文件，只有 status 为 completed 时才真正写入目标目录
+#[derive(Debug, Serialize, Clone)]
+pub struct SyncReport {
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    pub status: SyncStatus,
+    pub error: Option<String>,
+    pub succeeded: Vec<String>,
+    pub failed: Vec<FailedFile>,
+    // 已是最新或被排除而未处理的文件
+    pub skipped: Vec<String>,
+    pub deleted: Vec<String>,
+    #[serde(rename = "bytesTransferred")]
+    pub bytes_transferred: u64,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    // 清单声明的同步前后动作的执行结果，按执行顺序排列
+    pub hooks: Vec<HookResult>,
+}
+
+static REPORTS: Lazy<Mutex<VecDeque<SyncReport>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+impl SyncReport {
+    pub fn new(session_id: &str, skipped: Vec<String>) -> SyncReport {
+        SyncReport {
+            session_id: session_id.to_string(),
+            status: SyncStatus::Completed,
+            error: None,
+            succeeded: Vec::new(),
+            failed: Vec::new(),
+            skipped,
+            deleted: Vec::new(),
+            bytes_transferred: 0,
+            duration_ms: 0,
+            hooks: Vec::new(),
+        }
+    }
+
+    // 记录最终状态和统计，保存结果并发送 SYNC_FINISHED；`result` 为提交时删除的文件。
+    // 会话在这里才结束，提交和同步后动作进行期间目标目录仍算作正在同步
+    pub fn finish(mut self, events: &dyn EventSink, session: &SyncSession, result: Result<Vec<String>, String>) -> SyncReport {
+        session.finish();
+        match result {
+            Ok(deleted) => self.deleted = deleted,
+            Err(e) => {
+                self.status = if e == session::CANCELLED_ERROR { SyncStatus::Cancelled } else { SyncStatus::Failed };
+                self.error = Some(e);
+            }
+        }
+        self.bytes_transferred = session.transferred();
+        self.duration_ms = session.elapsed().as_millis() as u64;
+
+        let mut reports = REPORTS.lock().unwrap();
+        reports.retain(|report| report.session_id != self.session_id);
+        if reports.len() >= MAX_KEPT_REPORTS {
+            reports.pop_front();
+        }
+        reports.push_back(self.clone());
+        drop(reports);
+
+        events.emit("SYNC_FINISHED", &self);
+        self
+    }
+
+    // 供命令行等调用方把失败原因汇总为一条消息
+    pub fn error_message(&self) -> String {
+        let mut message = self.error.clone().unwrap_or_default();
+        for file in &self.failed {
+            message.push_str(&format!("\n  {}: {}", file.path, file.error));
+        }
+        message
+    }
+}
+
+pub fn get(session_id: &str) -> Result<SyncReport, String> {
+    REPORTS.lock().unwrap()
+        .iter()
+        .find(|report| report.session_id == session_id)
+        .cloned()
+        .ok_or_else(|| format!("No result for sync session {}", session_id))
+}
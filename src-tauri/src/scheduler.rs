@@ -0,0 +1,189 @@
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::paths;
+
+const DEFAULT_MAX_CONCURRENT: usize = 8;
+// 下载限制保存在应用数据目录
+const LIMITS_FILE_NAME: &str = "download_limits.json";
+
+// 重试退避：base * 2^attempt，最大 cap，在 [0, 上限] 内取随机值（full jitter）
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_CAP: Duration = Duration::from_secs(30);
+
+// 令牌桶最多攒一秒的额度，避免空闲后瞬间突发
+const BURST_SECS: f64 = 1.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct DownloadLimits {
+    // 同时进行的下载数
+    #[serde(rename = "maxConcurrent", alias = "max_concurrent")]
+    pub max_concurrent: usize,
+    // 全局限速，字节/秒，0 表示不限
+    #[serde(rename = "globalBytesPerSec", alias = "global_bytes_per_sec", default)]
+    pub global_bytes_per_sec: u64,
+    // 单个主机限速，字节/秒，0 表示不限
+    #[serde(rename = "perHostBytesPerSec", alias = "per_host_bytes_per_sec", default)]
+    pub per_host_bytes_per_sec: u64,
+}
+
+impl Default for DownloadLimits {
+    fn default() -> Self {
+        DownloadLimits {
+            max_concurrent: DEFAULT_MAX_CONCURRENT,
+            global_bytes_per_sec: 0,
+            per_host_bytes_per_sec: 0,
+        }
+    }
+}
+
+struct RateLimiter {
+    bytes_per_sec: f64,
+    // (可用额度, 上次补充时间)，额度可以为负，表示已经透支、需要等待
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(bytes_per_sec: u64) -> RateLimiter {
+        RateLimiter {
+            bytes_per_sec: bytes_per_sec as f64,
+            state: Mutex::new((bytes_per_sec as f64 * BURST_SECS, Instant::now())),
+        }
+    }
+
+    // 扣除已传输的字节数，返回需要等待的时间
+    fn consume(&self, bytes: u64) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let refill = now.duration_since(state.1).as_secs_f64() * self.bytes_per_sec;
+        state.0 = (state.0 + refill).min(self.bytes_per_sec * BURST_SECS) - bytes as f64;
+        state.1 = now;
+        if state.0 >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.0 / self.bytes_per_sec)
+        }
+    }
+}
+
+static LIMITS: Lazy<Mutex<DownloadLimits>> = Lazy::new(|| Mutex::new(normalized(paths::load_settings(LIMITS_FILE_NAME))));
+static GLOBAL_LIMITER: Lazy<Mutex<Option<Arc<RateLimiter>>>> = Lazy::new(|| Mutex::new(global_limiter(&limits())));
+static HOST_LIMITERS: Lazy<Mutex<HashMap<String, Arc<RateLimiter>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 所有下载共用一个 Client，同一主机的连接可以复用
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(15))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .build()
+        .expect("Failed to build HTTP client")
+});
+
+pub fn client() -> &'static reqwest::Client {
+    &CLIENT
+}
+
+pub fn limits() -> DownloadLimits {
+    *LIMITS.lock().unwrap()
+}
+
+fn normalized(limits: DownloadLimits) -> DownloadLimits {
+    DownloadLimits {
+        max_concurrent: limits.max_concurrent.max(1),
+        ..limits
+    }
+}
+
+fn global_limiter(limits: &DownloadLimits) -> Option<Arc<RateLimiter>> {
+    (limits.global_bytes_per_sec > 0).then(|| Arc::new(RateLimiter::new(limits.global_bytes_per_sec)))
+}
+
+// 保存并更新限制，新的限速对之后传输的数据立即生效，并发数从下一次同步开始生效
+pub fn set_limits(limits: DownloadLimits) -> Result<(), String> {
+    let limits = normalized(limits);
+    paths::save_settings(LIMITS_FILE_NAME, &limits)?;
+    override_limits(limits);
+    Ok(())
+}
+
+// 只对当前进程生效，例如命令行参数
+pub fn override_limits(limits: DownloadLimits) {
+    let limits = normalized(limits);
+    *LIMITS.lock().unwrap() = limits;
+    *GLOBAL_LIMITER.lock().unwrap() = global_limiter(&limits);
+    HOST_LIMITERS.lock().unwrap().clear();
+}
+
+fn host_limiter(url: &str) -> Option<Arc<RateLimiter>> {
+    let per_host = limits().per_host_bytes_per_sec;
+    if per_host == 0 {
+        return None;
+    }
+    let host = url::Url::parse(url).ok()?.host_str()?.to_string();
+    Some(HOST_LIMITERS.lock().unwrap()
+        .entry(host)
+        .or_insert_with(|| Arc::new(RateLimiter::new(per_host)))
+        .clone())
+}
+
+// 每收到一段数据调用一次，超出全局或主机限速时等待
+pub async fn throttle(url: &str, bytes: u64) {
+    let global = GLOBAL_LIMITER.lock().unwrap().clone();
+    let wait = [global, host_limiter(url)]
+        .iter()
+        .flatten()
+        .map(|limiter| limiter.consume(bytes))
+        .max()
+        .unwrap_or(Duration::ZERO);
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+// 第 attempt 次（从 0 开始）失败后的等待时间
+pub fn backoff_delay(attempt: u32) -> Duration {
+    let ceiling = BACKOFF_BASE.saturating_mul(1 << attempt.min(16)).min(BACKOFF_CAP);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_burst_then_waits_for_overdraft() {
+        let limiter = RateLimiter::new(1000);
+        assert_eq!(limiter.consume(1000), Duration::ZERO);
+
+        let wait = limiter.consume(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500), "{:?}", wait);
+    }
+
+    #[test]
+    fn caps_accumulated_budget_at_burst() {
+        let limiter = RateLimiter::new(1000);
+        limiter.state.lock().unwrap().1 -= Duration::from_secs(10);
+        assert!(limiter.consume(2000) >= Duration::from_millis(900));
+    }
+
+    #[test]
+    fn bounds_backoff_delay() {
+        for attempt in 0..40 {
+            let ceiling = BACKOFF_BASE.saturating_mul(1 << attempt.min(16)).min(BACKOFF_CAP);
+            assert!(backoff_delay(attempt) <= ceiling);
+        }
+        assert!(backoff_delay(u32::MAX) <= BACKOFF_CAP);
+    }
+
+    #[test]
+    fn normalizes_zero_concurrency() {
+        let limits = normalized(DownloadLimits { max_concurrent: 0, ..DownloadLimits::default() });
+        assert_eq!(limits.max_concurrent, 1);
+        assert!(global_limiter(&limits).is_none());
+    }
+}
@@ -0,0 +1,230 @@
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+pub const CANCELLED_ERROR: &str = "Sync cancelled";
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionState {
+    Running,
+    Paused,
+    Cancelled,
+}
+
+// 一次同步的控制句柄，下载任务在检查点处响应暂停和取消
+pub struct SyncSession {
+    pub id: String,
+    target_dir: PathBuf,
+    state: watch::Sender<SessionState>,
+    // 进入提交阶段后不再响应暂停和取消，保证目标目录一致
+    committing: AtomicBool,
+    // 取消后是否保留 .part 文件以便下次续传
+    keep_partial: AtomicBool,
+    started: Instant,
+    // 本次同步实际传输的字节数（不含续传前已有的部分）
+    transferred: AtomicU64,
+}
+
+// 正在运行的同步，按 ID 查找
+static SESSIONS: Lazy<Mutex<HashMap<String, Arc<SyncSession>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+// 会登记会话的测试持有此锁依次执行，cancel_all 不会取消其他测试中的同步
+#[cfg(test)]
+pub(crate) static TEST_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+impl SyncSession {
+    pub fn start(target_dir: &Path) -> Arc<SyncSession> {
+        let session = SyncSession::detached(target_dir);
+        SESSIONS.lock().unwrap().insert(session.id.clone(), session.clone());
+        session
+    }
+
+    // 不登记的会话，不能按 ID 查找，也不受 cancel_all 影响
+    pub(crate) fn detached(target_dir: &Path) -> Arc<SyncSession> {
+        Arc::new(SyncSession {
+            id: uuid::Uuid::new_v4().to_string(),
+            target_dir: target_dir.to_path_buf(),
+            state: watch::Sender::new(SessionState::Running),
+            committing: AtomicBool::new(false),
+            keep_partial: AtomicBool::new(true),
+            started: Instant::now(),
+            transferred: AtomicU64::new(0),
+        })
+    }
+
+    pub fn state(&self) -> SessionState {
+        *self.state.borrow()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state() == SessionState::Cancelled
+    }
+
+    pub fn keep_partial(&self) -> bool {
+        self.keep_partial.load(Ordering::SeqCst)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn record_transferred(&self, bytes: u64) {
+        self.transferred.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn transferred(&self) -> u64 {
+        self.transferred.load(Ordering::Relaxed)
+    }
+
+    // 暂停时在这里等待；已取消则返回错误，调用方应尽快退出
+    pub async fn checkpoint(&self) -> Result<(), String> {
+        let mut receiver = self.state.subscribe();
+        let state = *receiver
+            .wait_for(|state| *state != SessionState::Paused)
+            .await
+            .map_err(|e| e.to_string())?;
+        if state == SessionState::Cancelled {
+            return Err(CANCELLED_ERROR.to_string());
+        }
+        Ok(())
+    }
+
+    // 取消时返回，用于打断重试前的等待
+    pub async fn cancelled(&self) {
+        let mut receiver = self.state.subscribe();
+        let _ = receiver.wait_for(|state| *state == SessionState::Cancelled).await;
+    }
+
+    // 在 spawn_blocking 线程中使用的检查点
+    pub fn checkpoint_blocking(&self) -> Result<(), String> {
+        tokio::runtime::Handle::current().block_on(self.checkpoint())
+    }
+
+    // 开始提交前调用，之后的暂停和取消请求会被拒绝；与取消互斥，不会在提交开始后被标记为取消
+    pub fn begin_commit(&self) {
+        self.state.send_if_modified(|_| {
+            self.committing.store(true, Ordering::SeqCst);
+            false
+        });
+    }
+
+    // 已进入提交阶段时返回 false
+    fn request_cancel(&self, keep_partial: bool) -> bool {
+        self.state.send_if_modified(|state| {
+            if self.committing.load(Ordering::SeqCst) {
+                return false;
+            }
+            self.keep_partial.store(keep_partial, Ordering::SeqCst);
+            *state = SessionState::Cancelled;
+            true
+        })
+    }
+
+    // 提交和同步后动作都结束（或同步失败、取消）后移除
+    pub fn finish(&self) {
+        SESSIONS.lock().unwrap().remove(&self.id);
+    }
+}
+
+// 是否有同步正在进行，等待所有同步结束时使用
+pub fn any_running() -> bool {
+    !SESSIONS.lock().unwrap().is_empty()
+}
+
+// 目标目录上是否有本进程发起的同步尚未结束（包括提交和同步后动作）
+pub fn is_running(target_dir: &Path) -> bool {
+    SESSIONS.lock().unwrap().values().any(|session| session.target_dir == target_dir)
+}
+
+fn find(session_id: &str) -> Result<Arc<SyncSession>, String> {
+    let session = SESSIONS.lock().unwrap()
+        .get(session_id)
+        .cloned()
+        .ok_or_else(|| format!("Sync session {} not found or already finished", session_id))?;
+    if session.committing.load(Ordering::SeqCst) {
+        return Err(format!("Sync session {} is already applying changes", session_id));
+    }
+    Ok(session)
+}
+
+pub fn pause(session_id: &str) -> Result<(), String> {
+    let session = find(session_id)?;
+    session.state.send_if_modified(|state| {
+        let changed = *state == SessionState::Running;
+        if changed {
+            *state = SessionState::Paused;
+        }
+        changed
+    });
+    Ok(())
+}
+
+pub fn resume(session_id: &str) -> Result<(), String> {
+    let session = find(session_id)?;
+    session.state.send_if_modified(|state| {
+        let changed = *state == SessionState::Paused;
+        if changed {
+            *state = SessionState::Running;
+        }
+        changed
+    });
+    Ok(())
+}
+
+pub fn cancel(session_id: &str, keep_partial: bool) -> Result<(), String> {
+    if !find(session_id)?.request_cancel(keep_partial) {
+        return Err(format!("Sync session {} is already applying changes", session_id));
+    }
+    Ok(())
+}
+
+// 取消所有正在进行的同步，例如退出后台自动同步时；已经开始提交的同步照常完成
+pub fn cancel_all(keep_partial: bool) {
+    for session in SESSIONS.lock().unwrap().values() {
+        session.request_cancel(keep_partial);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn controls_sessions_until_commit() {
+        let _sessions = TEST_LOCK.lock().await;
+        let target = std::env::temp_dir().join(format!("acgstation-session-{}", uuid::Uuid::new_v4()));
+        let downloading = SyncSession::start(&target);
+        pause(&downloading.id).unwrap();
+        assert_eq!(downloading.state(), SessionState::Paused);
+        let waiting = tokio::spawn({
+            let session = downloading.clone();
+            async move { session.checkpoint().await }
+        });
+        resume(&downloading.id).unwrap();
+        assert!(waiting.await.unwrap().is_ok());
+
+        let committing = SyncSession::start(&target);
+        committing.begin_commit();
+        assert!(cancel(&committing.id, false).is_err());
+        assert!(pause(&committing.id).is_err());
+
+        // 退出时取消所有同步，已经开始提交的同步不受影响
+        cancel_all(false);
+        assert_eq!(downloading.checkpoint().await.unwrap_err(), CANCELLED_ERROR);
+        assert!(!downloading.keep_partial());
+        assert_eq!(committing.state(), SessionState::Running);
+        assert!(committing.keep_partial());
+
+        assert!(is_running(&target));
+        downloading.finish();
+        committing.finish();
+        assert!(!is_running(&target));
+        assert!(cancel(&downloading.id, true).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use base64::{engine::general_purpose, Engine as _};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::paths;
+
+// 签名内嵌在清单的 signature 字段中，签名内容为去掉该字段后的规范化 JSON
+const SIGNATURE_FIELD: &str = "signature";
+const SIGNATURE_ALGORITHM: &str = "ed25519";
+
+// 每个清单来源（地址或本地路径）信任的发布者公钥，保存在应用数据目录。
+// 按来源而不是清单里自己声明的 packageName 查找，改包名不能绕过校验
+const TRUST_STORE_FILE_NAME: &str = "trusted_sources.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ManifestSignature {
+    pub algorithm: String,
+    #[serde(rename = "publicKey", alias = "public_key")]
+    pub public_key: String,
+    pub value: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureStatus {
+    // 由该来源信任的公钥签名且验证通过
+    Trusted,
+    // 签名有效，但该来源尚未配置信任的公钥
+    Untrusted,
+    // 未签名，且该来源没有配置信任的公钥
+    Unsigned,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct SignatureInfo {
+    #[serde(rename = "packageName")]
+    pub package_name: String,
+    pub status: SignatureStatus,
+    #[serde(rename = "publicKey")]
+    pub public_key: Option<String>,
+}
+
+// 对象的键按字典序排列、不含多余空白，保证签名端和验证端得到相同的字节
+fn canonicalize(value: &Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            let mut sorted = Map::new();
+            for key in keys {
+                sorted.insert(key.clone(), canonicalize(&map[key]));
+            }
+            Value::Object(sorted)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(canonicalize).collect()),
+        other => other.clone(),
+    }
+}
+
+fn signed_bytes(manifest: &Value) -> Result<Vec<u8>, String> {
+    let mut unsigned = manifest.clone();
+    if let Some(object) = unsigned.as_object_mut() {
+        object.remove(SIGNATURE_FIELD);
+    }
+    serde_json::to_vec(&canonicalize(&unsigned)).map_err(|e| e.to_string())
+}
+
+fn decode_key<const N: usize>(text: &str, what: &str) -> Result<[u8; N], String> {
+    general_purpose::STANDARD.decode(text.trim())
+        .map_err(|e| format!("Invalid {}: {}", what, e))?
+        .try_into()
+        .map_err(|_| format!("Invalid {}: expected {} bytes", what, N))
+}
+
+pub fn public_key_of(signing_key: &SigningKey) -> String {
+    general_purpose::STANDARD.encode(signing_key.verifying_key().as_bytes())
+}
+
+// 生成新的签名密钥，私钥（base64）写入 `path`，返回对应的公钥
+pub fn generate_key_file(path: &Path) -> Result<String, String> {
+    let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+    fs::write(path, general_purpose::STANDARD.encode(signing_key.to_bytes()))
+        .map_err(|e| format!("Failed to write signing key: {}", e))?;
+    Ok(public_key_of(&signing_key))
+}
+
+pub fn load_key_file(path: &Path) -> Result<SigningKey, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read signing key {}: {}", path.to_string_lossy(), e))?;
+    Ok(SigningKey::from_bytes(&decode_key(&text, "signing key")?))
+}
+
+// 给清单加上 signature 字段
+pub fn sign_manifest(manifest: &mut Value, signing_key: &SigningKey) -> Result<(), String> {
+    let signature = signing_key.sign(&signed_bytes(manifest)?);
+    let signature = ManifestSignature {
+        algorithm: SIGNATURE_ALGORITHM.to_string(),
+        public_key: public_key_of(signing_key),
+        value: general_purpose::STANDARD.encode(signature.to_bytes()),
+    };
+    manifest.as_object_mut()
+        .ok_or("Manifest is not a JSON object")?
+        .insert(SIGNATURE_FIELD.to_string(), serde_json::to_value(signature).map_err(|e| e.to_string())?);
+    Ok(())
+}
+
+// 校验清单签名。来源配置了信任公钥时必须由其中之一签名，否则拒绝；
+// 没有配置时允许未签名的清单，但带了签名就必须有效。
+pub fn verify_manifest(text: &str, trusted_keys: &[String]) -> Result<SignatureInfo, String> {
+    let manifest: Value = serde_json::from_str(text).map_err(|e| format!("Invalid manifest JSON: {}", e))?;
+    let package_name = package_name_in(&manifest);
+
+    let signature = match manifest.get(SIGNATURE_FIELD) {
+        Some(value) => serde_json::from_value::<ManifestSignature>(value.clone())
+            .map_err(|e| format!("Manifest of {} has a malformed signature: {}", package_name, e))?,
+        None if trusted_keys.is_empty() => {
+            return Ok(SignatureInfo { package_name, status: SignatureStatus::Unsigned, public_key: None });
+        }
+        None => {
+            return Err(format!(
+                "Manifest of {} is not signed, but this pack requires a signature from a trusted publisher",
+                package_name
+            ));
+        }
+    };
+
+    if signature.algorithm != SIGNATURE_ALGORITHM {
+        return Err(format!("Manifest of {} uses unsupported signature algorithm {}", package_name, signature.algorithm));
+    }
+    if !trusted_keys.is_empty() && !trusted_keys.iter().any(|key| key.trim() == signature.public_key) {
+        return Err(format!(
+            "Manifest of {} is signed by untrusted key {}, expected one of: {}",
+            package_name, signature.public_key, trusted_keys.join(", ")
+        ));
+    }
+
+    let verifying_key = VerifyingKey::from_bytes(&decode_key(&signature.public_key, "publisher key")?)
+        .map_err(|e| format!("Invalid publisher key {}: {}", signature.public_key, e))?;
+    let signature_bytes = Signature::from_bytes(&decode_key(&signature.value, "signature")?);
+    verifying_key.verify(&signed_bytes(&manifest)?, &signature_bytes)
+        .map_err(|_| format!("Manifest signature of {} is invalid, the manifest may have been tampered with", package_name))?;
+
+    let status = if trusted_keys.is_empty() { SignatureStatus::Untrusted } else { SignatureStatus::Trusted };
+    Ok(SignatureInfo { package_name, status, public_key: Some(signature.public_key) })
+}
+
+fn trust_store_path() -> Result<PathBuf, String> {
+    Ok(paths::app_data_dir()?.join(TRUST_STORE_FILE_NAME))
+}
+
+fn load_trust_store() -> Result<HashMap<String, Vec<String>>, String> {
+    let path = trust_store_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+}
+
+fn save_trust_store(store: &HashMap<String, Vec<String>>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
+    fs::write(trust_store_path()?, json).map_err(|e| e.to_string())
+}
+
+// 信任库中的键：http(s) 地址原样使用，本地路径转为绝对路径
+fn source_key(source: &str) -> String {
+    let source = source.trim();
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return source.to_string();
+    }
+    std::path::absolute(source)
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .unwrap_or_else(|_| source.to_string())
+}
+
+pub fn trusted_keys_for(source: &str) -> Result<Vec<String>, String> {
+    Ok(load_trust_store()?.remove(&source_key(source)).unwrap_or_default())
+}
+
+fn package_name_in(manifest: &Value) -> String {
+    manifest.get("packageName")
+        .or_else(|| manifest.get("package_name"))
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string()
+}
+
+pub fn set_trusted_keys(source: &str, keys: Vec<String>) -> Result<(), String> {
+    for key in &keys {
+        VerifyingKey::from_bytes(&decode_key(key, "publisher key")?)
+            .map_err(|e| format!("Invalid publisher key {}: {}", key, e))?;
+    }
+    let mut store = load_trust_store()?;
+    if keys.is_empty() {
+        store.remove(&source_key(source));
+    } else {
+        store.insert(source_key(source), keys);
+    }
+    save_trust_store(&store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_manifest(signing_key: &SigningKey) -> String {
+        let mut manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "packageName": "pack",
+            "version": "1.0",
+            "files": [{"name": "a.jar", "relativePath": "mods/a.jar", "hash": "h", "size": 1}]
+        });
+        sign_manifest(&mut manifest, signing_key).unwrap();
+        // 验证与键的顺序和格式化无关
+        serde_json::to_string_pretty(&manifest).unwrap()
+    }
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn verifies_signed_manifest() {
+        let text = signed_manifest(&key(1));
+        let public_key = public_key_of(&key(1));
+
+        let info = verify_manifest(&text, std::slice::from_ref(&public_key)).unwrap();
+        assert_eq!(info.status, SignatureStatus::Trusted);
+        assert_eq!(info.public_key.as_deref(), Some(public_key.as_str()));
+        assert_eq!(verify_manifest(&text, &[]).unwrap().status, SignatureStatus::Untrusted);
+    }
+
+    #[test]
+    fn rejects_tampered_manifest() {
+        let text = signed_manifest(&key(1)).replace("\"1.0\"", "\"1.1\"");
+        assert!(verify_manifest(&text, &[]).is_err());
+        assert!(verify_manifest(&text, &[public_key_of(&key(1))]).is_err());
+    }
+
+    #[test]
+    fn requires_a_trusted_key_when_configured() {
+        let text = signed_manifest(&key(1));
+        assert!(verify_manifest(&text, &[public_key_of(&key(2))]).is_err());
+
+        // 改包名、去掉签名都不能绕过为来源配置的公钥
+        let mut unsigned: Value = serde_json::from_str(&text).unwrap();
+        unsigned.as_object_mut().unwrap().remove(SIGNATURE_FIELD);
+        unsigned["packageName"] = Value::from("renamed");
+        let unsigned = unsigned.to_string();
+        assert!(verify_manifest(&unsigned, &[public_key_of(&key(1))]).is_err());
+        assert_eq!(verify_manifest(&unsigned, &[]).unwrap().status, SignatureStatus::Unsigned);
+    }
+
+    #[test]
+    fn keys_sources_by_url_or_absolute_path() {
+        assert_eq!(source_key(" https://example.com/manifest.json "), "https://example.com/manifest.json");
+        let absolute = source_key("packs/pack.zip");
+        assert!(Path::new(&absolute).is_absolute());
+        assert!(absolute.ends_with("packs/pack.zip"));
+    }
+}
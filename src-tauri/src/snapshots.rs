@@ -0,0 +1,303 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::paths;
+use crate::transaction::SyncTransaction;
+
+// 快照放在目标目录内，提交时备份的原文件可以直接 rename 进来
+pub const SNAPSHOTS_DIR_NAME: &str = ".sync_snapshots";
+const RECORD_FILE_NAME: &str = "snapshot.json";
+
+const DEFAULT_MAX_SNAPSHOTS: usize = 5;
+// 保留策略保存在应用数据目录
+const RETENTION_FILE_NAME: &str = "snapshot_retention.json";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct SnapshotRetention {
+    // 每个目标目录最多保留的快照数，0 表示不创建快照
+    #[serde(rename = "maxSnapshots")]
+    pub max_snapshots: usize,
+    // 快照总大小上限，字节，0 表示不限；最新的快照总是保留
+    #[serde(rename = "maxBytes", default)]
+    pub max_bytes: u64,
+}
+
+impl Default for SnapshotRetention {
+    fn default() -> Self {
+        SnapshotRetention { max_snapshots: DEFAULT_MAX_SNAPSHOTS, max_bytes: 0 }
+    }
+}
+
+// 产生快照的操作
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SnapshotReason {
+    Sync {
+        #[serde(rename = "packageName")]
+        package_name: String,
+        version: String,
+    },
+    Restore {
+        #[serde(rename = "snapshotId")]
+        snapshot_id: String,
+    },
+}
+
+// 一次提交之前的状态：被覆盖或删除的文件备份在快照的 files 目录中，新建的文件只记录路径
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub id: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    pub reason: SnapshotReason,
+    // 已备份的文件
+    pub files: Vec<String>,
+    // 本次提交新建的文件，恢复时删除
+    pub created: Vec<String>,
+    // 备份文件的总大小，字节
+    pub size: u64,
+}
+
+static RETENTION: Lazy<Mutex<SnapshotRetention>> = Lazy::new(|| Mutex::new(paths::load_settings(RETENTION_FILE_NAME)));
+
+pub fn retention() -> SnapshotRetention {
+    *RETENTION.lock().unwrap()
+}
+
+// 保存保留策略，新的策略在下一次创建快照时生效
+pub fn set_retention(retention: SnapshotRetention) -> Result<(), String> {
+    paths::save_settings(RETENTION_FILE_NAME, &retention)?;
+    override_retention(retention);
+    Ok(())
+}
+
+// 只对当前进程生效，例如命令行参数
+pub fn override_retention(retention: SnapshotRetention) {
+    *RETENTION.lock().unwrap() = retention;
+}
+
+fn snapshots_dir(target_dir: &Path) -> PathBuf {
+    target_dir.join(SNAPSHOTS_DIR_NAME)
+}
+
+// 把提交时的备份目录保存为快照，随后按保留策略清理旧快照；没有任何改动时不创建
+pub(crate) fn save(
+    target_dir: &Path,
+    backup_dir: &Path,
+    reason: SnapshotReason,
+    files: Vec<String>,
+    created: Vec<String>,
+) -> Result<Option<Snapshot>, String> {
+    let retention = retention();
+    if retention.max_snapshots == 0 || (files.is_empty() && created.is_empty()) {
+        return Ok(None);
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let dir = snapshots_dir(target_dir).join(&id);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create snapshot directory: {}", e))?;
+    let files_dir = dir.join("files");
+    if backup_dir.exists() {
+        fs::rename(backup_dir, &files_dir).map_err(|e| format!("Failed to move backups into snapshot: {}", e))?;
+    }
+
+    let size = files.iter()
+        .filter_map(|path| fs::metadata(files_dir.join(path)).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let snapshot = Snapshot {
+        id,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        reason,
+        files,
+        created,
+        size,
+    };
+    let json = serde_json::to_string_pretty(&snapshot).map_err(|e| e.to_string())?;
+    fs::write(dir.join(RECORD_FILE_NAME), json).map_err(|e| format!("Failed to write snapshot: {}", e))?;
+
+    prune(target_dir, &retention)?;
+    Ok(Some(snapshot))
+}
+
+// 目标目录的所有快照，最新的在前；记录损坏的快照忽略
+pub fn list(target_dir: &Path) -> Result<Vec<Snapshot>, String> {
+    let dir = snapshots_dir(target_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots: Vec<Snapshot> = fs::read_dir(&dir)
+        .map_err(|e| format!("Failed to read snapshots: {}", e))?
+        .filter_map(Result::ok)
+        .filter_map(|entry| fs::read_to_string(entry.path().join(RECORD_FILE_NAME)).ok())
+        .filter_map(|text| serde_json::from_str(&text).ok())
+        .collect();
+    snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+    Ok(snapshots)
+}
+
+// 恢复依赖比它新的所有快照，因此超出限制后更早的快照一并删除
+fn prune(target_dir: &Path, retention: &SnapshotRetention) -> Result<(), String> {
+    let mut total = 0;
+    let mut exceeded = false;
+    for (index, snapshot) in list(target_dir)?.iter().enumerate() {
+        total += snapshot.size;
+        exceeded = exceeded || (index > 0 && (index >= retention.max_snapshots || (retention.max_bytes > 0 && total > retention.max_bytes)));
+        if exceeded {
+            fs::remove_dir_all(snapshots_dir(target_dir).join(&snapshot.id))
+                .map_err(|e| format!("Failed to remove snapshot {}: {}", snapshot.id, e))?;
+        }
+    }
+    Ok(())
+}
+
+// 把目标目录恢复到某个快照创建之前的状态，返回被改动的文件。
+// 恢复本身也作为一次提交，会生成新的快照，可以再撤销
+pub fn restore(target_dir: &Path, snapshot_id: &str) -> Result<Vec<String>, String> {
+    let snapshots = list(target_dir)?;
+    let index = snapshots.iter().position(|snapshot| snapshot.id == snapshot_id)
+        .ok_or_else(|| format!("Snapshot not found: {}", snapshot_id))?;
+
+    // 从该快照到最新的快照依次查看，每个路径第一次被改动之前的状态就是要恢复的状态；None 表示当时不存在
+    let mut sources: BTreeMap<&str, Option<PathBuf>> = BTreeMap::new();
+    for snapshot in snapshots[..=index].iter().rev() {
+        let files_dir = snapshots_dir(target_dir).join(&snapshot.id).join("files");
+        for path in &snapshot.files {
+            sources.entry(path).or_insert_with(|| Some(files_dir.join(path)));
+        }
+        for path in &snapshot.created {
+            sources.entry(path).or_insert(None);
+        }
+    }
+
+    let mut transaction = SyncTransaction::begin(target_dir, SnapshotReason::Restore { snapshot_id: snapshot_id.to_string() })?;
+    let mut changed = Vec::new();
+    let staged = (|| -> Result<(), String> {
+        for (path, source) in &sources {
+            let live_path = paths::resolve_in(target_dir, &paths::validate_relative_path(path)?)?;
+            match source {
+                Some(source) => {
+                    let staged_path = transaction.staged_path(path);
+                    if let Some(parent) = staged_path.parent() {
+                        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                    }
+                    fs::copy(source, &staged_path).map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+                    transaction.stage_replace(path.to_string(), &staged_path);
+                }
+                None if live_path.exists() => {
+                    transaction.stage_delete(path.to_string());
+                }
+                None => continue,
+            }
+            changed.push(path.to_string());
+        }
+        Ok(())
+    })();
+
+    match staged {
+        Ok(()) => transaction.commit()?,
+        Err(e) => {
+            transaction.abort(false);
+            return Err(e);
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_target() -> PathBuf {
+        let target = std::env::temp_dir().join(format!("acgstation-snapshots-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&target).unwrap();
+        target
+    }
+
+    // 像同步一样提交一次改动，`None` 表示删除；返回本次生成的快照
+    fn commit(target: &Path, changes: &[(&str, Option<&str>)]) -> Snapshot {
+        let reason = SnapshotReason::Sync { package_name: "pack".to_string(), version: "1.0".to_string() };
+        let mut transaction = SyncTransaction::begin(target, reason).unwrap();
+        for (path, content) in changes {
+            match content {
+                Some(content) => {
+                    let staged = transaction.staged_path(path);
+                    fs::write(&staged, content).unwrap();
+                    transaction.stage_replace(path.to_string(), &staged);
+                }
+                None => {
+                    transaction.stage_delete(path.to_string());
+                }
+            }
+        }
+        transaction.commit().unwrap();
+        list(target).unwrap().remove(0)
+    }
+
+    fn read(target: &Path, path: &str) -> Option<String> {
+        fs::read_to_string(target.join(path)).ok()
+    }
+
+    #[test]
+    fn restores_the_state_before_a_snapshot() {
+        let target = temp_target();
+        fs::write(target.join("a.txt"), "a1").unwrap();
+        fs::write(target.join("b.txt"), "b1").unwrap();
+
+        let first = commit(&target, &[("a.txt", Some("a2")), ("c.txt", Some("c2"))]);
+        assert_eq!(first.files, ["a.txt"]);
+        assert_eq!(first.created, ["c.txt"]);
+        assert_eq!(first.size, 2);
+        let second = commit(&target, &[("a.txt", Some("a3")), ("b.txt", None)]);
+        assert_eq!(list(&target).unwrap().iter().map(|s| &s.id).collect::<Vec<_>>(), [&second.id, &first.id]);
+
+        // 恢复到第二次提交之前
+        let mut changed = restore(&target, &second.id).unwrap();
+        changed.sort();
+        assert_eq!(changed, ["a.txt", "b.txt"]);
+        assert_eq!(read(&target, "a.txt").as_deref(), Some("a2"));
+        assert_eq!(read(&target, "b.txt").as_deref(), Some("b1"));
+
+        // 恢复到第一次提交之前，需要跨过之后的所有快照（包括刚才恢复产生的）
+        restore(&target, &first.id).unwrap();
+        assert_eq!(read(&target, "a.txt").as_deref(), Some("a1"));
+        assert_eq!(read(&target, "b.txt").as_deref(), Some("b1"));
+        assert_eq!(read(&target, "c.txt"), None);
+
+        // 恢复本身也生成快照，可以撤销
+        let undo = list(&target).unwrap().remove(0);
+        assert!(matches!(&undo.reason, SnapshotReason::Restore { snapshot_id } if snapshot_id == &first.id));
+        restore(&target, &undo.id).unwrap();
+        assert_eq!(read(&target, "a.txt").as_deref(), Some("a2"));
+        assert_eq!(read(&target, "c.txt").as_deref(), Some("c2"));
+        assert!(restore(&target, "missing").is_err());
+        let _ = fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn prunes_old_snapshots_by_count_and_size() {
+        let target = temp_target();
+        fs::write(target.join("a.txt"), "0").unwrap();
+        let ids: Vec<String> = ["1", "22", "333", "4444"].iter()
+            .map(|content| commit(&target, &[("a.txt", Some(content))]).id)
+            .collect();
+        let kept = || list(&target).unwrap().into_iter().map(|s| s.id).collect::<Vec<_>>();
+
+        prune(&target, &SnapshotRetention { max_snapshots: 3, max_bytes: 0 }).unwrap();
+        assert_eq!(kept(), [ids[3].clone(), ids[2].clone(), ids[1].clone()]);
+
+        // 备份大小依次为 3、2 字节，超过 4 字节后更早的快照一并删除
+        prune(&target, &SnapshotRetention { max_snapshots: 3, max_bytes: 4 }).unwrap();
+        assert_eq!(kept(), [ids[3].clone()]);
+
+        // 最新的快照即使超过大小上限也保留
+        prune(&target, &SnapshotRetention { max_snapshots: 1, max_bytes: 1 }).unwrap();
+        assert_eq!(kept(), [ids[3].clone()]);
+        assert!(!target.join(SNAPSHOTS_DIR_NAME).join(&ids[0]).exists());
+        let _ = fs::remove_dir_all(&target);
+    }
+}
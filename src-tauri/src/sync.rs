@@ -0,0 +1,1590 @@
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use walkdir::WalkDir;
+use zip::ZipArchive;
+
+use crate::events::EventSink;
+use crate::exclusions::Exclusions;
+use crate::hash_index::{self, calculate_file_hash};
+use crate::hooks::{self, HookContext, HookPhase, HookResult};
+use crate::manifest::{self, FilePatch, Manifest, ManifestFile};
+use crate::platform::Platform;
+use crate::report::{FailedFile, SyncReport};
+use crate::session::{self, SyncSession};
+use crate::snapshots::SnapshotReason;
+use crate::transaction::{self, SyncTransaction};
+use crate::{chunking, delta, mirrors, paths, scheduler};
+
+#[derive(Clone, serde::Serialize)]
+struct DownloadProgress {
+    file: String,
+    total: u64,
+    downloaded: u64,
+    progress: f32,
+}
+
+// 后台进行中的同步：图形界面只把会话 ID 交给前端，命令行等待 task 得到最终结果
+pub struct SyncHandle {
+    pub session_id: String,
+    pub task: tokio::task::JoinHandle<SyncReport>,
+}
+
+impl SyncHandle {
+    pub async fn wait(self) -> Result<SyncReport, String> {
+        self.task.await.map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Clone, serde::Serialize, Debug, PartialEq)]
+pub enum FileStatus {
+    Unchanged,
+    New,
+    Modified,
+    Extra,
+    Excluded,
+    ForceUpdate, // For zips
+}
+
+#[derive(Clone, serde::Serialize, Debug)]
+pub struct DiffFile {
+    pub path: String,
+    pub status: FileStatus,
+}
+
+pub fn calculate_diff(
+    mut manifest: Manifest, 
+    target_dir: String, 
+    excluded_files: Vec<String>,
+    enabled_groups: Option<Vec<String>>,
+    override_disable_hash_check: bool,
+    override_disable_size_check: bool,
+    force_rescan: Option<bool>,
+) -> Result<Vec<DiffFile>, String> {
+    let disable_hash_check = override_disable_hash_check || manifest.disable_hash_check.unwrap_or(false);
+    let disable_size_check = override_disable_size_check || manifest.disable_size_check.unwrap_or(false);
+
+    validate_manifest_paths(&manifest.files)?;
+    let inactive = split_inactive_files(&mut manifest, &target_dir, enabled_groups.as_deref());
+
+    // 先回滚上次被中断的同步，否则差异会基于半更新的目录计算
+    transaction::recover(Path::new(&target_dir))?;
+
+    let top_level_dirs: HashSet<String> = manifest.files.iter()
+        .filter_map(|file| {
+            Path::new(&file.relative_path).components().next().and_then(|comp| {
+                if let Component::Normal(dir) = comp {
+                    Some(dir.to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+
+    let scan_dirs: Vec<PathBuf> = top_level_dirs.iter()
+        .map(|dir| Path::new(&target_dir).join(dir))
+        .collect();
+
+    let local_files: HashMap<PathBuf, String> = scan_local_files(&target_dir, &scan_dirs, force_rescan.unwrap_or(false));
+    let mut diff_files = Vec::new();
+
+    let exclusions = Exclusions::new(Path::new(&target_dir), &excluded_files)?;
+
+    let manifest_diff: Vec<DiffFile> = manifest.files.par_iter()
+        .map(|file| {
+        let path_str = file.relative_path.clone();
+        if exclusions.is_excluded(&path_str, false) {
+                return DiffFile { path: path_str, status: FileStatus::Excluded };
+        }
+
+        if file.file_type == "zip" || file.file_type == "update_package" {
+                return DiffFile { path: path_str, status: FileStatus::ForceUpdate };
+        }
+
+        let local_path = std::path::Path::new(&target_dir).join(&path_str);
+        match local_files.get(&local_path) {
+                Some(local_hash) if !disable_hash_check && local_hash == &file.hash => {
+                    DiffFile { path: path_str, status: FileStatus::Unchanged }
+                }
+                Some(_local_hash) => {
+                    if disable_hash_check {
+                        if disable_size_check {
+                             // Both checks disabled, file exists, so unchanged
+                            return DiffFile { path: path_str, status: FileStatus::Unchanged };
+                        } else {
+                            // Hash check disabled, size check enabled
+                            if let Ok(metadata) = fs::metadata(&local_path) {
+                                if metadata.len() == file.size {
+                                    return DiffFile { path: path_str, status: FileStatus::Unchanged };
+                                }
+                            }
+                        }
+                    }
+                    // A check failed
+                    DiffFile { path: path_str, status: FileStatus::Modified }
+            }
+            None => {
+                    DiffFile { path: path_str, status: FileStatus::New }
+            }
+        }
+        })
+        .collect();
+
+    diff_files.extend(manifest_diff);
+
+    // Find extra local files
+    let manifest_paths: HashSet<_> = manifest.files.par_iter().map(|f| std::path::Path::new(&target_dir).join(&f.relative_path)).collect();
+    
+    let extra_files: Vec<DiffFile> = local_files.par_iter()
+        .filter_map(|(local_path, _)| {
+            if !manifest_paths.contains(local_path) && !inactive.is_unmanaged(Path::new(&target_dir), local_path) {
+             if let Ok(rel_path) = local_path.strip_prefix(&target_dir) {
+                    let rel_path_str = rel_path.to_string_lossy().replace('\\', "/");
+                if !exclusions.is_excluded(&rel_path_str, false) {
+                        return Some(DiffFile { path: rel_path_str, status: FileStatus::Extra });
+                }
+            }
+        }
+            None
+        })
+        .collect();
+
+    diff_files.extend(extra_files);
+
+    // 未启用组中已安装的文件同样会被删除
+    let reported: HashSet<String> = diff_files.iter().map(|f| f.path.clone()).collect();
+    for path in disabled_group_files(&target_dir, &inactive, &manifest.files, &exclusions)? {
+        if let Ok(rel_path) = path.strip_prefix(&target_dir) {
+            let rel_path_str = rel_path.to_string_lossy().replace('\\', "/");
+            if !reported.contains(&rel_path_str) {
+                diff_files.push(DiffFile { path: rel_path_str, status: FileStatus::Extra });
+            }
+        }
+    }
+
+    Ok(diff_files)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn sync_from_local_package(
+    events: Arc<dyn EventSink>,
+    zip_path: String,
+    target_dir: String,
+    excluded_files: Vec<String>,
+    enabled_groups: Option<Vec<String>>,
+    trusted_keys: Option<Vec<String>>,
+    override_disable_hash_check: bool,
+    override_disable_size_check: bool,
+    force_rescan: Option<bool>,
+) -> Result<SyncHandle, String> {
+    let manifest_str = manifest::read_manifest_text_from_zip(Path::new(&zip_path))?;
+    let mut manifest = manifest::parse_verified_manifest(&manifest_str, &zip_path, trusted_keys)?;
+    validate_manifest_paths(&manifest.files)?;
+    let inactive = split_inactive_files(&mut manifest, &target_dir, enabled_groups.as_deref());
+    let exclusions = Exclusions::new(Path::new(&target_dir), &excluded_files)?;
+    
+    let transaction = SyncTransaction::begin(Path::new(&target_dir), SnapshotReason::Sync {
+        package_name: manifest.package_name.clone(),
+        version: manifest.version.clone(),
+    })?;
+
+    // 与下载同步一样按哈希（或大小）比较，新增和修改过的文件都从包内安装
+    let disable_hash_check = override_disable_hash_check || manifest.disable_hash_check.unwrap_or(false);
+    let disable_size_check = override_disable_size_check || manifest.disable_size_check.unwrap_or(false);
+    let local_files = scan_local_files(&target_dir, &get_scan_dirs(&manifest, &target_dir), force_rescan.unwrap_or(false));
+
+    let files_to_process = manifest.files.clone();
+    let mut files_to_install = Vec::new();
+    let mut skipped = Vec::new();
+
+    for file in files_to_process {
+        // 压缩包总是重新解压
+        if file.file_type == "zip" || file.file_type == "update_package" {
+            files_to_install.push(file);
+            continue;
+        }
+        if exclusions.is_excluded(&file.relative_path, false) {
+            skipped.push(file.relative_path);
+            continue;
+        }
+        let local_path = paths::resolve_in(Path::new(&target_dir), &file.relative_path)?;
+        let unchanged = match local_files.get(&local_path) {
+            Some(local_hash) if !disable_hash_check => local_hash == &file.hash,
+            Some(_) => disable_size_check || fs::metadata(&local_path).is_ok_and(|metadata| metadata.len() == file.size),
+            None => false,
+        };
+        if unchanged {
+            skipped.push(file.relative_path);
+        } else {
+            files_to_install.push(file);
+        }
+    }
+
+    let session = SyncSession::start(Path::new(&target_dir));
+    let mut report = SyncReport::new(&session.id, skipped);
+
+    let total_files = files_to_install.len();
+    if total_files == 0 {
+        session.begin_commit();
+        let session_id = session.id.clone();
+        // 同步动作可能运行外部程序，放到后台执行
+        let task = tokio::task::spawn_blocking(move || {
+            let result = commit_with_hooks(&*events, &target_dir, &manifest, &exclusions, &mut report.hooks, transaction, |transaction| {
+                commit_sync(transaction, &target_dir, &[], &manifest.files, &inactive, &exclusions)
+            });
+            events.emit("OVERALL_PROGRESS", 100.0);
+            report.finish(&*events, &session, result)
+        });
+        return Ok(SyncHandle { session_id, task });
+    }
+    
+    let session_id = session.id.clone();
+
+    // 在后台解压，先把会话 ID 返回给调用方以便暂停或取消
+    let task = tokio::task::spawn_blocking(move || {
+        let staged = (|| -> Result<(), String> {
+            let zip_file = File::open(&zip_path).map_err(|e| e.to_string())?;
+            let mut archive = zip::ZipArchive::new(zip_file).map_err(|e| e.to_string())?;
+
+            for file_to_install in &files_to_install {
+                session.checkpoint_blocking()?;
+                let installed = (|| -> Result<u64, String> {
+                    let staged_path = transaction.staged_path(&file_to_install.relative_path);
+                    if let Some(parent) = staged_path.parent() {
+                        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+                    }
+
+                    // 与下载一样先写入 .part，校验后再暂存或解压
+                    let part_path = part_path_for(&staged_path);
+                    let mut dest_file = File::create(&part_path).map_err(|e| e.to_string())?;
+                    let written = if file_to_install.file_type == "chunked" {
+                        // 分块文件按块列表从 chunks/ 中依次拼接
+                        let mut written = 0;
+                        for chunk in file_to_install.chunks.as_deref().unwrap_or_default() {
+                            let mut chunk_entry = archive.by_name(&format!("chunks/{}", chunking::chunk_object_path(&chunk.hash)))
+                                .map_err(|e| format!("Chunk {} of {} missing: {}", chunk.hash, file_to_install.name, e))?;
+                            written += std::io::copy(&mut chunk_entry, &mut dest_file).map_err(|e| e.to_string())?;
+                        }
+                        written
+                    } else if let Some(parts) = &file_to_install.parts {
+                        // 分卷压缩包按顺序拼接各个分卷
+                        let mut written = 0;
+                        for index in 0..parts.len() {
+                            let part_name = format!("{}{}", file_to_install.relative_path, manifest::part_suffix(index));
+                            let mut part_entry = archive.by_name(&part_name).map_err(|e| archive_entry_error(&part_name, e))?;
+                            written += std::io::copy(&mut part_entry, &mut dest_file).map_err(|e| e.to_string())?;
+                        }
+                        written
+                    } else {
+                        let mut zip_file_entry = archive.by_name(&file_to_install.relative_path)
+                            .map_err(|e| archive_entry_error(&file_to_install.relative_path, e))?;
+                        std::io::copy(&mut zip_file_entry, &mut dest_file).map_err(|e| e.to_string())?
+                    };
+                    drop(dest_file);
+
+                    let extract_dir = transaction.extract_dir(&archive_folder_name(file_to_install));
+                    verify_and_unzip(file_to_install, &part_path, &staged_path, &extract_dir, &exclusions)?;
+                    Ok(written)
+                })();
+                match installed {
+                    Ok(written) => {
+                        session.record_transferred(written);
+                        report.succeeded.push(file_to_install.relative_path.clone());
+                    }
+                    Err(e) => {
+                        report.failed.push(FailedFile { path: file_to_install.relative_path.clone(), error: e.clone() });
+                        return Err(e);
+                    }
+                }
+
+                let progress = (report.succeeded.len() as f32 / total_files as f32) * 100.0;
+                events.emit("OVERALL_PROGRESS", progress);
+                events.emit("DOWNLOAD_SUCCESS", &file_to_install.name);
+            }
+            Ok(())
+        })();
+
+        let result = match staged {
+            Ok(()) => {
+                session.begin_commit();
+                commit_with_hooks(&*events, &target_dir, &manifest, &exclusions, &mut report.hooks, transaction, |transaction| {
+                    commit_sync(transaction, &target_dir, &files_to_install, &manifest.files, &inactive, &exclusions)
+                })
+            }
+            Err(e) => {
+                transaction.abort(session.keep_partial());
+                Err(e)
+            }
+        };
+        if session.is_cancelled() {
+            events.emit("SYNC_CANCELLED", &session.id);
+        } else if let Err(e) = &result {
+            events.emit("DOWNLOAD_ERROR", format!("Failed to install from local package: {}", e));
+        }
+        report.finish(&*events, &session, result)
+    });
+
+    Ok(SyncHandle { session_id, task })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn start_download(
+    events: Arc<dyn EventSink>,
+    mut manifest: Manifest,
+    target_dir: String,
+    excluded_files: Vec<String>,
+    enabled_groups: Option<Vec<String>>,
+    override_disable_hash_check: bool,
+    override_disable_size_check: bool,
+    force_rescan: Option<bool>,
+) -> Result<SyncHandle, String> {
+    let disable_hash_check = override_disable_hash_check || manifest.disable_hash_check.unwrap_or(false);
+    let disable_size_check = override_disable_size_check || manifest.disable_size_check.unwrap_or(false);
+
+    validate_manifest_paths(&manifest.files)?;
+    let inactive = split_inactive_files(&mut manifest, &target_dir, enabled_groups.as_deref());
+
+    // Step 1: Determine top-level directories and scan only those
+    let top_level_dirs: HashSet<String> = manifest.files.iter()
+        .filter_map(|file| {
+            Path::new(&file.relative_path).components().next().and_then(|comp| {
+                if let Component::Normal(dir) = comp {
+                    Some(dir.to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            })
+        })
+        .collect();
+
+    let scan_dirs: Vec<PathBuf> = top_level_dirs.iter()
+        .map(|dir| Path::new(&target_dir).join(dir))
+        .collect();
+
+    let exclusions = Arc::new(Exclusions::new(Path::new(&target_dir), &excluded_files)?);
+    let transaction = SyncTransaction::begin(Path::new(&target_dir), SnapshotReason::Sync {
+        package_name: manifest.package_name.clone(),
+        version: manifest.version.clone(),
+    })?;
+
+    let local_files = scan_local_files(&target_dir, &scan_dirs, force_rescan.unwrap_or(false));
+
+    let files_to_process = manifest.files.clone(); // Clone for modification
+    let mut files_to_download = Vec::new();
+    let mut skipped = Vec::new();
+    let mut patch_plan: HashMap<String, FilePatch> = HashMap::new();
+
+    for file in files_to_process {
+        // Always download and extract zip/update_packages
+        if file.file_type == "zip" || file.file_type == "update_package" {
+            files_to_download.push(file);
+            continue;
+        }
+        // 被排除的文件保持本地现状；压缩包内被排除的条目在解压时跳过
+        if exclusions.is_excluded(&file.relative_path, false) {
+            skipped.push(file.relative_path);
+            continue;
+        }
+
+        let local_path = paths::resolve_in(Path::new(&target_dir), &file.relative_path)?;
+        match local_files.get(&local_path) {
+            Some(local_hash) if !disable_hash_check && local_hash == &file.hash => {
+                // File exists and hash matches, skip
+                skipped.push(file.relative_path);
+                continue;
+            }
+            Some(_) if disable_hash_check => {
+                if disable_size_check {
+                    // Both checks disabled, file exists, skip
+                    skipped.push(file.relative_path);
+                    continue;
+                } else {
+                    if let Ok(metadata) = fs::metadata(&local_path) {
+                        if metadata.len() == file.size {
+                            // Size matches, skip
+                            skipped.push(file.relative_path);
+                            continue;
+                        }
+                    }
+                }
+            }
+            local_hash => {
+                // File is new, modified, or needs download
+                if let Some(patch) = local_hash.and_then(|hash| find_patch(&file, hash)) {
+                    patch_plan.insert(file.relative_path.clone(), patch);
+                }
+                files_to_download.push(file);
+            }
+        }
+    }
+
+    let session = SyncSession::start(Path::new(&target_dir));
+    let session_id = session.id.clone();
+    let mut report = SyncReport::new(&session.id, skipped);
+
+    let total_files = files_to_download.len();
+    if total_files == 0 {
+        // Nothing to download, but we still need to clean up
+        session.begin_commit();
+        let task = tokio::task::spawn_blocking(move || {
+            let result = commit_with_hooks(&*events, &target_dir, &manifest, &exclusions, &mut report.hooks, transaction, |transaction| {
+                commit_sync(transaction, &target_dir, &[], &manifest.files, &inactive, &exclusions)
+            });
+            events.emit("OVERALL_PROGRESS", 100.0);
+            report.finish(&*events, &session, result)
+        });
+        return Ok(SyncHandle { session_id, task });
+    }
+
+    // 分块文件：把本地旧版本切块，没变化的块直接复用
+    let chunk_sources = if files_to_download.iter().any(|file| file.file_type == "chunked") {
+        let candidates: Vec<(PathBuf, String)> = files_to_download.iter()
+            .filter(|file| file.file_type == "chunked")
+            .filter_map(|file| {
+                let local_path = Path::new(&target_dir).join(&file.relative_path);
+                local_files.get(&local_path).map(|hash| (local_path, hash.clone()))
+            })
+            .collect();
+        let chunk_target_dir = PathBuf::from(&target_dir);
+        tokio::task::spawn_blocking(move || chunking::build_local_chunk_sources(&chunk_target_dir, &candidates))
+            .await
+            .map_err(|e| e.to_string())?
+    } else {
+        HashMap::new()
+    };
+    let chunk_sources = Arc::new(chunk_sources);
+    let package_mirrors = Arc::new(manifest.mirrors.clone().unwrap_or_default());
+
+    let completed_files = Arc::new(Mutex::new(0));
+    let mut handles = Vec::with_capacity(total_files);
+    // 任务可以一次性创建，但同时进行的下载数受信号量限制
+    let download_slots = Arc::new(tokio::sync::Semaphore::new(scheduler::limits().max_concurrent));
+
+    for file in files_to_download.iter().cloned() {
+        let events_clone = events.clone();
+        let completed_files_clone = completed_files.clone();
+        // 所有内容先落到暂存区，全部校验通过后再统一提交
+        let staged_path = transaction.staged_path(&file.relative_path);
+        let extract_dir = transaction.extract_dir(&archive_folder_name(&file));
+        let local_path = Path::new(&target_dir).join(&file.relative_path);
+        let patch = patch_plan.remove(&file.relative_path);
+        let chunk_sources = chunk_sources.clone();
+        let package_mirrors = package_mirrors.clone();
+        let candidates = mirrors::candidate_urls(&file.download_url, file.mirrors.as_deref().unwrap_or_default(), &package_mirrors);
+        let download_slots = download_slots.clone();
+        let session = session.clone();
+        let exclusions = exclusions.clone();
+        
+        handles.push(tokio::spawn(async move {
+            let path = staged_path.as_path();
+            if let Some(parent) = path.parent() {
+                if !parent.exists() {
+                    if let Err(e) = fs::create_dir_all(parent) {
+                        let message = format!("Failed to create directory for {}: {}", file.name, e);
+                        events_clone.emit("DOWNLOAD_ERROR", &message);
+                        return Err(message);
+                    }
+                }
+            }
+
+            // 本地文件正好是补丁的来源版本时先尝试补丁，失败再回退到完整下载
+            let mut staged = false;
+            if let Some(patch) = &patch {
+                let _slot = download_slots.acquire().await.expect("download semaphore closed");
+                session.checkpoint().await?;
+                match download_and_apply_patch(&*events_clone, &session, &file, patch, &package_mirrors, &local_path, path).await {
+                    Ok(()) => staged = true,
+                    Err(e) => eprintln!("Patch for {} failed, falling back to full download: {}", file.name, e),
+                }
+            }
+
+            // 每个镜像至少有一次机会，失败后换到下一个镜像重试
+            let max_retries = candidates.len().max(3);
+            let mut failed_urls: Vec<String> = Vec::new();
+            let mut last_error = None;
+            for attempt in 0..max_retries {
+                if staged {
+                    break;
+                }
+                if attempt > 0 {
+                    tokio::select! {
+                        _ = tokio::time::sleep(scheduler::backoff_delay(attempt as u32 - 1)) => {}
+                        _ = session.cancelled() => {}
+                    }
+                }
+
+                let url = match mirrors::pick(&candidates, &failed_urls) {
+                    Some(url) => url,
+                    None => break,
+                };
+                let _slot = download_slots.acquire().await.expect("download semaphore closed");
+                session.checkpoint().await?;
+                let downloaded = if file.file_type == "chunked" {
+                    download_chunked_file(&*events_clone, &session, &file, &candidates, &chunk_sources, path).await
+                } else if file.parts.is_some() {
+                    download_parts(&*events_clone, &session, &file, &url, path).await
+                } else {
+                    download_file(&*events_clone, &session, &file, &url, path).await.map_err(|e| e.to_string())
+                };
+                if session.is_cancelled() {
+                    return Err(session::CANCELLED_ERROR.to_string());
+                }
+
+                match downloaded {
+                    Ok(part_path) => {
+                        // After download, verify and unzip
+                        if let Err(e) = verify_and_unzip(&file, &part_path, path, &extract_dir, &exclusions) {
+                            // 压缩包内容本身不安全，换镜像重试也没有意义
+                            if e.starts_with(paths::UNSAFE_PATH_ERROR) {
+                                events_clone.emit("DOWNLOAD_ERROR", format!("Refusing to install {}: {}", file.name, e));
+                                return Err(e);
+                            }
+                            eprintln!("Verification/Unzip failed for {} from {}: {}. Attempt {}/{}", file.name, url, e, attempt + 1, max_retries);
+                            mirrors::record_failure(&url);
+                            failed_urls.push(url);
+                            if attempt == max_retries - 1 {
+                                events_clone.emit("DOWNLOAD_ERROR", e.clone());
+                            }
+                            last_error = Some(e);
+                            continue;
+                        }
+                        staged = true;
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to download {} from {}: {}. Attempt {}/{}", file.name, url, e, attempt + 1, max_retries);
+                        mirrors::record_failure(&url);
+                        failed_urls.push(url);
+                        let message = format!("Failed to download {}: {}", file.name, e);
+                        if attempt == max_retries - 1 {
+                            events_clone.emit("DOWNLOAD_ERROR", &message);
+                        }
+                        last_error = Some(message);
+                    }
+                }
+            }
+
+            if !staged {
+                return Err(last_error.unwrap_or_else(|| format!("No download source left for {}", file.name)));
+            }
+
+            events_clone.emit("DOWNLOAD_SUCCESS", &file.name);
+
+            let mut completed_count = completed_files_clone.lock().unwrap();
+            *completed_count += 1;
+            
+            let progress = (*completed_count as f32 / total_files as f32) * 100.0;
+            events_clone.emit("OVERALL_PROGRESS", progress);
+
+            Ok(())
+        }));
+    }
+
+    // After every file has been staged, commit them together or leave the target untouched
+    let task = tokio::spawn(async move {
+        let results = futures_util::future::join_all(handles).await;
+        for (file, result) in files_to_download.iter().zip(results) {
+            match result.map_err(|e| e.to_string()).and_then(|r| r) {
+                Ok(()) => report.succeeded.push(file.relative_path.clone()),
+                // 因取消而中止的文件既不算成功也不算失败
+                Err(e) if e == session::CANCELLED_ERROR => {}
+                Err(e) => report.failed.push(FailedFile { path: file.relative_path.clone(), error: e }),
+            }
+        }
+
+        // 取消后目标目录保持不变，按用户选择保留或删除 .part
+        if session.is_cancelled() {
+            transaction.abort(session.keep_partial());
+            events.emit("SYNC_CANCELLED", &session.id);
+            return report.finish(&*events, &session, Err(session::CANCELLED_ERROR.to_string()));
+        }
+
+        if !report.failed.is_empty() {
+            transaction.abort(true);
+            let message = format!("Sync aborted: {} file(s) failed, target directory left unchanged", report.failed.len());
+            events.emit("DOWNLOAD_ERROR", &message);
+            return report.finish(&*events, &session, Err(message));
+        }
+
+        // 进入提交阶段后不再响应取消，保证目标目录一致
+        session.begin_commit();
+        let hook_events = events.clone();
+        let committed = tokio::task::spawn_blocking(move || {
+            let mut hooks = Vec::new();
+            let result = commit_with_hooks(&*hook_events, &target_dir, &manifest, &exclusions, &mut hooks, transaction, |transaction| {
+                commit_sync(transaction, &target_dir, &files_to_download, &manifest.files, &inactive, &exclusions)
+            });
+            (hooks, result)
+        }).await;
+        let commit_result = match committed {
+            Ok((hooks, result)) => {
+                report.hooks = hooks;
+                result
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        let commit_result = commit_result.map_err(|e| {
+            let message = format!("Failed to apply sync, changes were rolled back: {}", e);
+            events.emit("DOWNLOAD_ERROR", &message);
+            message
+        });
+        report.finish(&*events, &session, commit_result)
+    });
+
+    Ok(SyncHandle { session_id, task })
+}
+
+// 清单中来源哈希与本地文件一致的补丁
+fn find_patch(file_info: &ManifestFile, local_hash: &str) -> Option<FilePatch> {
+    file_info.patches.as_ref()?
+        .iter()
+        .find(|patch| patch.from_hash == local_hash)
+        .cloned()
+}
+
+// 下载补丁并与本地旧文件合成新文件，结果写入暂存路径并校验哈希
+async fn download_and_apply_patch(
+    events: &dyn EventSink,
+    session: &SyncSession,
+    file_info: &ManifestFile,
+    patch: &FilePatch,
+    package_mirrors: &[String],
+    local_path: &Path,
+    staged_path: &Path,
+) -> Result<(), String> {
+    let patch_file = ManifestFile {
+        name: format!("{}.patch", file_info.name),
+        download_url: patch.download_url.clone(),
+        relative_path: file_info.relative_path.clone(),
+        hash: patch.hash.clone(),
+        size: patch.size,
+        file_type: "file".to_string(),
+        auto_extract: None,
+        patches: None,
+        chunks: None,
+        mirrors: None,
+        parts: None,
+        group: None,
+        conditions: None,
+    };
+    let mut patch_path = staged_path.as_os_str().to_os_string();
+    patch_path.push(".patch");
+    let patch_path = PathBuf::from(patch_path);
+
+    // 补丁很小，只从当前最佳的镜像下载一次，失败由调用方回退到完整下载
+    let url = mirrors::pick(&mirrors::candidate_urls(&patch.download_url, &[], package_mirrors), &[])
+        .unwrap_or_else(|| patch.download_url.clone());
+    let part_path = match download_file(events, session, &patch_file, &url, &patch_path).await {
+        Ok(part_path) => part_path,
+        Err(e) => {
+            mirrors::record_failure(&url);
+            return Err(e.to_string());
+        }
+    };
+    let result = (|| {
+        let patch_hash = calculate_file_hash(&part_path).map_err(|e| e.to_string())?;
+        if patch_hash != patch.hash {
+            return Err(format!("Hash mismatch for patch of {}", file_info.name));
+        }
+        delta::apply_patch(local_path, &part_path, staged_path, file_info.size)?;
+        let patched_hash = calculate_file_hash(staged_path).map_err(|e| e.to_string())?;
+        if patched_hash != file_info.hash {
+            let _ = fs::remove_file(staged_path);
+            return Err(format!("Hash mismatch after patching {}", file_info.name));
+        }
+        Ok(())
+    })();
+    let _ = fs::remove_file(&part_path);
+    result
+}
+
+// 下载中的文件先写入同目录下的 `.part` 文件，校验通过后才会替换到目标位置
+fn part_path_for(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".part");
+    path.with_file_name(file_name)
+}
+
+// 解析 `Content-Range: bytes <start>-<end>/<total>` 中的起始偏移
+fn content_range_start(response: &reqwest::Response) -> Option<u64> {
+    response.headers()
+        .get(reqwest::header::CONTENT_RANGE)?
+        .to_str().ok()?
+        .strip_prefix("bytes ")?
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+async fn download_file(
+    events: &dyn EventSink,
+    session: &SyncSession,
+    file_info: &ManifestFile,
+    url: &str,
+    path: &std::path::Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let part_path = part_path_for(path);
+    let mut resume_from = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+
+    // 残留的 .part 比清单里的文件还大，说明是旧版本留下的，直接丢弃
+    if file_info.size > 0 && resume_from > file_info.size {
+        fs::remove_file(&part_path)?;
+        resume_from = 0;
+    }
+
+    let client = scheduler::client();
+    let started = std::time::Instant::now();
+    let mut response = if resume_from > 0 {
+        client.get(url)
+            .header(reqwest::header::RANGE, format!("bytes={}-", resume_from))
+            .send()
+            .await?
+    } else {
+        client.get(url).send().await?
+    };
+
+    if resume_from > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+        if resume_from == file_info.size {
+            // 上次已经下载完整，只是还没来得及校验
+            return Ok(part_path);
+        }
+        fs::remove_file(&part_path)?;
+        resume_from = 0;
+        response = client.get(url).send().await?;
+    }
+
+    let mut response = response.error_for_status()?;
+
+    // 只有服务器返回 206 且偏移一致时才续传，否则退回到完整下载
+    let resumed = resume_from > 0
+        && response.status() == reqwest::StatusCode::PARTIAL_CONTENT
+        && content_range_start(&response) == Some(resume_from);
+    let mut dest = if resumed {
+        fs::OpenOptions::new().append(true).open(&part_path)?
+    } else {
+        resume_from = 0;
+        fs::File::create(&part_path)?
+    };
+
+    let total_size = response.content_length().map(|len| len + resume_from).unwrap_or(0);
+    let mut downloaded: u64 = resume_from;
+
+    while let Some(chunk) = response.chunk().await? {
+        // 暂停时停在这里；取消时已写入的数据留在 .part 中
+        session.checkpoint().await?;
+        dest.write_all(&chunk)?;
+        downloaded += chunk.len() as u64;
+        session.record_transferred(chunk.len() as u64);
+        scheduler::throttle(url, chunk.len() as u64).await;
+        
+        let progress = if total_size > 0 {
+            (downloaded as f32 / total_size as f32) * 100.0
+        } else {
+            0.0
+        };
+
+        events.emit("DOWNLOAD_PROGRESS", &DownloadProgress {
+            file: file_info.name.clone(),
+            total: total_size,
+            downloaded,
+            progress,
+        });
+    }
+    dest.flush()?;
+    mirrors::record_success(url, downloaded - resume_from, started.elapsed());
+
+    Ok(part_path)
+}
+
+// 按块组装分块文件到 .part，交给 verify_and_unzip 校验整个文件的哈希；每个块各自在候选地址间重试
+async fn download_chunked_file(
+    events: &dyn EventSink,
+    session: &SyncSession,
+    file_info: &ManifestFile,
+    base_urls: &[String],
+    local_sources: &HashMap<String, chunking::ChunkLocation>,
+    path: &Path,
+) -> Result<PathBuf, String> {
+    let chunks = file_info.chunks.as_ref()
+        .ok_or_else(|| format!("Chunk list missing for {}", file_info.name))?;
+    let part_path = part_path_for(path);
+
+    let downloaded = chunking::assemble_chunked_file(scheduler::client(), session, base_urls, chunks, local_sources, &part_path, |written| {
+        let progress = if file_info.size > 0 {
+            (written as f32 / file_info.size as f32) * 100.0
+        } else {
+            0.0
+        };
+        events.emit("DOWNLOAD_PROGRESS", &DownloadProgress {
+            file: file_info.name.clone(),
+            total: file_info.size,
+            downloaded: written,
+            progress,
+        });
+    }).await?;
+
+    session.record_transferred(downloaded);
+    Ok(part_path)
+}
+
+// 分卷压缩包：依次下载并校验每个分卷，追加到整个压缩包的 .part 中，
+// 再交给 verify_and_unzip 校验整体哈希并解压。`url` 为所选镜像上整个压缩包的地址
+async fn download_parts(
+    events: &dyn EventSink,
+    session: &SyncSession,
+    file_info: &ManifestFile,
+    url: &str,
+    path: &Path,
+) -> Result<PathBuf, String> {
+    let parts = file_info.parts.as_deref().unwrap_or_default();
+    if parts.iter().map(|part| part.size).sum::<u64>() != file_info.size {
+        return Err(format!("Parts of {} do not add up to its size", file_info.name));
+    }
+
+    // 续传时已完整拼接的分卷不再下载，没有对齐到分卷边界的尾部丢弃
+    let part_path = part_path_for(path);
+    let existing = fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    let mut completed = 0;
+    let mut offset = 0;
+    while completed < parts.len() && offset + parts[completed].size <= existing {
+        offset += parts[completed].size;
+        completed += 1;
+    }
+    let mut assembled = fs::OpenOptions::new().create(true).write(true).truncate(false).open(&part_path)
+        .map_err(|e| format!("Failed to open {}: {}", part_path.to_string_lossy(), e))?;
+    assembled.set_len(offset).map_err(|e| e.to_string())?;
+    assembled.seek(SeekFrom::End(0)).map_err(|e| e.to_string())?;
+
+    for (index, part) in parts.iter().enumerate().skip(completed) {
+        let suffix = manifest::part_suffix(index);
+        // 分卷地址是压缩包地址加序号，镜像上的分卷同样如此
+        let part_url = match part.download_url.strip_prefix(file_info.download_url.as_str()) {
+            Some(rest) => format!("{}{}", url, rest),
+            None => part.download_url.clone(),
+        };
+        let part_info = ManifestFile {
+            name: format!("{}{}", file_info.name, suffix),
+            download_url: part_url.clone(),
+            relative_path: format!("{}{}", file_info.relative_path, suffix),
+            hash: part.hash.clone(),
+            size: part.size,
+            file_type: "file".to_string(),
+            auto_extract: None,
+            patches: None,
+            chunks: None,
+            mirrors: None,
+            parts: None,
+            group: None,
+            conditions: None,
+        };
+        let mut volume_name = path.file_name().unwrap_or_default().to_os_string();
+        volume_name.push(&suffix);
+
+        let volume_path = download_file(events, session, &part_info, &part_url, &path.with_file_name(volume_name)).await
+            .map_err(|e| e.to_string())?;
+        if part.hash != "DISABLED" {
+            let calculated_hash = calculate_file_hash(&volume_path).map_err(|e| e.to_string())?;
+            if calculated_hash != part.hash {
+                let _ = fs::remove_file(&volume_path);
+                return Err(format!("Hash mismatch for {}: expected {}, got {}", part_info.name, part.hash, calculated_hash));
+            }
+        }
+
+        let mut volume = File::open(&volume_path).map_err(|e| e.to_string())?;
+        std::io::copy(&mut volume, &mut assembled)
+            .map_err(|e| format!("Failed to assemble {}: {}", file_info.name, e))?;
+        let _ = fs::remove_file(&volume_path);
+    }
+    assembled.flush().map_err(|e| e.to_string())?;
+
+    Ok(part_path)
+}
+
+fn is_auto_extract(file_info: &ManifestFile) -> bool {
+    (file_info.file_type == "zip" || file_info.file_type == "update_package")
+        && file_info.auto_extract.unwrap_or(false)
+}
+
+// 压缩包解压到目标目录下与压缩包同名（去掉扩展名）的文件夹
+fn archive_folder_name(file_info: &ManifestFile) -> String {
+    Path::new(&file_info.relative_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("archive")
+        .to_string()
+}
+
+fn verify_and_unzip(
+    file_info: &ManifestFile,
+    part_path: &std::path::Path,
+    staged_path: &std::path::Path,
+    extract_dir: &std::path::Path,
+    exclusions: &Exclusions,
+) -> Result<(), String> {
+    // 1. Verify the downloaded bytes before anything is staged.
+    if file_info.hash != "DISABLED" {
+        let calculated_hash = calculate_file_hash(part_path).map_err(|e| e.to_string())?;
+        if calculated_hash != file_info.hash {
+            // A corrupt .part must not be resumed again, start over on the next attempt.
+            let _ = fs::remove_file(part_path);
+            return Err(format!(
+                "Hash mismatch for {}: expected {}, got {}",
+                file_info.name, file_info.hash, calculated_hash
+            ));
+        }
+    }
+
+    // 2. Auto-extract packages are unpacked into the staging area, the live folder is
+    //    only replaced when the whole sync commits.
+    if is_auto_extract(file_info) {
+        if extract_dir.exists() {
+            fs::remove_dir_all(extract_dir).map_err(|e| e.to_string())?;
+        }
+        fs::create_dir_all(extract_dir).map_err(|e| e.to_string())?;
+
+        let file = File::open(part_path).map_err(|e| e.to_string())?;
+        let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+
+        if let Err(e) = extract_archive(&mut archive, extract_dir, &archive_folder_name(file_info), exclusions) {
+            let _ = fs::remove_dir_all(extract_dir);
+            let _ = fs::remove_file(part_path);
+            return Err(e);
+        }
+
+        // After successful extraction, remove the downloaded archive.
+        fs::remove_file(part_path).map_err(|e| e.to_string())?;
+
+        return Ok(());
+    }
+
+    // 3. Regular files are moved into the staging area only after verification.
+    fs::rename(part_path, staged_path).map_err(|e| format!("Failed to stage {}: {}", file_info.name, e))?;
+
+    Ok(())
+}
+
+// 逐个校验条目路径后解压，不使用 ZipArchive::extract：越界路径和符号链接条目直接拒绝。
+// 解压后位于 `folder_name/条目` 且被排除的条目不解压，提交时也不会删除本地对应文件
+fn extract_archive(archive: &mut ZipArchive<File>, extract_dir: &Path, folder_name: &str, exclusions: &Exclusions) -> Result<(), String> {
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| archive_entry_error(&format!("entry {} of {}", i, folder_name), e))?;
+        let relative = paths::validate_relative_path(entry.name())?;
+        if exclusions.is_excluded(&format!("{}/{}", folder_name, relative), entry.is_dir()) {
+            continue;
+        }
+        let out_path = extract_dir.join(relative);
+
+        if entry.is_dir() {
+            fs::create_dir_all(&out_path).map_err(|e| e.to_string())?;
+            continue;
+        }
+        if entry.unix_mode().is_some_and(|mode| mode & 0o170000 == 0o120000) {
+            return Err(paths::unsafe_path(entry.name(), "is a symlink"));
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut out = File::create(&out_path).map_err(|e| e.to_string())?;
+        std::io::copy(&mut entry, &mut out).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// 导出端可以选择 Deflate、Zstd 或 Bzip2 压缩条目，条目使用了不支持的压缩方式时提示升级同步器
+fn archive_entry_error(name: &str, error: zip::result::ZipError) -> String {
+    match error {
+        zip::result::ZipError::UnsupportedArchive(detail) => {
+            format!("{} uses an unsupported compression method ({}), please update the app to sync this pack", name, detail)
+        }
+        error => format!("Failed to read {}: {}", name, error),
+    }
+}
+
+// 清单中的每个路径都必须是目标目录内的安全相对路径，块哈希会拼进下载地址，同样要校验；否则整个包都不可信
+pub fn validate_manifest_paths(manifest_files: &[ManifestFile]) -> Result<(), String> {
+    for file in manifest_files {
+        paths::validate_relative_path(&file.relative_path)?;
+        for chunk in file.chunks.iter().flatten() {
+            chunking::validate_chunk_hash(&chunk.hash)?;
+        }
+    }
+    Ok(())
+}
+
+// 把暂存好的文件、压缩包解压结果以及需要清理的多余文件写入事务并提交，返回删除的文件
+fn commit_sync(
+    transaction: &mut SyncTransaction,
+    target_dir: &str,
+    staged_files: &[ManifestFile],
+    manifest_files: &[ManifestFile],
+    inactive: &InactiveFiles,
+    exclusions: &Exclusions,
+) -> Result<Vec<String>, String> {
+    let mut extracted_folders = Vec::new();
+    let mut deleted = Vec::new();
+
+    for file in staged_files {
+        if !is_auto_extract(file) {
+            transaction.stage_replace(file.relative_path.clone(), &transaction.staged_path(&file.relative_path));
+            continue;
+        }
+
+        // 解压目录整体替换：包内文件覆盖，目录中其余未排除的文件删除
+        let folder_name = archive_folder_name(file);
+        let extract_dir = transaction.extract_dir(&folder_name);
+        let mut extracted = HashSet::new();
+        for entry in WalkDir::new(&extract_dir).into_iter().filter_map(Result::ok) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Ok(rel) = entry.path().strip_prefix(&extract_dir) {
+                let rel_path = format!("{}/{}", folder_name, rel.to_string_lossy().replace('\\', "/"));
+                transaction.stage_replace(rel_path.clone(), entry.path());
+                extracted.insert(rel_path);
+            }
+        }
+
+        let live_folder = paths::resolve_in(Path::new(target_dir), &folder_name)?;
+        let live_entries = WalkDir::new(&live_folder).into_iter()
+            .filter_entry(|entry| !exclusions.is_excluded_path(entry.path(), entry.file_type().is_dir()));
+        for entry in live_entries.filter_map(Result::ok) {
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if let Ok(rel) = entry.path().strip_prefix(target_dir) {
+                let rel_path = rel.to_string_lossy().replace('\\', "/");
+                if !extracted.contains(&rel_path) && transaction.stage_delete(rel_path.clone()) {
+                    deleted.push(rel_path);
+                }
+            }
+        }
+        extracted_folders.push(live_folder);
+    }
+
+    let mut extra_files = find_extra_files(target_dir, manifest_files, exclusions)?;
+    extra_files.retain(|path| !inactive.is_unmanaged(Path::new(target_dir), path));
+    extra_files.extend(disabled_group_files(target_dir, inactive, manifest_files, exclusions)?);
+    for extra in extra_files {
+        if let Ok(rel) = extra.strip_prefix(target_dir) {
+            let rel_path = rel.to_string_lossy().replace('\\', "/");
+            if transaction.stage_delete(rel_path.clone()) {
+                deleted.push(rel_path);
+            }
+        }
+    }
+
+    transaction.apply()?;
+
+    for folder in extracted_folders {
+        remove_empty_dirs(&folder);
+    }
+    for dir in get_top_level_dirs(manifest_files).into_iter().chain(get_top_level_dirs(&inactive.disabled)) {
+        remove_empty_dirs(&Path::new(target_dir).join(dir));
+    }
+    for file in inactive.disabled.iter().filter(|file| is_auto_extract(file)) {
+        remove_empty_dirs(&Path::new(target_dir).join(archive_folder_name(file)));
+    }
+    Ok(deleted)
+}
+
+// 提交同步，并在提交前后执行清单声明的动作；动作改动的文件和提交一起记入快照。
+// 动作的结果记录在 `hooks` 中，失败不影响同步本身
+fn commit_with_hooks(
+    events: &dyn EventSink,
+    target_dir: &str,
+    manifest: &Manifest,
+    exclusions: &Exclusions,
+    hooks: &mut Vec<HookResult>,
+    mut transaction: SyncTransaction,
+    commit: impl FnOnce(&mut SyncTransaction) -> Result<Vec<String>, String>,
+) -> Result<Vec<String>, String> {
+    let Some(sync_hooks) = &manifest.hooks else {
+        let deleted = commit(&mut transaction)?;
+        transaction.finish();
+        return Ok(deleted);
+    };
+    let platform = Platform::detect(Path::new(target_dir));
+    let context = HookContext { target_dir: Path::new(target_dir), manifest, platform: &platform, exclusions };
+
+    hooks.extend(hooks::run_phase(events, HookPhase::PreSync, &sync_hooks.pre_sync, &context, &mut transaction));
+    // 提交失败时撤销同步前动作的改动
+    let deleted = match commit(&mut transaction) {
+        Ok(deleted) => deleted,
+        Err(e) => {
+            transaction.abort(true);
+            return Err(e);
+        }
+    };
+    hooks.extend(hooks::run_phase(events, HookPhase::PostSync, &sync_hooks.post_sync, &context, &mut transaction));
+    transaction.finish();
+    Ok(deleted)
+}
+
+// 本次同步不处理的清单文件
+struct InactiveFiles {
+    // 玩家未启用的组，本地已安装的部分在清理时删除
+    disabled: Vec<ManifestFile>,
+    // 条件不适用于本机，既不下载也不清理
+    unmanaged: Vec<ManifestFile>,
+}
+
+impl InactiveFiles {
+    // 本地路径是否属于不适用于本机的文件或其解压目录
+    fn is_unmanaged(&self, target: &Path, path: &Path) -> bool {
+        self.unmanaged.iter().any(|file| {
+            path == target.join(&file.relative_path)
+                || (is_auto_extract(file) && path.starts_with(target.join(archive_folder_name(file))))
+        })
+    }
+}
+
+// 按本机平台和玩家启用的组从清单中拆出本次不同步的文件
+fn split_inactive_files(manifest: &mut Manifest, target_dir: &str, selection: Option<&[String]>) -> InactiveFiles {
+    let platform = Platform::detect(Path::new(target_dir));
+    let enabled_groups = manifest.enabled_groups(selection);
+    let files = std::mem::take(&mut manifest.files);
+    let (applicable, unmanaged): (Vec<_>, Vec<_>) = files.into_iter().partition(|file| file.applies_to(&platform));
+    let (disabled, enabled): (Vec<_>, Vec<_>) = applicable.into_iter().partition(|file| manifest.is_disabled(file, &enabled_groups));
+    manifest.files = enabled;
+    InactiveFiles { disabled, unmanaged }
+}
+
+// 未启用组中已经安装到本地的文件：普通文件按路径，自动解压的压缩包为整个解压目录。
+// 同时属于已启用内容或不适用于本机的路径保留
+fn disabled_group_files(
+    target_dir: &str,
+    inactive: &InactiveFiles,
+    manifest_files: &[ManifestFile],
+    exclusions: &Exclusions,
+) -> Result<Vec<PathBuf>, String> {
+    let target = Path::new(target_dir);
+    let mut kept: HashSet<PathBuf> = manifest_files.iter().map(|f| target.join(&f.relative_path)).collect();
+    let kept_folders: Vec<PathBuf> = manifest_files.iter()
+        .filter(|file| is_auto_extract(file))
+        .map(|file| target.join(archive_folder_name(file)))
+        .collect();
+
+    let mut files = Vec::new();
+    for file in &inactive.disabled {
+        if exclusions.is_excluded(&file.relative_path, false) {
+            continue;
+        }
+        let local_path = paths::resolve_in(target, &file.relative_path)?;
+        if local_path.is_file() {
+            files.push(local_path);
+        }
+        if !is_auto_extract(file) {
+            continue;
+        }
+        let folder = paths::resolve_in(target, &archive_folder_name(file))?;
+        let walker = WalkDir::new(&folder).into_iter()
+            .filter_entry(|entry| !exclusions.is_excluded_path(entry.path(), entry.file_type().is_dir()));
+        for entry in walker.filter_map(Result::ok) {
+            if entry.file_type().is_file() {
+                files.push(entry.path().to_path_buf());
+            }
+        }
+    }
+    files.retain(|path| {
+        !kept_folders.iter().any(|folder| path.starts_with(folder))
+            && !inactive.is_unmanaged(target, path)
+            && kept.insert(path.clone())
+    });
+    Ok(files)
+}
+
+pub fn load_exclusion_list(target_dir: String) -> Result<Vec<String>, String> {
+    let config_path = std::path::Path::new(&target_dir).join(".sync_exclude.json");
+    if !config_path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = File::open(config_path).map_err(|e| e.to_string())?;
+    let reader = BufReader::new(file);
+    let list = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+    Ok(list)
+}
+
+pub fn save_exclusion_list(target_dir: String, excluded_files: Vec<String>) -> Result<(), String> {
+    let config_path = std::path::Path::new(&target_dir).join(".sync_exclude.json");
+    let file = File::create(config_path).map_err(|e| e.to_string())?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer(writer, &excluded_files).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// 玩家选择启用的可选组，没有保存过时返回 None，使用清单中各组的默认开关
+pub fn load_group_selection(target_dir: String) -> Result<Option<Vec<String>>, String> {
+    let config_path = std::path::Path::new(&target_dir).join(".sync_groups.json");
+    if !config_path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(config_path).map_err(|e| e.to_string())?;
+    let reader = BufReader::new(file);
+    let list = serde_json::from_reader(reader).map_err(|e| e.to_string())?;
+    Ok(Some(list))
+}
+
+pub fn save_group_selection(target_dir: String, enabled_groups: Vec<String>) -> Result<(), String> {
+    let config_path = std::path::Path::new(&target_dir).join(".sync_groups.json");
+    let file = File::create(config_path).map_err(|e| e.to_string())?;
+    let writer = BufWriter::new(file);
+    serde_json::to_writer(writer, &enabled_groups).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn get_scan_dirs(manifest: &Manifest, target_dir: &str) -> Vec<PathBuf> {
+    get_top_level_dirs(&manifest.files).iter()
+        .map(|dir| Path::new(target_dir).join(dir))
+        .collect()
+}
+
+fn scan_local_files(target_dir: &str, scan_dirs: &[PathBuf], force_rescan: bool) -> HashMap<std::path::PathBuf, String> {
+    hash_index::scan_with_index(Path::new(target_dir), scan_dirs, force_rescan)
+}
+
+fn get_top_level_dirs(manifest_files: &[ManifestFile]) -> HashSet<String> {
+    manifest_files.iter()
+        .filter_map(|file| {
+            Path::new(&file.relative_path).components().next().and_then(|comp| {
+                if let Component::Normal(dir) = comp {
+                    Some(dir.to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+// 清单之外且未被排除的本地文件
+fn find_extra_files(target_dir: &str, manifest_files: &[ManifestFile], exclusions: &Exclusions) -> Result<Vec<PathBuf>, String> {
+    let manifest_paths: std::collections::HashSet<_> = manifest_files
+        .iter()
+        .map(|f| std::path::Path::new(target_dir).join(&f.relative_path))
+        .collect();
+
+    let mut extra_files = Vec::new();
+    for dir_name in get_top_level_dirs(manifest_files) {
+        // 清理范围由清单决定，不能让它通过符号链接删到目标目录之外
+        let dir_to_scan = paths::resolve_in(Path::new(target_dir), &dir_name)?;
+        if !dir_to_scan.is_dir() { continue; }
+
+        // 被排除的目录整体跳过
+        let walker = walkdir::WalkDir::new(dir_to_scan).into_iter()
+            .filter_entry(|entry| !exclusions.is_excluded_path(entry.path(), entry.file_type().is_dir()));
+        for entry in walker.filter_map(Result::ok) {
+            let path = entry.path();
+            // Don't touch the exclusion, group and hook approval config files
+            if path.ends_with(".sync_exclude.json") || path.ends_with(".sync_groups.json") || path.ends_with(".sync_hooks.json") {
+                continue;
+            }
+
+            if entry.file_type().is_file() && !manifest_paths.contains(path) {
+                extra_files.push(path.to_path_buf());
+            }
+        }
+    }
+    Ok(extra_files)
+}
+
+// Only remove empty dirs for safety
+fn remove_empty_dirs(dir: &Path) {
+    if !dir.is_dir() {
+        return;
+    }
+    for entry in walkdir::WalkDir::new(dir).min_depth(1).contents_first(true).into_iter().filter_map(Result::ok) {
+        if entry.file_type().is_dir() && fs::read_dir(entry.path()).map(|mut i| i.next().is_none()).unwrap_or(false) {
+            let _ = fs::remove_dir(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::{self, SyncStatus};
+    use crate::{export, test_server};
+    use sha2::Digest;
+    use std::collections::BTreeMap;
+
+    struct NoEvents;
+
+    impl EventSink for NoEvents {
+        fn emit_json(&self, _event: &str, _payload: serde_json::Value) {}
+    }
+
+    // 在 `value` 上添加或覆盖 `extra` 中的字段
+    fn merged(mut value: serde_json::Value, extra: serde_json::Value) -> serde_json::Value {
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        value
+    }
+
+    fn manifest_file(value: serde_json::Value) -> ManifestFile {
+        let file = serde_json::json!({
+            "name": "a.bin",
+            "downloadUrl": "",
+            "relativePath": "a.bin",
+            "hash": "DISABLED",
+            "size": 0,
+            "type": "file",
+            "autoExtract": null,
+        });
+        serde_json::from_value(merged(file, value)).unwrap()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("acgstation-sync-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn content(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+
+    fn write_files(root: &Path, files: &[(&str, &[u8])]) {
+        for (path, data) in files {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, data).unwrap();
+        }
+    }
+
+    // 目标目录中除同步器自己的文件以外的所有文件
+    fn read_tree(root: &Path) -> BTreeMap<String, Vec<u8>> {
+        WalkDir::new(root).into_iter()
+            .filter_entry(|entry| !entry.file_name().to_string_lossy().starts_with(paths::INTERNAL_PREFIX))
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                let relative = entry.path().strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+                (relative, fs::read(entry.path()).unwrap())
+            })
+            .collect()
+    }
+
+    // 把 `source` 下的各项导出为 zip 包，`items` 为相对 `source` 的路径和额外的导出选项
+    fn export_package(source: &Path, items: &[(&str, serde_json::Value)], settings: serde_json::Value, package: &Path) {
+        let files = items.iter()
+            .map(|(name, options)| {
+                let path = source.join(name);
+                let item = serde_json::json!({
+                    "path": path.to_string_lossy(),
+                    "name": name,
+                    "isDirectory": path.is_dir(),
+                    "selected": true,
+                });
+                serde_json::from_value(merged(item, options.clone())).unwrap()
+            })
+            .collect();
+        let defaults = serde_json::json!({
+            "packageName": "pack",
+            "downloadPrefix": "https://cdn.example.com/pack/",
+            "version": "1.0",
+        });
+        let settings = serde_json::from_value(merged(defaults, settings)).unwrap();
+        export::export_files(&NoEvents, files, settings, package.to_string_lossy().into_owned()).unwrap();
+    }
+
+    async fn sync_package(package: &Path, target: &Path, enabled_groups: Option<Vec<String>>) -> SyncReport {
+        let handle = sync_from_local_package(
+            Arc::new(NoEvents),
+            package.to_string_lossy().into_owned(),
+            target.to_string_lossy().into_owned(),
+            Vec::new(),
+            enabled_groups,
+            None,
+            false,
+            false,
+            None,
+        ).await.unwrap();
+        handle.wait().await.unwrap()
+    }
+
+    fn sorted(mut paths: Vec<String>) -> Vec<String> {
+        paths.sort();
+        paths
+    }
+
+    // 先写入 .part 再下载 a.bin，返回下载得到的内容、收到的 Range 请求头和实际传输的字节数
+    async fn resume_download(data: &[u8], part: &[u8], honor_range: bool) -> (Vec<u8>, Option<String>, u64) {
+        let dir = temp_dir();
+        let (base, requests) = test_server::serve_files(HashMap::from([("/a.bin".to_string(), data.to_vec())]), honor_range);
+        let path = dir.join("a.bin");
+        fs::write(part_path_for(&path), part).unwrap();
+        let file = manifest_file(serde_json::json!({"size": data.len()}));
+        let session = SyncSession::detached(&dir);
+
+        let part_path = download_file(&NoEvents, &session, &file, &format!("{}/a.bin", base), &path).await.unwrap();
+        let downloaded = fs::read(part_path).unwrap();
+        let range = requests.lock().unwrap().first().and_then(|request| request.header("range").map(str::to_string));
+        let _ = fs::remove_dir_all(&dir);
+        (downloaded, range, session.transferred())
+    }
+
+    #[tokio::test]
+    async fn resumes_part_files_when_the_server_honors_range() {
+        let data = content(10_000);
+        let (downloaded, range, transferred) = resume_download(&data, &data[..4_000], true).await;
+        assert_eq!(downloaded, data);
+        assert_eq!(range.as_deref(), Some("bytes=4000-"));
+        assert_eq!(transferred, 6_000);
+    }
+
+    #[tokio::test]
+    async fn restarts_when_the_server_ignores_range() {
+        let data = content(10_000);
+        // 服务器返回 200 时 .part 中的旧内容不能留在文件开头
+        let (downloaded, range, transferred) = resume_download(&data, &[0xff; 4_000], false).await;
+        assert_eq!(downloaded, data);
+        assert_eq!(range.as_deref(), Some("bytes=4000-"));
+        assert_eq!(transferred, 10_000);
+    }
+
+    #[tokio::test]
+    async fn discards_part_files_larger_than_the_file() {
+        let data = content(10_000);
+        let (downloaded, range, _) = resume_download(&data, &content(12_000), true).await;
+        assert_eq!(downloaded, data);
+        assert_eq!(range, None);
+    }
+
+    #[tokio::test]
+    async fn keeps_complete_part_files() {
+        let data = content(10_000);
+        let (downloaded, range, transferred) = resume_download(&data, &data, true).await;
+        assert_eq!(downloaded, data);
+        assert_eq!(range.as_deref(), Some("bytes=10000-"));
+        assert_eq!(transferred, 0);
+    }
+
+    #[tokio::test]
+    async fn reports_each_file_of_a_local_package_sync() {
+        let _sessions = session::TEST_LOCK.lock().await;
+        let dir = temp_dir();
+        let (source, target, package) = (dir.join("source"), dir.join("target"), dir.join("pack.zip"));
+        write_files(&source, &[("mods/a.jar", b"a"), ("mods/b.jar", b"b"), ("config/c.toml", b"c")]);
+        export_package(&source, &[("mods", serde_json::json!({})), ("config", serde_json::json!({}))], serde_json::json!({}), &package);
+        write_files(&target, &[("mods/a.jar", b"a"), ("mods/b.jar", b"old"), ("mods/extra.jar", b"x")]);
+
+        let report = sync_package(&package, &target, None).await;
+        assert_eq!(report.status, SyncStatus::Completed, "{:?}", report.error);
+        assert_eq!(sorted(report.succeeded.clone()), ["config/c.toml", "mods/b.jar"]);
+        assert_eq!(report.skipped, ["mods/a.jar"]);
+        assert_eq!(report.deleted, ["mods/extra.jar"]);
+        assert!(report.failed.is_empty());
+        assert_eq!(report.bytes_transferred, 2);
+        assert_eq!(read_tree(&target), read_tree(&source));
+        // 结果在会话结束后仍可查询
+        assert_eq!(report::get(&report.session_id).unwrap().succeeded, report.succeeded);
+
+        // 已是最新时没有要安装的文件，同样得到完整的结果
+        let report = sync_package(&package, &target, None).await;
+        assert_eq!(report.status, SyncStatus::Completed);
+        assert!(report.succeeded.is_empty() && report.deleted.is_empty());
+        assert_eq!(sorted(report.skipped), ["config/c.toml", "mods/a.jar", "mods/b.jar"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    // 几乎无法压缩的内容，文件夹压缩包会超过分卷大小
+    fn noise() -> Vec<u8> {
+        (0..2_000u32).flat_map(|i| sha2::Sha256::digest(i.to_le_bytes())).collect()
+    }
+
+    #[tokio::test]
+    async fn installs_split_archives_from_local_packages() {
+        let _sessions = session::TEST_LOCK.lock().await;
+        let dir = temp_dir();
+        let (source, target, package) = (dir.join("source"), dir.join("target"), dir.join("pack.zip"));
+        write_files(&source, &[("shaderpacks/noise.bin", &noise())]);
+        export_package(&source, &[("shaderpacks", serde_json::json!({"compress": true}))], serde_json::json!({"maxPartSize": 20_000}), &package);
+
+        let report = sync_package(&package, &target, None).await;
+        assert_eq!(report.status, SyncStatus::Completed, "{:?}", report.error);
+        assert_eq!(report.succeeded, ["shaderpacks.zip"]);
+        assert_eq!(read_tree(&target), read_tree(&source));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn joins_downloaded_parts_and_resumes_at_part_boundaries() {
+        let dir = temp_dir();
+        let (source, hosted) = (dir.join("source"), dir.join("hosted"));
+        let (base, requests) = test_server::serve({
+            let hosted = hosted.clone();
+            move |request| match fs::read(hosted.join(request.path.trim_start_matches('/'))) {
+                Ok(data) => test_server::Response::new(200, data),
+                Err(_) => test_server::Response::new(404, Vec::new()),
+            }
+        });
+        write_files(&source, &[("shaderpacks/noise.bin", &noise())]);
+        let settings = serde_json::json!({"outputMode": "directory", "downloadPrefix": format!("{}/", base), "maxPartSize": 20_000});
+        export_package(&source, &[("shaderpacks", serde_json::json!({"compress": true}))], settings, &hosted);
+        let manifest = manifest::parse_manifest(&fs::read_to_string(hosted.join("manifest.json")).unwrap()).unwrap();
+        let archive = &manifest.files[0];
+        let parts = archive.parts.as_deref().unwrap();
+        let expected: Vec<u8> = (0..parts.len())
+            .flat_map(|index| fs::read(hosted.join(format!("shaderpacks.zip{}", manifest::part_suffix(index)))).unwrap())
+            .collect();
+
+        // 上次中断时第一个分卷已经拼接完成，第二个分卷只写了一部分
+        let path = dir.join("download/shaderpacks.zip");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(part_path_for(&path), &expected[..parts[0].size as usize + 100]).unwrap();
+        let session = SyncSession::detached(&dir);
+        let assembled = download_parts(&NoEvents, &session, archive, &archive.download_url, &path).await.unwrap();
+        assert_eq!(fs::read(assembled).unwrap(), expected);
+        let requested: Vec<String> = requests.lock().unwrap().iter().map(|request| request.path.clone()).collect();
+        let expected_requests: Vec<String> = (1..parts.len())
+            .map(|index| format!("/shaderpacks.zip{}", manifest::part_suffix(index)))
+            .collect();
+        assert_eq!(requested, expected_requests);
+
+        // 分卷大小之和与文件大小不符的清单直接拒绝
+        let mut broken = archive.clone();
+        broken.size += 1;
+        assert!(download_parts(&NoEvents, &session, &broken, &broken.download_url, &path).await.is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn installs_and_removes_optional_groups() {
+        let _sessions = session::TEST_LOCK.lock().await;
+        let dir = temp_dir();
+        let (source, target, package) = (dir.join("source"), dir.join("target"), dir.join("pack.zip"));
+        write_files(&source, &[("mods/core.jar", b"core"), ("extras/a.txt", b"a"), ("shaderpacks/pack/s.txt", b"s")]);
+        let items = [
+            ("mods", serde_json::json!({})),
+            ("extras", serde_json::json!({"group": "extras"})),
+            ("shaderpacks", serde_json::json!({"compress": true, "group": "shaders"})),
+        ];
+        let groups = serde_json::json!({"groups": [
+            {"id": "extras", "name": "Extras", "enabledByDefault": true},
+            {"id": "shaders", "name": "Shaders"},
+        ]});
+        export_package(&source, &items, groups, &package);
+        let installed = |target: &Path| read_tree(target).into_keys().collect::<Vec<_>>();
+
+        // 没有选择时使用各组的默认开关
+        let report = sync_package(&package, &target, None).await;
+        assert_eq!(report.status, SyncStatus::Completed, "{:?}", report.error);
+        assert_eq!(installed(&target), ["extras/a.txt", "mods/core.jar"]);
+
+        // 关闭的组中已安装的文件在同步时删除，自动解压的压缩包删除整个解压目录
+        let report = sync_package(&package, &target, Some(vec!["shaders".to_string()])).await;
+        assert_eq!(report.deleted, ["extras/a.txt"]);
+        assert_eq!(installed(&target), ["mods/core.jar", "shaderpacks/pack/s.txt"]);
+
+        let report = sync_package(&package, &target, Some(Vec::new())).await;
+        assert_eq!(report.deleted, ["shaderpacks/pack/s.txt"]);
+        assert_eq!(installed(&target), ["mods/core.jar"]);
+        assert!(!target.join("shaderpacks/pack").exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,108 @@
+// 单元测试用的本地 HTTP 服务器，每个连接只处理一个请求
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub path: String,
+    // 请求头名称统一为小写
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+}
+
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Response {
+        Response { status, headers: Vec::new(), body: body.into() }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+// 服务器收到的请求，按到达顺序
+pub type RequestLog = Arc<Mutex<Vec<Request>>>;
+
+// 在后台线程中用 `handler` 回应请求，返回根地址（不带结尾的 `/`）和请求记录
+pub fn serve<F>(handler: F) -> (String, RequestLog)
+where
+    F: Fn(&Request) -> Response + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let requests: RequestLog = Arc::new(Mutex::new(Vec::new()));
+    let log = requests.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let Some(request) = read_request(&mut BufReader::new(&stream)) else { continue };
+            log.lock().unwrap().push(request.clone());
+
+            let response = handler(&request);
+            let reason = reqwest::StatusCode::from_u16(response.status).ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or("Unknown");
+            let mut head = format!("HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n", response.status, reason, response.body.len());
+            for (name, value) in &response.headers {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+            head.push_str("\r\n");
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&response.body);
+            let _ = stream.flush();
+        }
+    });
+    (base, requests)
+}
+
+fn read_request(reader: &mut impl BufRead) -> Option<Request> {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).ok()?;
+    let path = request_line.split_whitespace().nth(1)?.to_string();
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+    Some(Request { path, headers })
+}
+
+// 按路径提供静态文件；`honor_range` 为 false 时像不支持续传的服务器一样忽略 Range 请求头
+pub fn serve_files(files: HashMap<String, Vec<u8>>, honor_range: bool) -> (String, RequestLog) {
+    serve(move |request| {
+        let Some(data) = files.get(&request.path) else {
+            return Response::new(404, Vec::new());
+        };
+        let start = request.header("range")
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+            .filter(|_| honor_range);
+        match start {
+            Some(start) if start >= data.len() => {
+                Response::new(416, Vec::new()).header("Content-Range", &format!("bytes */{}", data.len()))
+            }
+            Some(start) => Response::new(206, data[start..].to_vec())
+                .header("Content-Range", &format!("bytes {}-{}/{}", start, data.len() - 1, data.len())),
+            None => Response::new(200, data.clone()),
+        }
+    })
+}
@@ -0,0 +1,535 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{self, File, TryLockError};
+use std::path::{Path, PathBuf};
+
+use crate::snapshots::{self, SnapshotReason};
+
+// 暂存区放在目标目录内，保证提交时的 rename 不会跨文件系统
+pub const STAGING_DIR_NAME: &str = ".sync_staging";
+const JOURNAL_FILE_NAME: &str = ".sync_journal.json";
+// 同步期间由事务持有的文件锁，防止同一目录上的其他同步或差异计算动到暂存区和日志
+const LOCK_FILE_NAME: &str = ".sync_lock";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum JournalState {
+    // 仅写入暂存区，目标目录尚未改动
+    Staging,
+    // 正在替换目标目录中的文件，中断后需要回滚
+    Committing,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum JournalOp {
+    Replace {
+        path: String,
+        staged: String,
+        #[serde(default)]
+        had_original: bool,
+    },
+    Delete {
+        path: String,
+    },
+}
+
+impl JournalOp {
+    fn path(&self) -> &str {
+        match self {
+            JournalOp::Replace { path, .. } | JournalOp::Delete { path } => path,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    id: String,
+    state: JournalState,
+    created_at: String,
+    ops: Vec<JournalOp>,
+}
+
+// 目标目录的独占锁，由操作系统在文件关闭（包括进程退出）时释放
+struct TargetLock {
+    _file: File,
+}
+
+impl TargetLock {
+    // 锁已被其他同步持有时返回 None
+    fn try_acquire(target_dir: &Path) -> Result<Option<TargetLock>, String> {
+        fs::create_dir_all(target_dir).map_err(|e| format!("Failed to create target directory: {}", e))?;
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(target_dir.join(LOCK_FILE_NAME))
+            .map_err(|e| format!("Failed to open sync lock: {}", e))?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(TargetLock { _file: file })),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(format!("Failed to lock {}: {}", target_dir.to_string_lossy(), e)),
+        }
+    }
+
+    fn acquire(target_dir: &Path) -> Result<TargetLock, String> {
+        TargetLock::try_acquire(target_dir)?
+            .ok_or_else(|| format!("Another sync is already running in {}", target_dir.to_string_lossy()))
+    }
+}
+
+// 目标目录上是否有同步正在进行（可能来自其他进程）
+pub fn is_locked(target_dir: &Path) -> bool {
+    target_dir.join(LOCK_FILE_NAME).exists() && matches!(TargetLock::try_acquire(target_dir), Ok(None))
+}
+
+pub struct SyncTransaction {
+    target_dir: PathBuf,
+    staging_dir: PathBuf,
+    journal: Journal,
+    staged_paths: HashSet<String>,
+    reason: SnapshotReason,
+    // 同步动作改动之前记录的路径，值为当时是否存在
+    preserved: Vec<(String, bool)>,
+    // 文件是否已经替换到目标目录
+    applied: bool,
+    _lock: TargetLock,
+}
+
+fn journal_path(target_dir: &Path) -> PathBuf {
+    target_dir.join(JOURNAL_FILE_NAME)
+}
+
+fn write_journal(target_dir: &Path, journal: &Journal) -> Result<(), String> {
+    let path = journal_path(target_dir);
+    let temp_path = target_dir.join(format!("{}.tmp", JOURNAL_FILE_NAME));
+    let json = serde_json::to_string_pretty(journal).map_err(|e| e.to_string())?;
+    fs::write(&temp_path, json).map_err(|e| format!("Failed to write sync journal: {}", e))?;
+    fs::rename(&temp_path, &path).map_err(|e| format!("Failed to write sync journal: {}", e))
+}
+
+// 优先 rename，失败时（例如跨设备）退回到复制后删除
+fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// 清理暂存区；`keep_partial` 为 true 时保留 .part 文件以便下次续传
+fn discard_staging(staging_dir: &Path, keep_partial: bool) {
+    if !keep_partial {
+        let _ = fs::remove_dir_all(staging_dir);
+        return;
+    }
+
+    let _ = fs::remove_dir_all(staging_dir.join("backup"));
+    let _ = fs::remove_dir_all(staging_dir.join("extract"));
+    let _ = fs::remove_dir_all(staging_dir.join("preserved"));
+    for entry in walkdir::WalkDir::new(staging_dir.join("files")).contents_first(true).into_iter().filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_type().is_dir() {
+            let _ = fs::remove_dir(path);
+        } else if path.extension() != Some(std::ffi::OsStr::new("part")) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// 按相反顺序撤销已经执行的操作，从备份中恢复原文件
+fn rollback_ops(target_dir: &Path, staging_dir: &Path, ops: &[JournalOp]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for op in ops.iter().rev() {
+        let live_path = target_dir.join(op.path());
+        let backup_path = staging_dir.join("backup").join(op.path());
+
+        let result = if backup_path.exists() {
+            if live_path.exists() {
+                let _ = remove_path(&live_path);
+            }
+            move_path(&backup_path, &live_path)
+        } else {
+            match op {
+                JournalOp::Replace { had_original: false, .. } if live_path.exists() => remove_path(&live_path),
+                _ => Ok(()),
+            }
+        };
+
+        if let Err(e) = result {
+            errors.push(format!("{}: {}", op.path(), e));
+        }
+    }
+    errors
+}
+
+// 检查目标目录中是否有未完成的同步事务，有则自动回滚。
+// 返回 true 表示执行了回滚；日志属于正在进行的同步时返回错误，不会动它。
+pub fn recover(target_dir: &Path) -> Result<bool, String> {
+    if !journal_path(target_dir).exists() {
+        return Ok(false);
+    }
+    let _lock = TargetLock::acquire(target_dir)?;
+    recover_locked(target_dir)
+}
+
+// 启动时回滚这些目录中被中断的同步，避免游戏在半更新的目录上启动；返回回滚过的目录。
+// 正在同步的目录跳过，失败只打印，不影响启动
+pub fn recover_all<P: AsRef<Path>>(target_dirs: &[P]) -> Vec<PathBuf> {
+    let mut rolled_back = Vec::new();
+    for target_dir in target_dirs.iter().map(AsRef::as_ref) {
+        if !journal_path(target_dir).exists() || is_locked(target_dir) {
+            continue;
+        }
+        match recover(target_dir) {
+            Ok(true) => rolled_back.push(target_dir.to_path_buf()),
+            Ok(false) => {}
+            Err(e) => eprintln!("Failed to recover {}: {}", target_dir.to_string_lossy(), e),
+        }
+    }
+    rolled_back
+}
+
+// 调用方必须持有目标目录的锁
+fn recover_locked(target_dir: &Path) -> Result<bool, String> {
+    let path = journal_path(target_dir);
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    let staging_dir = target_dir.join(STAGING_DIR_NAME);
+    let journal: Journal = match fs::read_to_string(&path).ok().and_then(|text| serde_json::from_str(&text).ok()) {
+        Some(journal) => journal,
+        None => {
+            // 日志损坏时无法判断提交进度，保留备份供人工处理
+            return Err(format!(
+                "Sync journal {} is unreadable, backups are kept in {}",
+                path.to_string_lossy(),
+                staging_dir.to_string_lossy()
+            ));
+        }
+    };
+
+    let rolled_back = journal.state == JournalState::Committing;
+    if rolled_back {
+        let errors = rollback_ops(target_dir, &staging_dir, &journal.ops);
+        if !errors.is_empty() {
+            return Err(format!("Failed to roll back interrupted sync {}: {}", journal.id, errors.join("; ")));
+        }
+        eprintln!("Rolled back interrupted sync {} in {}", journal.id, target_dir.to_string_lossy());
+    }
+
+    fs::remove_file(&path).map_err(|e| e.to_string())?;
+    discard_staging(&staging_dir, true);
+    Ok(rolled_back)
+}
+
+impl SyncTransaction {
+    // 在事务结束前一直持有目标目录的锁
+    pub fn begin(target_dir: &Path, reason: SnapshotReason) -> Result<SyncTransaction, String> {
+        let lock = TargetLock::acquire(target_dir)?;
+        recover_locked(target_dir)?;
+
+        let staging_dir = target_dir.join(STAGING_DIR_NAME);
+        fs::create_dir_all(staging_dir.join("files"))
+            .map_err(|e| format!("Failed to create staging directory: {}", e))?;
+
+        let journal = Journal {
+            id: uuid::Uuid::new_v4().to_string(),
+            state: JournalState::Staging,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            ops: Vec::new(),
+        };
+        write_journal(target_dir, &journal)?;
+
+        Ok(SyncTransaction {
+            target_dir: target_dir.to_path_buf(),
+            staging_dir,
+            journal,
+            staged_paths: HashSet::new(),
+            reason,
+            preserved: Vec::new(),
+            applied: false,
+            _lock: lock,
+        })
+    }
+
+    // 普通文件在暂存区中的位置
+    pub fn staged_path(&self, relative_path: &str) -> PathBuf {
+        self.staging_dir.join("files").join(relative_path)
+    }
+
+    // 压缩包在暂存区中的解压目录
+    pub fn extract_dir(&self, name: &str) -> PathBuf {
+        self.staging_dir.join("extract").join(name)
+    }
+
+    pub fn stage_replace(&mut self, path: String, staged: &Path) {
+        let staged = staged.strip_prefix(&self.staging_dir)
+            .unwrap_or(staged)
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !self.staged_paths.insert(path.clone()) {
+            self.journal.ops.retain(|op| op.path() != path);
+        }
+        self.journal.ops.push(JournalOp::Replace { path, staged, had_original: false });
+    }
+
+    // 返回 false 表示该路径已经安排过，不会重复删除
+    pub fn stage_delete(&mut self, path: String) -> bool {
+        if !self.staged_paths.insert(path.clone()) {
+            return false;
+        }
+        self.journal.ops.push(JournalOp::Delete { path });
+        true
+    }
+
+    // 将暂存区中的文件替换到目标目录；任一步失败都会回滚到提交前的状态
+    pub fn apply(&mut self) -> Result<(), String> {
+        for op in self.journal.ops.iter_mut() {
+            if let JournalOp::Replace { path, had_original, .. } = op {
+                *had_original = self.target_dir.join(path.as_str()).exists();
+            }
+        }
+        self.journal.state = JournalState::Committing;
+        write_journal(&self.target_dir, &self.journal)?;
+
+        for (index, op) in self.journal.ops.iter().enumerate() {
+            let live_path = self.target_dir.join(op.path());
+            let backup_path = self.staging_dir.join("backup").join(op.path());
+
+            let result = (|| -> std::io::Result<()> {
+                if live_path.exists() {
+                    move_path(&live_path, &backup_path)?;
+                }
+                if let JournalOp::Replace { staged, .. } = op {
+                    move_path(&self.staging_dir.join(staged), &live_path)?;
+                }
+                Ok(())
+            })();
+
+            if let Err(e) = result {
+                let errors = rollback_ops(&self.target_dir, &self.staging_dir, &self.journal.ops[..=index]);
+                if errors.is_empty() {
+                    // 同步动作的改动同样撤销，暂存区随后清理
+                    self.undo_preserved();
+                    self.preserved.clear();
+                    let _ = fs::remove_file(journal_path(&self.target_dir));
+                    discard_staging(&self.staging_dir, true);
+                }
+                return Err(format!("Failed to commit {}: {}", op.path(), e));
+            }
+        }
+
+        // 日志删除即视为提交完成
+        fs::remove_file(journal_path(&self.target_dir)).map_err(|e| e.to_string())?;
+        self.applied = true;
+        Ok(())
+    }
+
+    // 同步动作改动目标目录中的路径之前调用，把它当前的状态记入本次快照，恢复快照时可以撤销动作的改动；
+    // 提交前记录的状态在放弃同步时直接还原。目录按其中的文件记录；每个路径只记录第一次改动之前的状态
+    pub fn preserve(&mut self, path: &str) -> Result<(), String> {
+        if self.applied && snapshots::retention().max_snapshots == 0 {
+            return Ok(());
+        }
+        let live_path = self.target_dir.join(path);
+        if live_path.is_dir() {
+            for entry in walkdir::WalkDir::new(&live_path).min_depth(1).into_iter().filter_map(Result::ok) {
+                if entry.file_type().is_file() {
+                    if let Ok(relative) = entry.path().strip_prefix(&self.target_dir) {
+                        self.preserve(&relative.to_string_lossy().replace('\\', "/"))?;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        // 提交已经改动过的路径，快照中已有更早的状态
+        let recorded = self.preserved.iter().any(|(preserved, _)| preserved == path)
+            || (self.applied && self.journal.ops.iter().any(|op| op.path() == path));
+        if recorded {
+            return Ok(());
+        }
+        let existed = live_path.is_file();
+        if existed {
+            // 提交前的备份放在单独的目录，提交时的备份不能覆盖它
+            let area = if self.applied { "backup" } else { "preserved" };
+            let copy_path = self.staging_dir.join(area).join(path);
+            if let Some(parent) = copy_path.parent() {
+                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            fs::copy(&live_path, &copy_path).map_err(|e| format!("Failed to back up {}: {}", path, e))?;
+        }
+        self.preserved.push((path.to_string(), existed));
+        Ok(())
+    }
+
+    // 保存快照并清理暂存区，之后释放目标目录的锁；必须在 apply 成功之后调用
+    pub fn finish(self) {
+        // 备份的原文件保存为快照，失败时只影响之后的恢复，不影响本次提交
+        let backup_dir = self.staging_dir.join("backup");
+        let mut files: BTreeSet<String> = self.journal.ops.iter()
+            .filter(|op| backup_dir.join(op.path()).is_file())
+            .map(|op| op.path().to_string())
+            .collect();
+        let mut created: BTreeSet<String> = self.journal.ops.iter()
+            .filter_map(|op| match op {
+                JournalOp::Replace { path, had_original: false, .. } => Some(path.clone()),
+                _ => None,
+            })
+            .collect();
+
+        // 同步动作记录的状态：提交前记录的比提交的备份更早，取代它；提交后记录的路径提交没有动过
+        let preserved_dir = self.staging_dir.join("preserved");
+        for (path, existed) in &self.preserved {
+            let preserved_path = preserved_dir.join(path);
+            let backup_path = backup_dir.join(path);
+            if preserved_path.is_file() {
+                let _ = fs::remove_file(&backup_path);
+                if let Err(e) = move_path(&preserved_path, &backup_path) {
+                    eprintln!("Failed to keep backup of {}: {}", path, e);
+                    continue;
+                }
+            } else if !existed {
+                let _ = fs::remove_file(&backup_path);
+            }
+            if *existed {
+                created.remove(path);
+                files.insert(path.clone());
+            } else {
+                files.remove(path);
+                created.insert(path.clone());
+            }
+        }
+
+        if let Err(e) = snapshots::save(&self.target_dir, &backup_dir, self.reason, files.into_iter().collect(), created.into_iter().collect()) {
+            eprintln!("Failed to save snapshot in {}: {}", self.target_dir.to_string_lossy(), e);
+        }
+        discard_staging(&self.staging_dir, false);
+    }
+
+    pub fn commit(mut self) -> Result<(), String> {
+        self.apply()?;
+        self.finish();
+        Ok(())
+    }
+
+    // 放弃本次同步，同步动作在提交前做的改动一并还原；`keep_partial` 决定是否保留 .part 以便续传
+    pub fn abort(self, keep_partial: bool) {
+        if !self.applied {
+            self.undo_preserved();
+        }
+        let _ = fs::remove_file(journal_path(&self.target_dir));
+        discard_staging(&self.staging_dir, keep_partial);
+    }
+
+    // 按相反顺序把记录过的路径还原到改动之前，当时不存在的路径删除
+    fn undo_preserved(&self) {
+        for (path, existed) in self.preserved.iter().rev() {
+            let live_path = self.target_dir.join(path);
+            let preserved_path = self.staging_dir.join("preserved").join(path);
+            let result = if live_path.exists() { remove_path(&live_path) } else { Ok(()) }
+                .and_then(|_| if *existed { move_path(&preserved_path, &live_path) } else { Ok(()) });
+            if let Err(e) = result {
+                eprintln!("Failed to undo changes to {}: {}", path, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restore_reason() -> SnapshotReason {
+        SnapshotReason::Restore { snapshot_id: "test".to_string() }
+    }
+
+    #[test]
+    fn running_transaction_locks_target() {
+        let target = std::env::temp_dir().join(format!("acgstation-transaction-{}", uuid::Uuid::new_v4()));
+        let transaction = SyncTransaction::begin(&target, restore_reason()).unwrap();
+        assert!(is_locked(&target));
+
+        // 第二次同步和差异计算都不能动正在进行的事务
+        assert!(SyncTransaction::begin(&target, restore_reason()).is_err());
+        assert!(recover(&target).is_err());
+        assert!(journal_path(&target).exists());
+
+        transaction.abort(false);
+        assert!(!is_locked(&target));
+        assert!(SyncTransaction::begin(&target, restore_reason()).is_ok());
+        let _ = fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn recovers_interrupted_commits_at_startup() {
+        let target = std::env::temp_dir().join(format!("acgstation-transaction-{}", uuid::Uuid::new_v4()));
+        let clean = std::env::temp_dir().join(format!("acgstation-transaction-{}", uuid::Uuid::new_v4()));
+        // 提交到一半时进程退出：a.txt 已替换为新版本，原文件在备份中，b.txt 是新建的
+        fs::create_dir_all(target.join(STAGING_DIR_NAME).join("backup")).unwrap();
+        fs::write(target.join("a.txt"), "a1").unwrap();
+        fs::write(target.join(STAGING_DIR_NAME).join("backup/a.txt"), "a0").unwrap();
+        fs::write(target.join("b.txt"), "b1").unwrap();
+        write_journal(&target, &Journal {
+            id: "interrupted".to_string(),
+            state: JournalState::Committing,
+            created_at: chrono::Utc::now().to_rfc3339(),
+            ops: vec![
+                JournalOp::Replace { path: "a.txt".to_string(), staged: "files/a.txt".to_string(), had_original: true },
+                JournalOp::Replace { path: "b.txt".to_string(), staged: "files/b.txt".to_string(), had_original: false },
+            ],
+        }).unwrap();
+
+        assert_eq!(recover_all(&[&target, &clean]), vec![target.clone()]);
+        assert_eq!(fs::read_to_string(target.join("a.txt")).unwrap(), "a0");
+        assert!(!target.join("b.txt").exists());
+        assert!(!journal_path(&target).exists());
+        assert!(recover_all(&[&target]).is_empty());
+        let _ = fs::remove_dir_all(&target);
+    }
+
+    #[test]
+    fn preserved_changes_are_restored_with_the_snapshot() {
+        snapshots::override_retention(snapshots::SnapshotRetention::default());
+        let target = std::env::temp_dir().join(format!("acgstation-transaction-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(target.join("config")).unwrap();
+        fs::write(target.join("config/a.txt"), "a0").unwrap();
+        fs::write(target.join("b.txt"), "b0").unwrap();
+
+        let mut transaction = SyncTransaction::begin(&target, restore_reason()).unwrap();
+        // 同步前动作删除整个目录
+        transaction.preserve("config").unwrap();
+        fs::remove_dir_all(target.join("config")).unwrap();
+        let staged = transaction.staged_path("c.txt");
+        fs::write(&staged, "c1").unwrap();
+        transaction.stage_replace("c.txt".to_string(), &staged);
+        transaction.apply().unwrap();
+        // 同步后动作改写已有文件并新建文件
+        transaction.preserve("b.txt").unwrap();
+        fs::write(target.join("b.txt"), "b1").unwrap();
+        transaction.preserve("d.txt").unwrap();
+        fs::write(target.join("d.txt"), "d1").unwrap();
+        transaction.finish();
+
+        let snapshot = snapshots::list(&target).unwrap().remove(0);
+        snapshots::restore(&target, &snapshot.id).unwrap();
+        assert_eq!(fs::read_to_string(target.join("config/a.txt")).unwrap(), "a0");
+        assert_eq!(fs::read_to_string(target.join("b.txt")).unwrap(), "b0");
+        assert!(!target.join("c.txt").exists());
+        assert!(!target.join("d.txt").exists());
+        let _ = fs::remove_dir_all(&target);
+    }
+}
@@ -0,0 +1,430 @@
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+use crate::events::EventSink;
+use crate::history::{self, HistoryEvent, SyncTrigger};
+use crate::manifest::{self, ManifestFetch};
+use crate::paths;
+use crate::profiles::{self, AutoSync, SyncProfile};
+use crate::session;
+use crate::transaction;
+
+// 每个配置上次检查的结果保存在应用数据目录，重启后仍可以发送条件请求
+const STATE_FILE_NAME: &str = "update_checks.json";
+// 检查更新的设置同样保存在应用数据目录
+const SETTINGS_FILE_NAME: &str = "update_check_settings.json";
+
+const DEFAULT_INTERVAL_MINUTES: u64 = 30;
+
+// 调度循环的间隔，定时同步和空闲同步按这个粒度判断
+const TICK: Duration = Duration::from_secs(60);
+
+// 总 CPU 占用低于这个百分比，且没有从目标目录启动的进程时视为空闲
+const IDLE_CPU_PERCENT: f32 = 25.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct UpdateCheckSettings {
+    // 关闭后不再检查更新，也不会自动同步
+    pub enabled: bool,
+    #[serde(rename = "intervalMinutes")]
+    pub interval_minutes: u64,
+}
+
+impl Default for UpdateCheckSettings {
+    fn default() -> Self {
+        UpdateCheckSettings { enabled: true, interval_minutes: DEFAULT_INTERVAL_MINUTES }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct CheckState {
+    // 检查时的来源，配置改了来源后之前的缓存作废
+    source: String,
+    etag: Option<String>,
+    // 服务器的 Last-Modified；本地来源为文件的修改时间
+    #[serde(rename = "lastModified")]
+    last_modified: Option<String>,
+    #[serde(rename = "latestVersion")]
+    latest_version: Option<String>,
+    #[serde(rename = "checkedAt")]
+    checked_at: Option<String>,
+    error: Option<String>,
+    // 最近一次到达定时同步时间的本地日期，每天只触发一次
+    #[serde(rename = "lastScheduledDate")]
+    last_scheduled_date: Option<String>,
+    // 最近一次空闲时自动同步的时间，失败后隔一个检查间隔再试
+    #[serde(rename = "lastIdleSyncAt")]
+    last_idle_sync_at: Option<String>,
+}
+
+// 配置的更新状态，随 UPDATE_AVAILABLE 事件发送
+#[derive(Debug, Serialize, Clone)]
+pub struct UpdateStatus {
+    #[serde(rename = "profileId")]
+    pub profile_id: String,
+    #[serde(rename = "profileName")]
+    pub profile_name: String,
+    #[serde(rename = "currentVersion")]
+    pub current_version: Option<String>,
+    #[serde(rename = "latestVersion")]
+    pub latest_version: Option<String>,
+    #[serde(rename = "updateAvailable")]
+    pub update_available: bool,
+    #[serde(rename = "checkedAt")]
+    pub checked_at: Option<String>,
+    pub error: Option<String>,
+}
+
+static SETTINGS: Lazy<Mutex<UpdateCheckSettings>> = Lazy::new(|| {
+    let settings: UpdateCheckSettings = paths::load_settings(SETTINGS_FILE_NAME);
+    Mutex::new(if settings.interval_minutes == 0 { UpdateCheckSettings::default() } else { settings })
+});
+
+// 检查和自动同步都要读改写状态文件，并且检查期间有网络请求，用异步锁串行
+static CHECK_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+pub fn settings() -> UpdateCheckSettings {
+    *SETTINGS.lock().unwrap()
+}
+
+// 保存并应用设置，新的间隔在下一次检查后生效
+pub fn set_settings(settings: UpdateCheckSettings) -> Result<(), String> {
+    validate_settings(&settings)?;
+    paths::save_settings(SETTINGS_FILE_NAME, &settings)?;
+    *SETTINGS.lock().unwrap() = settings;
+    Ok(())
+}
+
+// 只对当前进程生效，例如命令行的 watch
+pub fn override_settings(settings: UpdateCheckSettings) -> Result<(), String> {
+    validate_settings(&settings)?;
+    *SETTINGS.lock().unwrap() = settings;
+    Ok(())
+}
+
+fn validate_settings(settings: &UpdateCheckSettings) -> Result<(), String> {
+    if settings.interval_minutes == 0 {
+        return Err("Update check interval must be at least 1 minute".to_string());
+    }
+    Ok(())
+}
+
+fn state_path() -> Result<PathBuf, String> {
+    Ok(paths::app_data_dir()?.join(STATE_FILE_NAME))
+}
+
+fn load_states() -> Result<BTreeMap<String, CheckState>, String> {
+    let path = state_path()?;
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to parse {}: {}", path.to_string_lossy(), e))
+}
+
+fn save_states(states: &BTreeMap<String, CheckState>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(states).map_err(|e| e.to_string())?;
+    fs::write(state_path()?, json).map_err(|e| e.to_string())
+}
+
+fn is_update(profile: &SyncProfile, state: &CheckState) -> bool {
+    state.latest_version.is_some() && state.latest_version != profile.last_synced_version
+}
+
+fn status(profile: &SyncProfile, state: Option<&CheckState>) -> UpdateStatus {
+    UpdateStatus {
+        profile_id: profile.id.clone(),
+        profile_name: profile.name.clone(),
+        current_version: profile.last_synced_version.clone(),
+        latest_version: state.and_then(|state| state.latest_version.clone()),
+        update_available: state.is_some_and(|state| is_update(profile, state)),
+        checked_at: state.and_then(|state| state.checked_at.clone()),
+        error: state.and_then(|state| state.error.clone()),
+    }
+}
+
+// 上次检查的结果，不发送请求
+pub fn statuses() -> Result<Vec<UpdateStatus>, String> {
+    let states = load_states()?;
+    Ok(profiles::list()?.iter().map(|profile| status(profile, states.get(&profile.id))).collect())
+}
+
+// 读取配置来源的清单版本；http 来源发送条件请求，本地来源按修改时间判断，未改变时保持原状态
+async fn check_profile(profile: &SyncProfile, state: &mut CheckState) -> Result<(), String> {
+    let (text, etag, last_modified) = if profile.source.starts_with("http://") || profile.source.starts_with("https://") {
+        let fetched = match manifest::fetch_manifest_if_changed(&profile.source, state.etag.as_deref(), state.last_modified.as_deref()).await? {
+            ManifestFetch::NotModified if state.latest_version.is_some() => return Ok(()),
+            // 还没有读到过版本时不带条件重新请求，同样保存新的 ETag 和 Last-Modified
+            ManifestFetch::NotModified => manifest::fetch_manifest_if_changed(&profile.source, None, None).await?,
+            fetched => fetched,
+        };
+        match fetched {
+            ManifestFetch::Modified { text, etag, last_modified } => (text, etag, last_modified),
+            ManifestFetch::NotModified => return Err(format!("{} answered 304 to an unconditional request", profile.source)),
+        }
+    } else {
+        let modified = fs::metadata(&profile.source)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| format!("Failed to read {}: {}", profile.source, e))?;
+        let modified = chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339();
+        if state.latest_version.is_some() && state.last_modified.as_deref() == Some(modified.as_str()) {
+            return Ok(());
+        }
+        (manifest::read_manifest_file(Path::new(&profile.source))?, None, Some(modified))
+    };
+
+    let manifest = manifest::parse_manifest(&text).map_err(|e| format!("Failed to parse manifest: {}", e))?;
+    state.latest_version = Some(manifest.version);
+    state.etag = etag;
+    state.last_modified = last_modified;
+    Ok(())
+}
+
+// 检查所有配置的清单；发现与上次同步不同的新版本时发送 UPDATE_AVAILABLE 并写入同步记录
+pub async fn check_all(events: &dyn EventSink) -> Result<Vec<UpdateStatus>, String> {
+    let _lock = CHECK_LOCK.lock().await;
+    let profiles = profiles::list()?;
+    let mut states = load_states()?;
+    states.retain(|id, _| profiles.iter().any(|profile| &profile.id == id));
+
+    for profile in &profiles {
+        let state = states.entry(profile.id.clone()).or_default();
+        if state.source != profile.source {
+            *state = CheckState {
+                source: profile.source.clone(),
+                last_scheduled_date: state.last_scheduled_date.take(),
+                ..CheckState::default()
+            };
+        }
+
+        let previous = state.latest_version.clone();
+        match check_profile(profile, state).await {
+            Ok(()) => {
+                state.error = None;
+                if state.latest_version != previous && is_update(profile, state) {
+                    let latest = state.latest_version.clone().unwrap_or_default();
+                    history::record(&profile.id, &profile.name, HistoryEvent::UpdateAvailable {
+                        version: latest,
+                        current_version: profile.last_synced_version.clone(),
+                    });
+                    events.emit("UPDATE_AVAILABLE", status(profile, Some(state)));
+                }
+            }
+            Err(e) => {
+                // 同样的错误只记录一次
+                if state.error.as_ref() != Some(&e) {
+                    history::record(&profile.id, &profile.name, HistoryEvent::Error { error: e.clone() });
+                }
+                state.error = Some(e);
+            }
+        }
+        state.checked_at = Some(chrono::Utc::now().to_rfc3339());
+    }
+
+    save_states(&states)?;
+    Ok(profiles.iter().map(|profile| status(profile, states.get(&profile.id))).collect())
+}
+
+// 判断电脑是否空闲：CPU 占用按两次刷新之间的平均值计算
+struct IdleMonitor {
+    system: System,
+}
+
+impl IdleMonitor {
+    fn new() -> IdleMonitor {
+        IdleMonitor { system: System::new() }
+    }
+
+    fn refresh(&mut self) {
+        self.system.refresh_cpu();
+    }
+
+    // 游戏等从目标目录启动或在目标目录中运行的进程会占用文件，此时不同步
+    fn is_idle(&mut self, target_dir: &str) -> bool {
+        if self.system.global_cpu_info().cpu_usage() > IDLE_CPU_PERCENT {
+            return false;
+        }
+        let Ok(target) = std::path::absolute(target_dir) else { return false };
+        self.system.refresh_processes();
+        !self.system.processes().values().any(|process| {
+            process.exe().is_some_and(|exe| exe.starts_with(&target))
+                || process.cwd().is_some_and(|cwd| cwd.starts_with(&target))
+        })
+    }
+}
+
+async fn start_auto_sync(events: Arc<dyn EventSink>, profile: SyncProfile, trigger: SyncTrigger) -> Result<(), String> {
+    let manifest = profiles::load_manifest(&profile).await?;
+    profiles::run(events, profile, manifest, trigger).await?;
+    Ok(())
+}
+
+// 今天已经到了定时同步的时间（HH:MM）且还没有触发过；时间无效时从不触发
+fn is_scheduled_due(time: &str, now: chrono::NaiveDateTime, last_scheduled_date: Option<&str>) -> bool {
+    let Ok(time) = chrono::NaiveTime::parse_from_str(time, "%H:%M") else { return false };
+    now.time() >= time && last_scheduled_date != Some(now.format("%Y-%m-%d").to_string().as_str())
+}
+
+// 为到了定时时间或空闲的配置开始同步；目标目录上有同步（包括其他进程的）正在进行时跳过，一次只开始一个
+async fn run_auto_syncs(events: Arc<dyn EventSink>, idle: &mut IdleMonitor) -> Result<(), String> {
+    let _lock = CHECK_LOCK.lock().await;
+    let mut states = load_states()?;
+    let now = chrono::Local::now();
+    let today = now.format("%Y-%m-%d").to_string();
+    let retry_after = chrono::Duration::minutes(settings().interval_minutes as i64);
+
+    let mut changed = false;
+    for profile in profiles::list()? {
+        let Some(state) = states.get_mut(&profile.id) else { continue };
+        let target_dir = Path::new(&profile.target_dir);
+        if session::is_running(target_dir) || transaction::is_locked(target_dir) {
+            continue;
+        }
+        let trigger = match &profile.auto_sync {
+            AutoSync::Off => continue,
+            AutoSync::Scheduled { time } => {
+                if !is_scheduled_due(time, now.naive_local(), state.last_scheduled_date.as_deref()) {
+                    continue;
+                }
+                // 到了时间即算作今天的定时同步，之后才出现的新版本等到明天
+                state.last_scheduled_date = Some(today.clone());
+                changed = true;
+                if !is_update(&profile, state) {
+                    continue;
+                }
+                SyncTrigger::Scheduled
+            }
+            AutoSync::Idle => {
+                let retrying = state.last_idle_sync_at.as_deref()
+                    .and_then(|at| chrono::DateTime::parse_from_rfc3339(at).ok())
+                    .is_some_and(|at| now.signed_duration_since(at) < retry_after);
+                if !is_update(&profile, state) || retrying || !idle.is_idle(&profile.target_dir) {
+                    continue;
+                }
+                state.last_idle_sync_at = Some(now.to_rfc3339());
+                changed = true;
+                SyncTrigger::Idle
+            }
+        };
+
+        let (id, name) = (profile.id.clone(), profile.name.clone());
+        match start_auto_sync(events.clone(), profile, trigger).await {
+            Ok(()) => break,
+            Err(e) => history::record(&id, &name, HistoryEvent::Error { error: format!("Failed to start auto sync: {}", e) }),
+        }
+    }
+
+    if changed {
+        save_states(&states)?;
+    }
+    Ok(())
+}
+
+// 后台检查更新和自动同步的循环，按设置的间隔检查，每分钟判断一次是否需要自动同步
+pub async fn run_scheduler(events: Arc<dyn EventSink>) {
+    let mut idle = IdleMonitor::new();
+    let mut last_check: Option<Instant> = None;
+    loop {
+        let settings = settings();
+        if settings.enabled {
+            idle.refresh();
+            let interval = Duration::from_secs(settings.interval_minutes * 60);
+            if last_check.is_none_or(|checked| checked.elapsed() >= interval) {
+                last_check = Some(Instant::now());
+                if let Err(e) = check_all(events.as_ref()).await {
+                    eprintln!("Failed to check for updates: {}", e);
+                }
+            }
+            if let Err(e) = run_auto_syncs(events.clone(), &mut idle).await {
+                eprintln!("Failed to run auto sync: {}", e);
+            }
+        }
+        tokio::time::sleep(TICK).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+
+    fn profile(source: &str) -> SyncProfile {
+        SyncProfile {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: "updates".to_string(),
+            source: source.to_string(),
+            target_dir: "/games/pack".to_string(),
+            trusted_keys: Vec::new(),
+            enabled_groups: None,
+            disable_hash_check: false,
+            disable_size_check: false,
+            force_rescan: false,
+            auto_sync: AutoSync::Off,
+            last_synced_version: Some("1.0".to_string()),
+            last_synced_at: None,
+        }
+    }
+
+    #[test]
+    fn schedules_once_per_day_after_the_time() {
+        let at = |time: &str| chrono::NaiveDateTime::parse_from_str(&format!("2026-10-17 {}", time), "%Y-%m-%d %H:%M").unwrap();
+        assert!(!is_scheduled_due("09:00", at("08:59"), None));
+        assert!(is_scheduled_due("09:00", at("09:00"), None));
+        assert!(is_scheduled_due("09:00", at("23:30"), Some("2026-10-16")));
+        assert!(!is_scheduled_due("09:00", at("23:30"), Some("2026-10-17")));
+        assert!(!is_scheduled_due("25:00", at("23:30"), None));
+        assert!(!is_scheduled_due("9am", at("23:30"), None));
+    }
+
+    #[tokio::test]
+    async fn checks_http_sources_with_conditional_requests() {
+        // 服务器上的版本，ETag 随版本变化
+        let version = Arc::new(Mutex::new("1.0".to_string()));
+        let (base, requests) = test_server::serve({
+            let version = version.clone();
+            move |request| {
+                let version = version.lock().unwrap().clone();
+                let etag = format!("\"{}\"", version);
+                if request.header("if-none-match") == Some(etag.as_str()) {
+                    return Response::new(304, Vec::new());
+                }
+                let manifest = serde_json::json!({"schemaVersion": 6, "packageName": "pack", "version": version, "files": []});
+                Response::new(200, manifest.to_string()).header("ETag", &etag)
+            }
+        });
+        let profile = profile(&format!("{}/manifest.json", base));
+        let if_none_match = |index: usize| requests.lock().unwrap()[index].header("if-none-match").map(str::to_string);
+
+        let mut state = CheckState::default();
+        check_profile(&profile, &mut state).await.unwrap();
+        assert_eq!(state.latest_version.as_deref(), Some("1.0"));
+        assert_eq!(state.etag.as_deref(), Some("\"1.0\""));
+        assert!(!is_update(&profile, &state));
+        assert_eq!(if_none_match(0), None);
+
+        // 未改变时服务器回应 304，状态保持不变
+        check_profile(&profile, &mut state).await.unwrap();
+        assert_eq!(if_none_match(1).as_deref(), Some("\"1.0\""));
+        assert_eq!(state.latest_version.as_deref(), Some("1.0"));
+
+        *version.lock().unwrap() = "2.0".to_string();
+        check_profile(&profile, &mut state).await.unwrap();
+        assert_eq!(state.latest_version.as_deref(), Some("2.0"));
+        assert_eq!(state.etag.as_deref(), Some("\"2.0\""));
+        assert!(is_update(&profile, &state));
+
+        // 有 ETag 但还没有读到过版本时，304 之后不带条件重新请求
+        let mut state = CheckState { etag: Some("\"2.0\"".to_string()), ..CheckState::default() };
+        check_profile(&profile, &mut state).await.unwrap();
+        assert_eq!(state.latest_version.as_deref(), Some("2.0"));
+        assert_eq!(requests.lock().unwrap().len(), 5);
+        assert_eq!(if_none_match(3).as_deref(), Some("\"2.0\""));
+        assert_eq!(if_none_match(4), None);
+    }
+}
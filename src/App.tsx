@@ -9,6 +9,7 @@ import { Sidebar, SidebarHeader, SidebarContent, SidebarNav, SidebarFooter, Auth
 import { ExportTab } from "@/components/export-tab";
 import { SyncMarket } from "@/components/sync-market";
 import { SyncConfirmation } from "@/components/sync-confirmation";
+import type { SyncProfile } from "@/components/sync-profiles";
 import { SettingsDialog } from "@/components/settings-dialog";
 import { UpdateDialog } from "@/components/update-dialog";
 import { HomePage } from "@/components/home-page";
@@ -21,14 +22,22 @@ import { authDataAtom, avatarAtom, isSidebarCollapsedAtom } from "@/atoms";
 import { SystemInfoTab } from "@/components/system-info-tab";
 import { AboutPage } from "@/components/about-page";
 import { Toaster } from "@/components/ui/toaster";
+import { useToast } from "@/hooks/use-toast";
 
 interface SyncOptions {
     manifestUrl: string;
     localPackagePath?: string;
     useLocalFiles?: boolean;
+    trustedKeys?: string[];
+    profile?: SyncProfile;
 }
 
 const THREAD_COUNT_KEY = 'sync_thread_count';
+const DOWNLOAD_LIMITS_KEY = 'sync_download_limits';
+const SNAPSHOT_RETENTION_KEY = 'sync_snapshot_retention';
+const UPDATE_CHECK_KEY = 'sync_update_check';
+// 目标目录选择器记住的目录，见 target-directory-selector
+const DIRECTORY_HISTORY_KEY = 'sync_directory_history';
 const CURRENT_VERSION = '1.2.2'; // This should be updated by the developer for each release
 
 interface UpdateInfo {
@@ -36,6 +45,12 @@ interface UpdateInfo {
     url: string;
 }
 
+// 后台检查到同步配置有新版本，见 UPDATE_AVAILABLE 事件
+interface ProfileUpdate {
+    profileName: string;
+    latestVersion: string | null;
+}
+
 interface AuthData {
   token: {
     access_token: string;
@@ -58,6 +73,7 @@ export const App = () => {
   const [, setAuthData] = useAtom(authDataAtom);
   const [, setAvatar] = useAtom(avatarAtom);
   const [isCollapsed, setIsCollapsed] = useAtom(isSidebarCollapsedAtom);
+  const { toast } = useToast();
 
 
   // 显示 Toast 消息的函数 (简易实现)
@@ -75,6 +91,24 @@ export const App = () => {
   useEffect(() => {
     const applyThreadSetting = async () => {
         try {
+            // 下载限制由后端保存，旧版本存在 localStorage 中的值迁移过去后删除
+            const storedLimits = localStorage.getItem(DOWNLOAD_LIMITS_KEY);
+            if (storedLimits) {
+                await invoke('set_download_limits', { limits: JSON.parse(storedLimits) });
+                localStorage.removeItem(DOWNLOAD_LIMITS_KEY);
+            }
+            // 快照保留策略由后端保存，旧版本存在 localStorage 中的值迁移过去后删除
+            const storedRetention = localStorage.getItem(SNAPSHOT_RETENTION_KEY);
+            if (storedRetention) {
+                await invoke('set_snapshot_retention', { retention: JSON.parse(storedRetention) });
+                localStorage.removeItem(SNAPSHOT_RETENTION_KEY);
+            }
+            // 检查更新的设置由后端保存，旧版本存在 localStorage 中的值迁移过去后删除
+            const storedUpdateCheck = localStorage.getItem(UPDATE_CHECK_KEY);
+            if (storedUpdateCheck) {
+                await invoke('set_update_check_settings', { settings: JSON.parse(storedUpdateCheck) });
+                localStorage.removeItem(UPDATE_CHECK_KEY);
+            }
             const storedCount = localStorage.getItem(THREAD_COUNT_KEY);
             if (storedCount) {
                 await invoke('set_thread_pool', { numThreads: parseInt(storedCount, 10) });
@@ -84,6 +118,18 @@ export const App = () => {
         }
     };
     
+    // 最近使用的目标目录中如有被中断的同步，启动时先回滚；配置的目标目录由后端自己处理
+    const recoverTargetDirs = async () => {
+        try {
+            const storedHistory = localStorage.getItem(DIRECTORY_HISTORY_KEY);
+            if (storedHistory) {
+                await invoke<string[]>('recover_target_dirs', { targetDirs: JSON.parse(storedHistory) });
+            }
+        } catch (e) {
+            console.error("Failed to recover interrupted syncs:", e);
+        }
+    };
+
     const checkForUpdates = async () => {
         try {
             const data = await invoke<UpdateInfo>('check_for_updates');
@@ -111,6 +157,7 @@ export const App = () => {
     };
 
     applyThreadSetting();
+    recoverTargetDirs();
     checkForUpdates();
     initializeAuth();
 
@@ -125,6 +172,13 @@ export const App = () => {
       showToast(`登录失败: ${event.payload}`);
     });
 
+    const unlistenProfileUpdate = listen<ProfileUpdate>('UPDATE_AVAILABLE', (event) => {
+      toast({
+        title: '同步配置有新版本',
+        description: `${event.payload.profileName} 可以更新到 ${event.payload.latestVersion}`,
+      });
+    });
+
     // to call set_thread_pool on launch. The default is fine for the first run.
     const setupWindowListeners = async () => {
       const handleResize = async () => {
@@ -142,12 +196,26 @@ export const App = () => {
     return () => {
         unlistenSuccess.then(f => f());
         unlistenError.then(f => f());
+        unlistenProfileUpdate.then(f => f());
         unlistenResizePromise.then(unlisten => unlisten());
     }
   }, []);
 
-  const handleSync = (url: string, localPackagePath?: string, useLocalFiles?: boolean) => {
-    setSyncOptions({ manifestUrl: url, localPackagePath, useLocalFiles });
+  const handleSync = (url: string, localPackagePath?: string, useLocalFiles?: boolean, trustedKeys?: string[]) => {
+    setSyncOptions({ manifestUrl: url, localPackagePath, useLocalFiles, trustedKeys });
+    setView('confirmation');
+  };
+
+  // 按保存的配置打开同步确认页，zip 包来源直接从包内安装
+  const handleRunProfile = (profile: SyncProfile) => {
+    const isLocalPackage = !/^https?:\/\//.test(profile.source) && profile.source.toLowerCase().endsWith('.zip');
+    setSyncOptions({
+      manifestUrl: isLocalPackage ? '' : profile.source,
+      localPackagePath: isLocalPackage ? profile.source : undefined,
+      useLocalFiles: isLocalPackage,
+      trustedKeys: profile.trustedKeys,
+      profile,
+    });
     setView('confirmation');
   };
 
@@ -208,7 +276,7 @@ export const App = () => {
         if (view === 'confirmation' && syncOptions) {
             return <SyncConfirmation syncOptions={syncOptions} onBack={handleBack} />;
         }
-        return <SyncMarket onSync={handleSync} />;
+        return <SyncMarket onSync={handleSync} onRunProfile={handleRunProfile} />;
       case "minecraft":
         return <MinecraftLauncher />;
       case "system_info":
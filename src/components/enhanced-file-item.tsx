@@ -1,9 +1,51 @@
 import { useState } from 'react';
 import { Checkbox } from "@/components/ui/checkbox";
-import { Folder, Archive, Package, Settings } from "lucide-react";
+import { Folder, Archive, Package, Settings, Blocks, FileArchive, Layers, Monitor } from "lucide-react";
 import { Button } from '@/components/ui/button';
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from '@/components/ui/select';
 import { ExclusionManagerDialog } from './exclusion-manager-dialog';
 
+// 包中条目的压缩方式，未设置时文件和压缩文件夹使用 Deflate，不压缩的文件夹直接存储
+export interface Compression {
+  method: 'stored' | 'deflate' | 'zstd' | 'bzip2';
+  level?: number;
+}
+
+// 玩家可以自行开关的可选文件组，导出项通过 group 引用其 id
+export interface FileGroup {
+  id: string;
+  name: string;
+  description?: string;
+  enabledByDefault: boolean;
+}
+
+// 只在满足条件的机器上同步，设置了的每一项都要满足
+export interface FileCondition {
+  os?: string[];
+  arch?: string[];
+  gameVersion?: string[];
+  loader?: string[];
+}
+
+const OS_OPTIONS = [
+  { value: 'windows', label: 'Windows' },
+  { value: 'linux', label: 'Linux' },
+  { value: 'macos', label: 'macOS' },
+];
+
+// 逗号分隔的输入转为列表，空输入表示不限制
+const parseList = (text: string) => {
+  const list = text.split(',').map(item => item.trim()).filter(Boolean);
+  return list.length > 0 ? list : undefined;
+};
+
+const COMPRESSION_LEVELS: Record<Compression['method'], string> = {
+  stored: '',
+  deflate: '0-9',
+  zstd: '-7-22',
+  bzip2: '1-9',
+};
+
 interface FileItem {
   path: string;
   name: string;
@@ -12,26 +54,53 @@ interface FileItem {
   selected: boolean;
   compress?: boolean;
   isUpdatePackage?: boolean;
+  chunked?: boolean;
+  compression?: Compression;
+  group?: string;
+  conditions?: FileCondition;
   exclusions?: string[];
 }
 
 interface EnhancedFileItemProps {
   file: FileItem;
+  groups: FileGroup[];
   onToggleSelection: (path: string, checked: boolean) => void;
   onToggleCompression: (path: string, checked: boolean) => void;
   onToggleUpdatePackage: (path: string, checked: boolean) => void;
+  onToggleChunked: (path: string, checked: boolean) => void;
+  onUpdateCompression: (path: string, compression?: Compression) => void;
+  onUpdateGroup: (path: string, group?: string) => void;
+  onUpdateConditions: (path: string, conditions?: FileCondition) => void;
   onUpdateExclusions: (path: string, exclusions: string[]) => void;
 }
 
 export function EnhancedFileItem({ 
   file, 
+  groups,
   onToggleSelection, 
   onToggleCompression, 
   onToggleUpdatePackage,
+  onToggleChunked,
+  onUpdateCompression,
+  onUpdateGroup,
+  onUpdateConditions,
   onUpdateExclusions,
 }: EnhancedFileItemProps) {
   const [isExclusionManagerOpen, setIsExclusionManagerOpen] = useState(false);
 
+  // 所有条件都清空时不再写入 conditions
+  const updateCondition = (changes: Partial<FileCondition>) => {
+    const next = { ...file.conditions, ...changes };
+    const isEmpty = !next.os && !next.arch && !next.gameVersion && !next.loader;
+    onUpdateConditions(file.path, isEmpty ? undefined : next);
+  };
+
+  const toggleOs = (os: string, checked: boolean) => {
+    const current = file.conditions?.os ?? [];
+    const list = checked ? [...current, os] : current.filter(item => item !== os);
+    updateCondition({ os: list.length > 0 ? list : undefined });
+  };
+
   const formatFileSize = (bytes?: number) => {
     if (!bytes) return "";
     const units = ["B", "KB", "MB", "GB"];
@@ -85,6 +154,84 @@ export function EnhancedFileItem({
           </div>
         </div>
         
+        {/* 可选组：玩家关闭该组时不同步此项 */}
+        {groups.length > 0 && (
+          <div className="ml-8 flex items-center space-x-3 border-l-2 border-muted pl-4">
+            <Layers className="h-4 w-4 text-sky-500" />
+            <span className="text-sm">可选组</span>
+            <Select
+              value={file.group ?? 'none'}
+              onValueChange={(v) => onUpdateGroup(file.path, v === 'none' ? undefined : v)}
+            >
+              <SelectTrigger className="w-[160px] h-8">
+                <SelectValue />
+              </SelectTrigger>
+              <SelectContent>
+                <SelectItem value="none">必需（总是同步）</SelectItem>
+                {groups.filter(group => group.id).map(group => (
+                  <SelectItem key={group.id} value={group.id}>{group.name || group.id}</SelectItem>
+                ))}
+              </SelectContent>
+            </Select>
+          </div>
+        )}
+
+        {/* 适用平台：不满足条件的机器既不下载也不清理此项 */}
+        <div className="ml-8 space-y-2 border-l-2 border-muted pl-4">
+          <div className="flex items-center space-x-3">
+            <Monitor className="h-4 w-4 text-slate-500" />
+            <span className="text-sm">适用系统</span>
+            {OS_OPTIONS.map(option => (
+              <div key={option.value} className="flex items-center space-x-1">
+                <Checkbox
+                  checked={file.conditions?.os?.includes(option.value) ?? false}
+                  onCheckedChange={(checked) => toggleOs(option.value, !!checked)}
+                />
+                <span className="text-sm">{option.label}</span>
+              </div>
+            ))}
+          </div>
+          <div className="flex items-center space-x-2">
+            <input
+              type="text"
+              defaultValue={file.conditions?.arch?.join(', ') ?? ''}
+              onBlur={(e) => updateCondition({ arch: parseList(e.target.value) })}
+              className="w-1/3 px-2 py-1 border rounded-md text-sm"
+              placeholder="架构，如 x86_64"
+            />
+            <input
+              type="text"
+              defaultValue={file.conditions?.gameVersion?.join(', ') ?? ''}
+              onBlur={(e) => updateCondition({ gameVersion: parseList(e.target.value) })}
+              className="w-1/3 px-2 py-1 border rounded-md text-sm"
+              placeholder="游戏版本，如 1.20.*"
+            />
+            <input
+              type="text"
+              defaultValue={file.conditions?.loader?.join(', ') ?? ''}
+              onBlur={(e) => updateCondition({ loader: parseList(e.target.value) })}
+              className="w-1/3 px-2 py-1 border rounded-md text-sm"
+              placeholder="加载器，如 fabric"
+            />
+          </div>
+          <span className="text-xs text-muted-foreground">不勾选、不填写表示不限制；不满足条件的机器既不下载也不清理此项</span>
+        </div>
+
+        {/* 分块选项：大文件按内容分块，更新时只下载变化的块 */}
+        {!file.isDirectory && (
+          <div className="ml-8 flex items-center space-x-3 border-l-2 border-muted pl-4">
+            <Checkbox
+              checked={file.chunked || false}
+              onCheckedChange={(checked) => onToggleChunked(file.path, !!checked)}
+            />
+            <Blocks className="h-4 w-4 text-purple-500" />
+            <div className="flex flex-col">
+              <span className="text-sm">分块传输</span>
+              <span className="text-xs text-muted-foreground">大于 8MB 时按内容分块，更新时只下载变化的部分</span>
+            </div>
+          </div>
+        )}
+
         {/* 文件夹选项 */}
         {file.isDirectory && (
           <div className="ml-8 space-y-3 border-l-2 border-muted pl-4">
@@ -121,6 +268,60 @@ export function EnhancedFileItem({
                 </span>
               </div>
             </div>
+            {/* 分块选项 */}
+            <div className="flex items-center space-x-3">
+              <Checkbox
+                checked={file.chunked || false}
+                disabled={file.compress}
+                onCheckedChange={(checked) => onToggleChunked(file.path, !!checked)}
+              />
+              <Blocks className={`h-4 w-4 ${file.compress ? 'text-muted-foreground' : 'text-purple-500'}`} />
+              <div className="flex flex-col">
+                <span className={`text-sm ${file.compress ? 'text-muted-foreground' : 'text-foreground'}`}>
+                  大文件分块传输
+                </span>
+                <span className="text-xs text-muted-foreground">
+                  {file.compress ? '压缩的文件夹不支持分块' : '大于 8MB 的文件按内容分块，更新时只下载变化的部分'}
+                </span>
+              </div>
+            </div>
+            {/* 压缩方式 */}
+            <div className="flex items-center space-x-3">
+              <FileArchive className="h-4 w-4 text-green-600" />
+              <div className="flex flex-col flex-1">
+                <div className="flex items-center space-x-2">
+                  <span className="text-sm">压缩方式</span>
+                  <Select
+                    value={file.compression?.method ?? 'default'}
+                    onValueChange={(v) => onUpdateCompression(file.path, v === 'default' ? undefined : { method: v as Compression['method'] })}
+                  >
+                    <SelectTrigger className="w-[120px] h-8">
+                      <SelectValue />
+                    </SelectTrigger>
+                    <SelectContent>
+                      <SelectItem value="default">默认</SelectItem>
+                      <SelectItem value="stored">不压缩</SelectItem>
+                      <SelectItem value="deflate">Deflate</SelectItem>
+                      <SelectItem value="zstd">Zstd</SelectItem>
+                      <SelectItem value="bzip2">Bzip2</SelectItem>
+                    </SelectContent>
+                  </Select>
+                  {file.compression && file.compression.method !== 'stored' && (
+                    <input
+                      type="number"
+                      value={file.compression.level ?? ''}
+                      onChange={(e) => onUpdateCompression(file.path, {
+                        ...file.compression!,
+                        level: e.target.value === '' ? undefined : Number(e.target.value),
+                      })}
+                      className="w-20 px-2 py-1 border rounded-md text-sm"
+                      placeholder={`级别 ${COMPRESSION_LEVELS[file.compression.method]}`}
+                    />
+                  )}
+                </div>
+                <span className="text-xs text-muted-foreground">.jar、.png、.ogg 等已压缩的格式始终直接存储</span>
+              </div>
+            </div>
             <div className="flex items-center space-x-3">
                 <Button variant="outline" size="sm" onClick={() => setIsExclusionManagerOpen(true)}>
                     <Settings className="h-4 w-4 mr-2" />
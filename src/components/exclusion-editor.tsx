@@ -9,6 +9,7 @@ import {
   DialogClose,
 } from './ui/dialog';
 import { ScrollArea } from './ui/scroll-area';
+import { Input } from './ui/input';
 import { X, Plus } from 'lucide-react';
 import { open } from '@tauri-apps/api/dialog';
 import { invoke } from '@tauri-apps/api';
@@ -29,6 +30,7 @@ export function ExclusionEditor({
   onExclusionChange,
 }: ExclusionEditorProps) {
   const [excludedFiles, setExcludedFiles] = useState<string[]>(initialExcludedFiles);
+  const [pattern, setPattern] = useState('');
 
   useEffect(() => {
     setExcludedFiles(initialExcludedFiles);
@@ -55,6 +57,14 @@ export function ExclusionEditor({
     }
   };
 
+  // gitignore 风格的规则，如 saves/、*.log、!keep.log
+  const handleAddPattern = () => {
+    const trimmed = pattern.trim();
+    if (!trimmed) return;
+    setExcludedFiles(prev => [...new Set([...prev, trimmed])]);
+    setPattern('');
+  };
+
   const handleRemoveFile = (fileToRemove: string) => {
     setExcludedFiles(prev => prev.filter(file => file !== fileToRemove));
   };
@@ -77,7 +87,19 @@ export function ExclusionEditor({
           <DialogTitle>Manage Exclusion List</DialogTitle>
         </DialogHeader>
         <div className="py-4">
-          <div className="flex justify-end mb-4">
+          <div className="flex gap-2 mb-4">
+            <Input
+              value={pattern}
+              onChange={e => setPattern(e.target.value)}
+              onKeyDown={e => {
+                if (e.key === 'Enter') handleAddPattern();
+              }}
+              placeholder="Pattern, e.g. saves/, *.log, !keep.log"
+              className="h-9"
+            />
+            <Button onClick={handleAddPattern} size="sm" variant="outline" disabled={!pattern.trim()}>
+              Add Pattern
+            </Button>
             <Button onClick={handleAddFiles} size="sm">
               <Plus className="mr-2 h-4 w-4" /> Add Files
             </Button>
@@ -90,7 +112,7 @@ export function ExclusionEditor({
                 <ul className="space-y-2">
                   {excludedFiles.map(file => (
                     <li key={file} className="flex items-center justify-between p-2 bg-muted rounded-md text-sm">
-                      <span>{file}</span>
+                      <span className="font-mono break-all">{file}</span>
                       <Button variant="ghost" size="icon" onClick={() => handleRemoveFile(file)}>
                         <X className="h-4 w-4" />
                       </Button>
@@ -9,8 +9,9 @@ import {
 } from './ui/dialog';
 import { ScrollArea } from './ui/scroll-area';
 import { Checkbox } from './ui/checkbox';
+import { Input } from './ui/input';
 import { invoke } from '@tauri-apps/api';
-import { Folder, File as FileIcon } from 'lucide-react';
+import { Folder, File as FileIcon, X } from 'lucide-react';
 
 interface FileNode {
     path: string;
@@ -66,6 +67,9 @@ const FileTreeItem = ({ node, excludedPaths, onToggle }: { node: FileNode; exclu
 export function ExclusionManagerDialog({ isOpen, onClose, basePath, onSave, initialExclusions }: ExclusionManagerDialogProps) {
     const [fileTree, setFileTree] = useState<FileNode[]>([]);
     const [excludedPaths, setExcludedPaths] = useState(new Set(initialExclusions));
+    const [pattern, setPattern] = useState('');
+    // 勾选的条目保存为绝对路径，其余为 gitignore 风格的规则
+    const patterns = Array.from(excludedPaths).filter(path => !path.startsWith(basePath));
 
     useEffect(() => {
         if (isOpen && basePath) {
@@ -85,6 +89,13 @@ export function ExclusionManagerDialog({ isOpen, onClose, basePath, onSave, init
         });
     };
 
+    const handleAddPattern = () => {
+        const trimmed = pattern.trim();
+        if (!trimmed) return;
+        handleToggle(trimmed, true);
+        setPattern('');
+    };
+
     const handleSave = () => {
         onSave(Array.from(excludedPaths));
         onClose();
@@ -101,6 +112,32 @@ export function ExclusionManagerDialog({ isOpen, onClose, basePath, onSave, init
                         {fileTree.map(node => <FileTreeItem key={node.path} node={node} excludedPaths={excludedPaths} onToggle={handleToggle} />)}
                     </div>
                 </ScrollArea>
+                <div className="space-y-2">
+                    <div className="flex gap-2">
+                        <Input
+                            value={pattern}
+                            onChange={e => setPattern(e.target.value)}
+                            onKeyDown={e => {
+                                if (e.key === 'Enter') handleAddPattern();
+                            }}
+                            placeholder="排除规则，如 *.log、saves/、!keep.log"
+                            className="h-9"
+                        />
+                        <Button variant="outline" size="sm" onClick={handleAddPattern} disabled={!pattern.trim()}>添加规则</Button>
+                    </div>
+                    {patterns.length > 0 && (
+                        <div className="flex flex-wrap gap-2">
+                            {patterns.map(rule => (
+                                <span key={rule} className="flex items-center gap-1 px-2 py-1 bg-muted rounded-md text-xs font-mono">
+                                    {rule}
+                                    <button type="button" onClick={() => handleToggle(rule, false)}>
+                                        <X className="h-3 w-3" />
+                                    </button>
+                                </span>
+                            ))}
+                        </div>
+                    )}
+                </div>
                 <DialogFooter>
                     <Button variant="outline" onClick={onClose}>取消</Button>
                     <Button onClick={handleSave}>保存</Button>
@@ -4,11 +4,13 @@ import { open, message, save } from "@tauri-apps/api/dialog";
 import { Button } from "@/components/ui/button";
 import { Card, CardContent, CardDescription, CardHeader, CardTitle } from "@/components/ui/card";
 import { FolderOpen, Download, Trash2 } from "lucide-react";
-import { EnhancedFileItem } from "@/components/enhanced-file-item";
+import { EnhancedFileItem, Compression, FileCondition, FileGroup } from "@/components/enhanced-file-item";
 import { Textarea } from "@/components/ui/textarea";
 import { Checkbox } from "@/components/ui/checkbox";
 import { Progress } from "@/components/ui/progress";
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
 import { listen } from "@tauri-apps/api/event";
+import { formatBytes } from "@/lib/utils";
 
 interface FileItem {
   path: string;
@@ -18,6 +20,10 @@ interface FileItem {
   selected: boolean;
   compress?: boolean; // 是否压缩该文件夹
   isUpdatePackage?: boolean; // 是否为压缩包更新（减少服务器请求）
+  chunked?: boolean; // 是否对大文件按内容分块
+  compression?: Compression; // 包中条目的压缩方式
+  group?: string; // 所属可选组的 id
+  conditions?: FileCondition; // 适用的系统、架构、游戏版本和加载器
   exclusions?: string[];
 }
 
@@ -25,6 +31,8 @@ interface ExportProgress {
     total: number;
     current: number;
     fileName: string;
+    totalBytes: number;
+    processedBytes: number;
 }
 
 export function ExportTab() {
@@ -39,7 +47,15 @@ export function ExportTab() {
     description: "# ACGStation - 包名（允许使用markdown）\n\n使用的基础客户端: Java【版本】+Forge【最新版】+Minecraft【版本】\n\n使用教程：请选中对应的.minecraft/versions/对应的游戏文件",
     disableHashCheck: false,
     disableSizeCheck: false,
+    previousPackage: "",
+    previousManifest: "",
+    outputMode: "zip" as "zip" | "directory" | "manifest_only",
+    maxPartSize: null as number | null,
+    groups: [] as FileGroup[],
+    signingKey: "",
   });
+  // 同步前后动作，格式与清单的 hooks 字段相同，留空表示没有动作
+  const [hooksText, setHooksText] = useState("");
 
   useEffect(() => {
     let unlisten: () => void;
@@ -57,6 +73,53 @@ export function ExportTab() {
     };
   }, []);
 
+  const selectPreviousPackage = async () => {
+    const selected = await open({
+      multiple: false,
+      title: "选择上一版本的导出包",
+      filters: [{ name: 'Zip Archive', extensions: ['zip'] }]
+    });
+    if (selected && typeof selected === "string") {
+      setExportSettings(prev => ({ ...prev, previousPackage: selected }));
+    }
+  };
+
+  const selectPreviousManifest = async () => {
+    const selected = await open({
+      multiple: false,
+      title: "选择上一版本的清单或导出包",
+      filters: [{ name: 'Manifest', extensions: ['json', 'zip'] }]
+    });
+    if (selected && typeof selected === "string") {
+      setExportSettings(prev => ({ ...prev, previousManifest: selected }));
+    }
+  };
+
+  const selectSigningKey = async () => {
+    const selected = await open({
+      multiple: false,
+      title: "选择签名私钥文件",
+    });
+    if (selected && typeof selected === "string") {
+      setExportSettings(prev => ({ ...prev, signingKey: selected }));
+    }
+  };
+
+  const generateSigningKey = async () => {
+    const savePath = await save({
+      title: "保存签名私钥",
+      defaultPath: "acgstation-signing.key",
+    });
+    if (!savePath) return;
+    try {
+      const publicKey = await invoke<string>("generate_signing_key", { savePath });
+      setExportSettings(prev => ({ ...prev, signingKey: savePath }));
+      await message(`私钥已保存到 ${savePath}，请妥善保管，不要上传到服务器。\n\n公钥（提供给玩家或写入同步市场）:\n${publicKey}`, { title: "签名密钥已生成", type: "info" });
+    } catch (error) {
+      await message(`生成签名密钥失败: ${error}`, { title: "错误", type: "error" });
+    }
+  };
+
   const selectFolder = async () => {
     try {
       const selected = await open({
@@ -83,7 +146,8 @@ export function ExportTab() {
         ...item, 
         selected: false, 
         compress: false, 
-        isUpdatePackage: false 
+        isUpdatePackage: false,
+        chunked: false
       })));
     } catch (error) {
       console.error("加载文件夹内容失败:", error);
@@ -100,7 +164,13 @@ export function ExportTab() {
 
   const toggleCompression = (path: string, checked: boolean) => {
     setFiles(files.map(file => 
-      file.path === path ? { ...file, compress: checked, isUpdatePackage: checked ? file.isUpdatePackage : false } : file
+      file.path === path ? { ...file, compress: checked, isUpdatePackage: checked ? file.isUpdatePackage : false, chunked: checked ? false : file.chunked } : file
+    ));
+  };
+
+  const toggleChunked = (path: string, checked: boolean) => {
+    setFiles(files.map(file => 
+      file.path === path ? { ...file, chunked: checked } : file
     ));
   };
 
@@ -110,6 +180,49 @@ export function ExportTab() {
     ));
   };
 
+  const updateCompression = (path: string, compression?: Compression) => {
+    setFiles(files.map(file => 
+      file.path === path ? { ...file, compression } : file
+    ));
+  };
+
+  const updateGroup = (path: string, group?: string) => {
+    setFiles(files.map(file => 
+      file.path === path ? { ...file, group } : file
+    ));
+  };
+
+  const updateConditions = (path: string, conditions?: FileCondition) => {
+    setFiles(files.map(file => 
+      file.path === path ? { ...file, conditions } : file
+    ));
+  };
+
+  const addGroup = () => {
+    setExportSettings(prev => ({
+      ...prev,
+      groups: [...prev.groups, { id: `group${prev.groups.length + 1}`, name: "", description: "", enabledByDefault: false }],
+    }));
+  };
+
+  const updateGroupDefinition = (index: number, changes: Partial<FileGroup>) => {
+    const oldId = exportSettings.groups[index].id;
+    setExportSettings(prev => ({
+      ...prev,
+      groups: prev.groups.map((group, i) => i === index ? { ...group, ...changes } : group),
+    }));
+    // 修改 id 时同步更新已分配到该组的导出项
+    if (changes.id !== undefined && changes.id !== oldId) {
+      setFiles(files.map(file => file.group === oldId ? { ...file, group: changes.id } : file));
+    }
+  };
+
+  const removeGroup = (index: number) => {
+    const removedId = exportSettings.groups[index].id;
+    setExportSettings(prev => ({ ...prev, groups: prev.groups.filter((_, i) => i !== index) }));
+    setFiles(files.map(file => file.group === removedId ? { ...file, group: undefined } : file));
+  };
+
   const updateExclusions = (path: string, exclusions: string[]) => {
     setFiles(files.map(file => 
       file.path === path ? { ...file, exclusions } : file
@@ -122,7 +235,7 @@ export function ExportTab() {
   };
 
   const clearSelection = () => {
-    setFiles(files.map(file => ({ ...file, selected: false, compress: false, isUpdatePackage: false })));
+    setFiles(files.map(file => ({ ...file, selected: false, compress: false, isUpdatePackage: false, chunked: false })));
   };
 
   const exportFiles = async () => {
@@ -132,20 +245,41 @@ export function ExportTab() {
       return;
     }
 
-    const savePath = await save({
-      defaultPath: `${exportSettings.packageName || "export"}.zip`,
-      filters: [{ name: 'Zip Archive', extensions: ['zip'] }]
-    });
+    let hooks = null;
+    if (hooksText.trim()) {
+      try {
+        hooks = JSON.parse(hooksText);
+      } catch (error) {
+        await message(`同步动作不是有效的 JSON: ${error}`, { title: "错误", type: "error" });
+        return;
+      }
+    }
 
-    if (!savePath) return;
+    // 目录模式选择输出目录，仅清单模式保存 manifest.json，否则保存为zip
+    let savePath: string | string[] | null;
+    if (exportSettings.outputMode === "directory") {
+      savePath = await open({ directory: true, title: "选择输出目录（可直接上传到静态托管）" });
+    } else if (exportSettings.outputMode === "manifest_only") {
+      savePath = await save({
+        defaultPath: "manifest.json",
+        filters: [{ name: 'Manifest', extensions: ['json'] }]
+      });
+    } else {
+      savePath = await save({
+        defaultPath: `${exportSettings.packageName || "export"}.zip`,
+        filters: [{ name: 'Zip Archive', extensions: ['zip'] }]
+      });
+    }
+
+    if (!savePath || typeof savePath !== "string") return;
 
     setIsLoading(true);
-    setExportProgress({ total: selectedFiles.length, current: 0, fileName: 'Starting...' });
+    setExportProgress({ total: 0, current: 0, fileName: 'Starting...', totalBytes: 0, processedBytes: 0 });
     try {
       // 执行导出
       const result = await invoke("export_files", {
         files: selectedFiles,
-        settings: exportSettings,
+        settings: { ...exportSettings, hooks },
         savePathStr: savePath
       });
       await message(`导出成功！\n${result}`, { title: "成功", type: "info" });
@@ -193,9 +327,14 @@ export function ExportTab() {
                 <EnhancedFileItem
                   key={file.path}
                   file={file}
+                  groups={exportSettings.groups}
                   onToggleSelection={toggleSelection}
                   onToggleCompression={toggleCompression}
                   onToggleUpdatePackage={toggleUpdatePackage}
+                  onToggleChunked={toggleChunked}
+                  onUpdateCompression={updateCompression}
+                  onUpdateGroup={updateGroup}
+                  onUpdateConditions={updateConditions}
                   onUpdateExclusions={updateExclusions}
                 />
               ))}
@@ -257,16 +396,17 @@ export function ExportTab() {
               </div>
               <div>
                 <label className="text-sm font-medium">下载链接前缀</label>
-                <input
-                  type="text"
+                <Textarea
                   value={exportSettings.downloadPrefix}
                   onChange={(e) => setExportSettings(prev => ({
                     ...prev,
                     downloadPrefix: e.target.value
                   }))}
-                  className="w-full mt-1 px-3 py-2 border rounded-md"
-                  placeholder="https://example.com/downloads/"
+                  className="w-full mt-1 font-mono text-sm"
+                  rows={2}
+                  placeholder={"https://example.com/downloads/\nhttps://mirror.example.com/downloads/"}
                 />
+                <p className="text-xs text-muted-foreground mt-1">每行一个前缀，第一个为主地址，其余作为镜像在主地址失败时自动切换</p>
               </div>
               <div>
                 <label className="text-sm font-medium">版本</label>
@@ -281,6 +421,90 @@ export function ExportTab() {
                   placeholder="1.0.0"
                 />
               </div>
+              <div>
+                <label className="text-sm font-medium">输出方式</label>
+                <Select
+                  value={exportSettings.outputMode}
+                  onValueChange={(v) => setExportSettings(prev => ({ ...prev, outputMode: v as typeof prev.outputMode }))}
+                >
+                  <SelectTrigger className="w-full mt-1">
+                    <SelectValue />
+                  </SelectTrigger>
+                  <SelectContent>
+                    <SelectItem value="zip">单个ZIP包</SelectItem>
+                    <SelectItem value="directory">目录（按下载地址排布，可直接上传CDN）</SelectItem>
+                    <SelectItem value="manifest_only">仅清单（文件已托管在下载前缀下，不支持压缩文件夹）</SelectItem>
+                  </SelectContent>
+                </Select>
+              </div>
+              <div>
+                <label className="text-sm font-medium">分卷大小（MB，可选，压缩文件夹超过时切分为多个分卷）</label>
+                <input
+                  type="number"
+                  min={1}
+                  value={exportSettings.maxPartSize ? exportSettings.maxPartSize / 1024 / 1024 : ""}
+                  onChange={(e) => setExportSettings(prev => ({
+                    ...prev,
+                    maxPartSize: e.target.value === "" ? null : Math.floor(Number(e.target.value) * 1024 * 1024)
+                  }))}
+                  className="w-full mt-1 px-3 py-2 border rounded-md"
+                  placeholder="不切分"
+                />
+              </div>
+              <div>
+                <div className="flex items-center justify-between">
+                  <label className="text-sm font-medium">可选文件组（玩家同步时可以自行开关）</label>
+                  <Button onClick={addGroup} variant="outline" size="sm">添加</Button>
+                </div>
+                {exportSettings.groups.map((group, index) => (
+                  <div key={index} className="mt-2 p-2 border rounded-md space-y-2">
+                    <div className="flex space-x-2">
+                      <input
+                        type="text"
+                        value={group.id}
+                        onChange={(e) => updateGroupDefinition(index, { id: e.target.value })}
+                        className="w-1/3 px-2 py-1 border rounded-md text-sm"
+                        placeholder="ID"
+                      />
+                      <input
+                        type="text"
+                        value={group.name}
+                        onChange={(e) => updateGroupDefinition(index, { name: e.target.value })}
+                        className="flex-1 px-2 py-1 border rounded-md text-sm"
+                        placeholder="名称，如：光影"
+                      />
+                      <Button onClick={() => removeGroup(index)} variant="ghost" size="sm">
+                        <Trash2 className="h-4 w-4" />
+                      </Button>
+                    </div>
+                    <input
+                      type="text"
+                      value={group.description ?? ""}
+                      onChange={(e) => updateGroupDefinition(index, { description: e.target.value })}
+                      className="w-full px-2 py-1 border rounded-md text-sm"
+                      placeholder="说明（可选）"
+                    />
+                    <div className="flex items-center space-x-2">
+                      <Checkbox
+                        id={`group-default-${index}`}
+                        checked={group.enabledByDefault}
+                        onCheckedChange={(checked) => updateGroupDefinition(index, { enabledByDefault: !!checked })}
+                      />
+                      <label htmlFor={`group-default-${index}`} className="text-sm">默认启用</label>
+                    </div>
+                  </div>
+                ))}
+              </div>
+              <div>
+                <label className="text-sm font-medium">同步动作（可选，JSON）</label>
+                <Textarea
+                  value={hooksText}
+                  onChange={(e) => setHooksText(e.target.value)}
+                  className="w-full mt-1 font-mono text-xs"
+                  placeholder={'{"postSync": [{"action": "delete", "pattern": "mods/*.old"}, {"action": "writeFile", "path": "config/pack.txt", "template": "{packageName} {version}"}]}'}
+                  rows={4}
+                />
+              </div>
               <div>
                 <label className="text-sm font-medium">简介</label>
                 <Textarea
@@ -314,6 +538,55 @@ export function ExportTab() {
                   禁用大小校验
                 </label>
               </div>
+              <div>
+                <label className="text-sm font-medium">上一版本导出包（可选，用于生成差量补丁）</label>
+                <div className="flex space-x-2 mt-1">
+                  <input
+                    type="text"
+                    value={exportSettings.previousPackage}
+                    readOnly
+                    className="flex-1 px-3 py-2 border rounded-md"
+                    placeholder="未选择则不生成补丁"
+                  />
+                  <Button onClick={selectPreviousPackage} variant="outline" size="sm">选择</Button>
+                  {exportSettings.previousPackage && (
+                    <Button onClick={() => setExportSettings(prev => ({ ...prev, previousPackage: "" }))} variant="ghost" size="sm">清除</Button>
+                  )}
+                </div>
+              </div>
+              <div>
+                <label className="text-sm font-medium">上一版本清单（可选，增量导出，只打包新增或变化的文件）</label>
+                <div className="flex space-x-2 mt-1">
+                  <input
+                    type="text"
+                    value={exportSettings.previousManifest}
+                    readOnly
+                    className="flex-1 px-3 py-2 border rounded-md"
+                    placeholder="未选择则完整导出"
+                  />
+                  <Button onClick={selectPreviousManifest} variant="outline" size="sm">选择</Button>
+                  {exportSettings.previousManifest && (
+                    <Button onClick={() => setExportSettings(prev => ({ ...prev, previousManifest: "" }))} variant="ghost" size="sm">清除</Button>
+                  )}
+                </div>
+              </div>
+              <div>
+                <label className="text-sm font-medium">签名私钥（可选，用 Ed25519 签名清单）</label>
+                <div className="flex space-x-2 mt-1">
+                  <input
+                    type="text"
+                    value={exportSettings.signingKey}
+                    readOnly
+                    className="flex-1 px-3 py-2 border rounded-md"
+                    placeholder="未选择则不签名"
+                  />
+                  <Button onClick={selectSigningKey} variant="outline" size="sm">选择</Button>
+                  <Button onClick={generateSigningKey} variant="outline" size="sm">生成</Button>
+                  {exportSettings.signingKey && (
+                    <Button onClick={() => setExportSettings(prev => ({ ...prev, signingKey: "" }))} variant="ghost" size="sm">清除</Button>
+                  )}
+                </div>
+              </div>
             </CardContent>
           </Card>
 
@@ -322,9 +595,9 @@ export function ExportTab() {
             <CardContent className="pt-6">
                 {isLoading && exportProgress ? (
                     <div className="space-y-2">
-                        <Progress value={(exportProgress.current / exportProgress.total) * 100} />
+                        <Progress value={exportProgress.totalBytes > 0 ? (exportProgress.processedBytes / exportProgress.totalBytes) * 100 : 0} />
                         <div className="text-xs text-center text-muted-foreground">
-                            ({exportProgress.current}/{exportProgress.total}) 正在处理: {exportProgress.fileName}
+                            ({exportProgress.current}/{exportProgress.total}, {formatBytes(exportProgress.processedBytes)}/{formatBytes(exportProgress.totalBytes)}) 正在处理: {exportProgress.fileName}
                         </div>
                     </div>
                 ) : (
@@ -18,8 +18,29 @@ interface SettingsDialogProps {
 
 const THREAD_COUNT_KEY = 'sync_thread_count';
 
+interface DownloadLimits {
+  maxConcurrent: number;
+  globalBytesPerSec: number;
+  perHostBytesPerSec: number;
+}
+
+// 每个同步目录保留的快照
+interface SnapshotRetention {
+  maxSnapshots: number;
+  maxBytes: number;
+}
+
+// 后台检查同步配置的更新，自动同步也依赖它
+interface UpdateCheckSettings {
+  enabled: boolean;
+  intervalMinutes: number;
+}
+
 export function SettingsDialog({ isOpen, onClose }: SettingsDialogProps) {
   const [threadCount, setThreadCount] = useState(0); // 0 means use Rayon's default
+  const [downloadLimits, setDownloadLimits] = useState<DownloadLimits>({ maxConcurrent: 8, globalBytesPerSec: 0, perHostBytesPerSec: 0 });
+  const [snapshotRetention, setSnapshotRetention] = useState<SnapshotRetention>({ maxSnapshots: 5, maxBytes: 0 });
+  const [updateCheck, setUpdateCheck] = useState<UpdateCheckSettings>({ enabled: true, intervalMinutes: 30 });
 
   useEffect(() => {
     try {
@@ -35,10 +56,22 @@ export function SettingsDialog({ isOpen, onClose }: SettingsDialogProps) {
     } catch (e) {
       console.error('Failed to load thread count:', e);
     }
+    invoke<DownloadLimits>('get_download_limits').then(setDownloadLimits).catch(e => {
+      console.error('Failed to load download limits:', e);
+    });
+    invoke<SnapshotRetention>('get_snapshot_retention').then(setSnapshotRetention).catch(e => {
+      console.error('Failed to load snapshot retention:', e);
+    });
+    invoke<UpdateCheckSettings>('get_update_check_settings').then(setUpdateCheck).catch(e => {
+      console.error('Failed to load update check settings:', e);
+    });
   }, []);
 
   const handleSave = async () => {
     try {
+      await invoke('set_download_limits', { limits: downloadLimits });
+      await invoke('set_snapshot_retention', { retention: snapshotRetention });
+      await invoke('set_update_check_settings', { settings: updateCheck });
       localStorage.setItem(THREAD_COUNT_KEY, threadCount.toString());
       await invoke('set_thread_pool', { numThreads: threadCount });
       onClose();
@@ -73,6 +106,102 @@ export function SettingsDialog({ isOpen, onClose }: SettingsDialogProps) {
               设置为0将使用默认线程数（通常是CPU核心数）。
             </p>
           </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="max-concurrent" className="text-right">
+              并发下载数
+            </Label>
+            <input
+              id="max-concurrent"
+              type="number"
+              value={downloadLimits.maxConcurrent}
+              onChange={(e) => setDownloadLimits(prev => ({ ...prev, maxConcurrent: Math.max(1, parseInt(e.target.value, 10) || 1) }))}
+              className="col-span-3 p-2 border rounded-md"
+              min="1"
+            />
+          </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="global-limit" className="text-right">
+              总限速 (KB/s)
+            </Label>
+            <input
+              id="global-limit"
+              type="number"
+              value={Math.round(downloadLimits.globalBytesPerSec / 1024)}
+              onChange={(e) => setDownloadLimits(prev => ({ ...prev, globalBytesPerSec: Math.max(0, parseInt(e.target.value, 10) || 0) * 1024 }))}
+              className="col-span-3 p-2 border rounded-md"
+              min="0"
+            />
+          </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="per-host-limit" className="text-right">
+              单服务器限速 (KB/s)
+            </Label>
+            <input
+              id="per-host-limit"
+              type="number"
+              value={Math.round(downloadLimits.perHostBytesPerSec / 1024)}
+              onChange={(e) => setDownloadLimits(prev => ({ ...prev, perHostBytesPerSec: Math.max(0, parseInt(e.target.value, 10) || 0) * 1024 }))}
+              className="col-span-3 p-2 border rounded-md"
+              min="0"
+            />
+            <p className="col-span-4 text-xs text-muted-foreground text-center">
+              限速设置为0表示不限速。
+            </p>
+          </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="max-snapshots" className="text-right">
+              保留快照数
+            </Label>
+            <input
+              id="max-snapshots"
+              type="number"
+              value={snapshotRetention.maxSnapshots}
+              onChange={(e) => setSnapshotRetention(prev => ({ ...prev, maxSnapshots: Math.max(0, parseInt(e.target.value, 10) || 0) }))}
+              className="col-span-3 p-2 border rounded-md"
+              min="0"
+            />
+          </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="snapshot-max-size" className="text-right">
+              快照总大小 (MB)
+            </Label>
+            <input
+              id="snapshot-max-size"
+              type="number"
+              value={Math.round(snapshotRetention.maxBytes / 1024 / 1024)}
+              onChange={(e) => setSnapshotRetention(prev => ({ ...prev, maxBytes: Math.max(0, parseInt(e.target.value, 10) || 0) * 1024 * 1024 }))}
+              className="col-span-3 p-2 border rounded-md"
+              min="0"
+            />
+            <p className="col-span-4 text-xs text-muted-foreground text-center">
+              每次同步前会备份将被覆盖或删除的文件，可在同步页面恢复。快照数为0时不备份，大小为0表示不限。
+            </p>
+          </div>
+          <div className="grid grid-cols-4 items-center gap-4">
+            <Label htmlFor="update-check-interval" className="text-right">
+              检查更新间隔 (分钟)
+            </Label>
+            <input
+              id="update-check-interval"
+              type="number"
+              value={updateCheck.intervalMinutes}
+              onChange={(e) => setUpdateCheck(prev => ({ ...prev, intervalMinutes: Math.max(1, parseInt(e.target.value, 10) || 1) }))}
+              className="col-span-2 p-2 border rounded-md"
+              min="1"
+              disabled={!updateCheck.enabled}
+            />
+            <label className="flex items-center space-x-2 text-sm">
+              <input
+                type="checkbox"
+                checked={updateCheck.enabled}
+                onChange={(e) => setUpdateCheck(prev => ({ ...prev, enabled: e.target.checked }))}
+              />
+              <span>启用</span>
+            </label>
+            <p className="col-span-4 text-xs text-muted-foreground text-center">
+              后台定期检查已保存的同步配置是否有新版本，关闭后同步配置的自动同步也不会进行。
+            </p>
+          </div>
         </div>
         <DialogFooter>
           <Button variant="outline" onClick={onClose}>取消</Button>
@@ -0,0 +1,102 @@
+import { useState, useEffect } from 'react';
+import { Button } from './ui/button';
+import { Card, CardContent, CardHeader, CardTitle } from './ui/card';
+import { ScrollArea } from './ui/scroll-area';
+import { invoke } from '@tauri-apps/api';
+import { confirm, message } from '@tauri-apps/api/dialog';
+import { RotateCcw } from 'lucide-react';
+import { formatBytes } from '@/lib/utils';
+
+// 每次同步前自动创建的快照，见 list_snapshots
+interface Snapshot {
+  id: string;
+  createdAt: string;
+  reason: { kind: 'sync'; packageName: string; version: string } | { kind: 'restore'; snapshotId: string };
+  files: string[];
+  created: string[];
+  size: number;
+}
+
+interface SnapshotListProps {
+  targetDir: string;
+  // 变化时重新读取快照列表，例如同步完成后
+  refreshKey?: string | number | null;
+  disabled?: boolean;
+  onRestored: () => void;
+}
+
+export function SnapshotList({ targetDir, refreshKey, disabled, onRestored }: SnapshotListProps) {
+  const [snapshots, setSnapshots] = useState<Snapshot[]>([]);
+  const [isRestoring, setIsRestoring] = useState(false);
+
+  const loadSnapshots = async () => {
+    try {
+      setSnapshots(await invoke<Snapshot[]>('list_snapshots', { targetDir }));
+    } catch (e) {
+      console.error('Failed to load snapshots:', e);
+    }
+  };
+
+  useEffect(() => {
+    loadSnapshots();
+  }, [targetDir, refreshKey]);
+
+  const describe = (snapshot: Snapshot) => {
+    const reason = snapshot.reason;
+    if (reason.kind === 'sync') {
+      return `同步 ${reason.packageName} ${reason.version} 之前`;
+    }
+    const restored = snapshots.find(s => s.id === reason.snapshotId);
+    return restored ? `恢复到 ${new Date(restored.createdAt).toLocaleString()} 之前` : '恢复之前';
+  };
+
+  const handleRestore = async (snapshot: Snapshot) => {
+    const approved = await confirm(
+      `将目录恢复到${describe(snapshot)}的状态？之后的同步所改动的文件都会还原，恢复前的状态也会保存为快照。`,
+      { title: '恢复快照', type: 'warning' }
+    );
+    if (!approved) return;
+
+    setIsRestoring(true);
+    try {
+      const changed = await invoke<string[]>('restore_snapshot', { targetDir, snapshotId: snapshot.id });
+      await message(`已恢复 ${changed.length} 个文件`, { title: '恢复完成', type: 'info' });
+      onRestored();
+    } catch (e) {
+      await message(`恢复失败: ${e}`, { title: '错误', type: 'error' });
+    } finally {
+      setIsRestoring(false);
+      loadSnapshots();
+    }
+  };
+
+  if (snapshots.length === 0) return null;
+
+  return (
+    <Card className="mt-4">
+      <CardHeader>
+        <CardTitle>历史快照</CardTitle>
+      </CardHeader>
+      <CardContent>
+        <ScrollArea className="max-h-48">
+          <div className="space-y-2">
+            {snapshots.map(snapshot => (
+              <div key={snapshot.id} className="flex items-center justify-between text-sm">
+                <div>
+                  <div>{describe(snapshot)}</div>
+                  <div className="text-xs text-muted-foreground">
+                    {new Date(snapshot.createdAt).toLocaleString()} · 备份 {snapshot.files.length} 个文件（{formatBytes(snapshot.size)}），新增 {snapshot.created.length} 个
+                  </div>
+                </div>
+                <Button size="sm" variant="outline" onClick={() => handleRestore(snapshot)} disabled={disabled || isRestoring}>
+                  <RotateCcw className="h-4 w-4 mr-1" />
+                  恢复
+                </Button>
+              </div>
+            ))}
+          </div>
+        </ScrollArea>
+      </CardContent>
+    </Card>
+  );
+}
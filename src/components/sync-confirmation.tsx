@@ -1,15 +1,20 @@
-import { useState, useEffect, useMemo } from 'react';
+import { useState, useEffect, useMemo, useRef } from 'react';
 import { Button } from './ui/button';
 import { Card, CardHeader, CardTitle, CardContent } from './ui/card';
 import { Checkbox } from './ui/checkbox';
 import { invoke } from '@tauri-apps/api';
 import { listen } from '@tauri-apps/api/event';
+import { confirm } from '@tauri-apps/api/dialog';
 import { ArrowLeft, AlertCircle, RefreshCw, Check, AlertTriangle as AlertTriangleIcon } from 'lucide-react';
 import { Progress } from './ui/progress';
 import { ScrollArea } from './ui/scroll-area';
 import { ExclusionEditor } from './exclusion-editor';
+import { formatBytes } from '@/lib/utils';
 import { FileTree, buildFileTree, FileStatus, DiffFile } from './file-tree';
 import { TargetDirectorySelector } from './target-directory-selector';
+import { SnapshotList } from './snapshot-list';
+import { SyncProfile } from './sync-profiles';
+import { Input } from './ui/input';
 import ReactMarkdown from 'react-markdown';
 import remarkGfm from 'remark-gfm';
 
@@ -22,6 +27,9 @@ interface SyncOptions {
     manifestUrl: string;
     localPackagePath?: string;
     useLocalFiles?: boolean;
+    trustedKeys?: string[];
+    // 从保存的同步配置打开时的配置
+    profile?: SyncProfile;
 }
 
 interface SyncConfirmationProps {
@@ -29,29 +37,41 @@ interface SyncConfirmationProps {
   onBack: () => void;
 }
 
+// 后端 parse_manifest 迁移到当前格式版本后的清单
 interface Manifest {
-  packageName?: string;
-  package_name?: string;
+  schemaVersion: number;
+  packageName: string;
   version: string;
   description?: string;
   disableHashCheck?: boolean;
   disableSizeCheck?: boolean;
+  groups?: FileGroup[];
   files: ManifestFile[];
 }
 
+// 玩家可以自行开关的可选文件组
+interface FileGroup {
+  id: string;
+  name: string;
+  description?: string;
+  enabledByDefault: boolean;
+}
+
 interface ManifestFile {
   name: string;
-  relativePath?: string;
-  relative_path?: string;
+  relativePath: string;
   hash: string;
   size: number;
-  type?: 'file' | 'zip' | 'update_package';
-  file_type?: 'file' | 'zip' | 'update_package';
+  type: 'file' | 'zip' | 'update_package' | 'chunked';
   autoExtract?: boolean | null;
-  auto_extract?: boolean | null;
-  downloadUrl?: string;
-  url?: string;
-  download_url?: string;
+  downloadUrl: string;
+  group?: string;
+}
+
+interface SignatureInfo {
+  packageName: string;
+  status: 'trusted' | 'untrusted' | 'unsigned';
+  publicKey?: string | null;
 }
 
 interface DownloadProgress {
@@ -61,30 +81,51 @@ interface DownloadProgress {
   progress: number;
 }
 
-// Helper to get the download URL regardless of the key used
-const getDownloadUrl = (file: ManifestFile): string | undefined => {
-  return file.downloadUrl || file.url || file.download_url;
+// SYNC_FINISHED 事件 / get_sync_result 返回的同步结果
+interface SyncReport {
+  sessionId: string;
+  status: 'completed' | 'failed' | 'cancelled';
+  error?: string | null;
+  succeeded: string[];
+  failed: { path: string; error: string }[];
+  skipped: string[];
+  deleted: string[];
+  bytesTransferred: number;
+  durationMs: number;
+  hooks: HookResult[];
 }
 
-// Helper to get the relative path regardless of the key used
-const getRelativePath = (file: ManifestFile): string | undefined => {
-    return file.relativePath || file.relative_path;
+// 清单声明的同步前后动作的执行结果
+interface HookResult {
+  phase: 'preSync' | 'postSync';
+  action: string;
+  status: 'succeeded' | 'failed' | 'skipped';
+  message?: string | null;
+}
+
+// 需要玩家确认才能运行的程序
+interface PendingRun {
+  executable: string;
+  args: string[];
+  hash: string;
+  approval: string;
 }
 
 export { FileStatus };
 export type { DiffFile };
 
-const getPackageName = (manifest: Manifest): string | undefined => {
-    return manifest.packageName || manifest.package_name;
-}
 
 
 export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps) {
   const [manifest, setManifest] = useState<Manifest | null>(null);
+  // 原始清单文本，计算差异和下载时交给后端重新校验签名
+  const [manifestText, setManifestText] = useState<string | null>(null);
   const [isLoading, setIsLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
   const [targetDir, setTargetDir] = useState<string | null>(null);
   const [excludedFiles, setExcludedFiles] = useState<string[]>([]);
+  // 未保存过选择时为 null，后端按各组的默认开关处理
+  const [enabledGroups, setEnabledGroups] = useState<string[] | null>(null);
   const [fileDiff, setFileDiff] = useState<DiffFile[]>([]);
   const [isCalculatingDiff, setIsCalculatingDiff] = useState(false);
   const [overallProgress, setOverallProgress] = useState(0);
@@ -94,45 +135,39 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
   const [syncLogs, setSyncLogs] = useState<SyncLog[]>([]);
   const [overrideDisableHashCheck, setOverrideDisableHashCheck] = useState(false);
   const [overrideDisableSizeCheck, setOverrideDisableSizeCheck] = useState(false);
+  const [forceRescan, setForceRescan] = useState(false);
+  const [signature, setSignature] = useState<SignatureInfo | null>(null);
+  const [sessionId, setSessionId] = useState<string | null>(null);
+  const [isPaused, setIsPaused] = useState(false);
+  const [syncReport, setSyncReport] = useState<SyncReport | null>(null);
+  const [diffRefresh, setDiffRefresh] = useState(0);
+  const sessionIdRef = useRef<string | null>(null);
+  const [profile, setProfile] = useState<SyncProfile | null>(syncOptions.profile ?? null);
+  const [profileName, setProfileName] = useState('');
 
   const fileTree = useMemo(() => buildFileTree(fileDiff), [fileDiff]);
+  // 信任的公钥按清单来源保存：zip 包为包路径，其余为清单地址
+  const manifestSource = syncOptions.localPackagePath ?? syncOptions.manifestUrl;
 
   useEffect(() => {
     const fetchManifest = async () => {
       try {
         let text;
+        // 后端会先校验清单签名，未通过校验的清单直接报错
+        const trustedKeys = syncOptions.trustedKeys;
         if (syncOptions.localPackagePath && syncOptions.useLocalFiles) {
-            text = await invoke<string>('read_manifest_from_zip', { zipPath: syncOptions.localPackagePath });
+            text = await invoke<string>('read_manifest_from_zip', { zipPath: syncOptions.localPackagePath, trustedKeys });
         } else if (syncOptions.localPackagePath) {
-             text = await invoke<string>('read_manifest_from_zip', { zipPath: syncOptions.localPackagePath });
+             text = await invoke<string>('read_manifest_from_zip', { zipPath: syncOptions.localPackagePath, trustedKeys });
         } else {
-            text = await invoke<string>('fetch_manifest_text', { url: syncOptions.manifestUrl });
+            text = await invoke<string>('fetch_manifest_text', { url: syncOptions.manifestUrl, trustedKeys });
         }
-
-        try {
-          const data: Manifest = JSON.parse(text);
-          if (!getPackageName(data)) {
-            throw new Error("清单文件格式错误: 缺少 'packageName' 或 'package_name' 字段。");
-          }
-          if (!data.files || !Array.isArray(data.files)) {
-            throw new Error("清单文件格式错误: 'files' 字段不存在或不是一个数组。");
-          }
-          // Validate that each file has required fields
-          for (const file of data.files) {
-            if (!getDownloadUrl(file)) {
-              throw new Error(`文件 "${file.name}" 缺少下载链接 ('downloadUrl', 'url', or 'download_url')。`);
-            }
-            if (!getRelativePath(file)) {
-              throw new Error(`文件 "${file.name}" 缺少相对路径 ('relativePath' or 'relative_path')。`);
-            }
-          }
-          setManifest(data);
-        } catch (jsonError: any) {
-          throw new Error(`清单文件JSON解析失败: ${jsonError.message}`);
-        }
-
+        setSignature(await invoke<SignatureInfo>('get_manifest_signature', { manifestText: text, source: manifestSource, trustedKeys }));
+        // 旧版清单在后端迁移为当前格式，缺少必需字段或版本过新时直接报错
+        setManifest(await invoke<Manifest>('parse_manifest', { manifestText: text }));
+        setManifestText(text);
       } catch (e: any) {
-        setError(e.message);
+        setError(e.message ?? e.toString());
       } finally {
         setIsLoading(false);
       }
@@ -143,6 +178,8 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
     let unlistenFile: () => void;
     let unlistenSuccess: () => void;
     let unlistenError: () => void;
+    let unlistenCancelled: () => void;
+    let unlistenFinished: () => void;
 
     const setupListeners = async () => {
       unlistenOverall = await listen<number>('OVERALL_PROGRESS', (event) => {
@@ -157,6 +194,17 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
       unlistenError = await listen<string>('DOWNLOAD_ERROR', (event) => {
         setSyncLogs(prev => [...prev, { type: 'error', message: `错误: ${event.payload}` }]);
       });
+      unlistenCancelled = await listen<string>('SYNC_CANCELLED', () => {
+        setSyncLogs(prev => [...prev, { type: 'error', message: '同步已取消，目标目录未被修改' }]);
+        setIsDownloading(false);
+        setIsPaused(false);
+        setSessionId(null);
+      });
+      unlistenFinished = await listen<SyncReport>('SYNC_FINISHED', (event) => {
+        setSyncReport(event.payload);
+        setIsPaused(false);
+        setSessionId(null);
+      });
     };
 
     setupListeners();
@@ -166,12 +214,14 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
       if (unlistenFile) unlistenFile();
       if (unlistenSuccess) unlistenSuccess();
       if (unlistenError) unlistenError();
+      if (unlistenCancelled) unlistenCancelled();
+      if (unlistenFinished) unlistenFinished();
     };
   }, [syncOptions]);
 
   useEffect(() => {
     const calculateDiff = async () => {
-        if (!targetDir || !manifest) {
+        if (!targetDir || !manifest || !manifestText) {
             setFileDiff([]);
             return;
         }
@@ -179,11 +229,15 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
         setIsCalculatingDiff(true);
         try {
             const diffResult: DiffFile[] = await invoke('calculate_diff', {
-                manifest,
+                manifestText,
+                source: manifestSource,
+                trustedKeys: syncOptions.trustedKeys,
                 targetDir,
                 excludedFiles,
+                enabledGroups,
                 overrideDisableHashCheck,
                 overrideDisableSizeCheck,
+                forceRescan,
             });
             setFileDiff(diffResult);
         } catch (e: any) {
@@ -193,8 +247,52 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
         }
     };
     calculateDiff();
-  }, [targetDir, manifest, excludedFiles, overrideDisableHashCheck, overrideDisableSizeCheck]);
+  }, [targetDir, manifest, manifestText, excludedFiles, enabledGroups, overrideDisableHashCheck, overrideDisableSizeCheck, forceRescan, diffRefresh]);
+
+  const groups = manifest?.groups ?? [];
+  const isGroupEnabled = (group: FileGroup) =>
+    enabledGroups ? enabledGroups.includes(group.id) : group.enabledByDefault;
+
+  const toggleGroup = (group: FileGroup, enabled: boolean) => {
+    const current = groups.filter(isGroupEnabled).map(g => g.id);
+    setEnabledGroups(enabled ? [...current, group.id] : current.filter(id => id !== group.id));
+  };
+
+
+  // 从配置打开时恢复目标目录和选项，配置中的组选择优先于目录中保存的选择
+  useEffect(() => {
+    const saved = syncOptions.profile;
+    if (!saved) return;
+    setOverrideDisableHashCheck(saved.disableHashCheck);
+    setOverrideDisableSizeCheck(saved.disableSizeCheck);
+    setForceRescan(saved.forceRescan);
+    handleDirectorySelected(saved.targetDir).then(() => {
+      if (saved.enabledGroups) setEnabledGroups(saved.enabledGroups);
+    });
+  }, []);
+
+  // zip 包只读取清单、从网络下载时无法用配置表示
+  const canSaveProfile = !(syncOptions.localPackagePath && !syncOptions.useLocalFiles);
 
+  const handleSaveProfile = async () => {
+    if (!targetDir || !profileName.trim()) return;
+    try {
+      setProfile(await invoke<SyncProfile>('create_profile', {
+        profile: {
+          name: profileName.trim(),
+          source: syncOptions.useLocalFiles && syncOptions.localPackagePath ? syncOptions.localPackagePath : syncOptions.manifestUrl,
+          targetDir,
+          trustedKeys: syncOptions.trustedKeys ?? [],
+          enabledGroups,
+          disableHashCheck: overrideDisableHashCheck,
+          disableSizeCheck: overrideDisableSizeCheck,
+          forceRescan,
+        },
+      }));
+    } catch (e: any) {
+      setError(`保存同步配置失败: ${e.toString()}`);
+    }
+  };
 
   const handleDirectorySelected = async (path: string) => {
     setTargetDir(path);
@@ -205,33 +303,135 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
         console.error("Failed to load exclusion list:", e);
         // Handle error, maybe show a toast
       }
+      try {
+        setEnabledGroups(await invoke<string[] | null>('load_group_selection', { targetDir: path }));
+      } catch (e) {
+        console.error("Failed to load group selection:", e);
+      }
+  };
+
+  useEffect(() => {
+    sessionIdRef.current = sessionId;
+  }, [sessionId]);
+
+  // 离开页面时取消仍在运行的同步，避免后台任务继续写入
+  useEffect(() => {
+    return () => {
+      if (sessionIdRef.current) {
+        invoke('cancel_sync', { sessionId: sessionIdRef.current }).catch(() => {});
+      }
+    };
+  }, []);
+
+  const handlePauseResume = async () => {
+    if (!sessionId) return;
+    try {
+      await invoke(isPaused ? 'resume_sync' : 'pause_sync', { sessionId });
+      setIsPaused(!isPaused);
+    } catch (e: any) {
+      setSyncLogs(prev => [...prev, { type: 'error', message: `错误: ${e.toString()}` }]);
+    }
+  };
+
+  const handleCancel = async (keepPartial: boolean) => {
+    if (!sessionId) return;
+    try {
+      await invoke('cancel_sync', { sessionId, keepPartial });
+    } catch (e: any) {
+      setSyncLogs(prev => [...prev, { type: 'error', message: `错误: ${e.toString()}` }]);
+    }
+  };
+
+  // 查看完结果后回到差异视图，并按同步后的目录重新计算差异
+  const handleCloseReport = () => {
+    setSyncReport(null);
+    setIsDownloading(false);
+    setDiffRefresh(prev => prev + 1);
+  };
+
+  const handleTrustPublisher = async () => {
+    if (!signature?.publicKey) return;
+    try {
+      const keys: string[] = await invoke('get_trusted_publisher_keys', { source: manifestSource });
+      await invoke('set_trusted_publisher_keys', {
+        source: manifestSource,
+        keys: [...keys, signature.publicKey],
+      });
+      setSignature({ ...signature, status: 'trusted' });
+    } catch (e: any) {
+      setError(`保存信任的发布者失败: ${e.toString()}`);
+    }
   };
 
   const handleStartSync = async () => {
-    if (!targetDir || !manifest) return;
+    if (!targetDir || !manifest || !manifestText) return;
     setIsDownloading(true);
     setOverallProgress(0);
     setFileProgress(null);
     setSyncLogs([]);
+    setSyncReport(null);
 
     try {
       // Save the exclusion list before starting the download
       await invoke('save_exclusion_list', { targetDir, excludedFiles });
+      if (enabledGroups) {
+        await invoke('save_group_selection', { targetDir, enabledGroups });
+      }
 
-      if (syncOptions.localPackagePath && syncOptions.useLocalFiles) {
-          await invoke('sync_from_local_package', {
+      // 清单要运行程序时先请玩家确认，未确认的运行动作会被跳过
+      const pendingRuns = await invoke<PendingRun[]>('get_pending_hook_runs', {
+        manifestText,
+        source: manifestSource,
+        trustedKeys: syncOptions.trustedKeys,
+        targetDir,
+      });
+      if (pendingRuns.length > 0) {
+        const commands = pendingRuns.map(run => [run.executable, ...run.args].join(' ')).join('\n');
+        const approved = await confirm(`此整合包会在同步时运行以下程序：\n${commands}\n\n是否允许运行？`, { title: '运行程序确认', type: 'warning' });
+        if (approved) {
+          await invoke('approve_hook_runs', { targetDir, approvals: pendingRuns.map(run => run.approval) });
+        }
+      }
+
+      if (profile) {
+          // 按配置同步前先保存本次的目录和选项，同步完成后后端记录同步的版本
+          const updated = await invoke<SyncProfile>('update_profile', {
+            profile: {
+              ...profile,
+              targetDir,
+              enabledGroups,
+              disableHashCheck: overrideDisableHashCheck,
+              disableSizeCheck: overrideDisableSizeCheck,
+              forceRescan,
+            },
+          });
+          setProfile(updated);
+          setSessionId(await invoke<string>('run_profile', { id: updated.id }));
+      } else if (syncOptions.localPackagePath && syncOptions.useLocalFiles) {
+          const id = await invoke<string>('sync_from_local_package', {
             zipPath: syncOptions.localPackagePath,
             targetDir,
             excludedFiles,
+            enabledGroups,
+            trustedKeys: syncOptions.trustedKeys,
+            overrideDisableHashCheck,
+            overrideDisableSizeCheck,
+            forceRescan,
           });
+          setSessionId(id);
       } else {
-        await invoke('start_download', {
-          manifest,
+        const id = await invoke<string>('start_download', {
+          manifestText,
+          source: manifestSource,
+          trustedKeys: syncOptions.trustedKeys,
           targetDir,
           excludedFiles,
+          enabledGroups,
           overrideDisableHashCheck,
           overrideDisableSizeCheck,
+          forceRescan,
         });
+        setSessionId(id);
       }
     } catch (e: any) {
       setError(`下载启动失败: ${e.toString()}`);
@@ -262,7 +462,7 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
          <Button onClick={onBack} variant="ghost" size="icon">
            <ArrowLeft className="h-4 w-4" />
          </Button>
-         <h2 className="text-2xl font-bold ml-2">同步确认: {getPackageName(manifest)}</h2>
+         <h2 className="text-2xl font-bold ml-2">同步确认: {manifest.packageName}</h2>
        </div>
        
       {manifest.description && (
@@ -280,8 +480,91 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
         </Card>
       )}
        
+      {signature && signature.status !== 'trusted' && (
+        <Card className="mb-4 border-amber-500">
+          <CardContent className="pt-4 flex items-start space-x-2 text-sm">
+            <AlertTriangleIcon className="w-4 h-4 mt-0.5 text-amber-500 flex-shrink-0" />
+            {signature.status === 'unsigned' ? (
+              <span>此清单没有签名，无法确认它来自整合包作者且未被篡改。</span>
+            ) : (
+              <div className="space-y-2">
+                <div>此清单已签名，但尚未信任该发布者。信任后，从此地址（或此本地包）读取的清单都必须由该密钥签名。</div>
+                <div className="font-mono text-xs break-all text-muted-foreground">{signature.publicKey}</div>
+                <Button size="sm" variant="outline" onClick={handleTrustPublisher}>信任此发布者</Button>
+              </div>
+            )}
+          </CardContent>
+        </Card>
+      )}
+
       <TargetDirectorySelector onDirectorySelect={handleDirectorySelected} disabled={isCalculatingDiff || isDownloading} />
 
+      {groups.length > 0 && (
+        <Card className="mt-4">
+            <CardHeader>
+                <CardTitle>可选内容</CardTitle>
+            </CardHeader>
+            <CardContent className="space-y-4">
+                {groups.map(group => (
+                    <div key={group.id} className="flex items-start space-x-2">
+                        <Checkbox
+                            id={`group-${group.id}`}
+                            checked={isGroupEnabled(group)}
+                            onCheckedChange={(checked) => toggleGroup(group, !!checked)}
+                            disabled={isDownloading}
+                        />
+                        <div>
+                            <label htmlFor={`group-${group.id}`} className="text-sm font-medium">
+                                {group.name}
+                            </label>
+                            {group.description && (
+                                <p className="text-xs text-muted-foreground">{group.description}</p>
+                            )}
+                        </div>
+                    </div>
+                ))}
+                <p className="text-xs text-muted-foreground">关闭的内容不会下载，已安装的部分会在同步时删除。</p>
+            </CardContent>
+        </Card>
+      )}
+
+      {targetDir && (profile || canSaveProfile) && (
+        <Card className="mt-4">
+            <CardHeader>
+                <CardTitle>同步配置</CardTitle>
+            </CardHeader>
+            <CardContent className="text-sm">
+                {profile ? (
+                    <div>
+                        <div>正在使用配置“{profile.name}”，目标目录：{targetDir}</div>
+                        <p className="text-xs text-muted-foreground">开始同步时会把当前的目录和选项保存到该配置。</p>
+                    </div>
+                ) : (
+                    <div className="flex space-x-2">
+                        <Input
+                            value={profileName}
+                            onChange={(e) => setProfileName(e.target.value)}
+                            placeholder="配置名称"
+                            disabled={isDownloading}
+                        />
+                        <Button variant="outline" onClick={handleSaveProfile} disabled={isDownloading || !profileName.trim()}>
+                            保存为同步配置
+                        </Button>
+                    </div>
+                )}
+            </CardContent>
+        </Card>
+      )}
+
+      {targetDir && (
+        <SnapshotList
+          targetDir={targetDir}
+          refreshKey={syncReport?.sessionId}
+          disabled={isDownloading}
+          onRestored={() => setDiffRefresh(prev => prev + 1)}
+        />
+      )}
+
        <Card className="mt-4">
             <CardHeader>
                 <CardTitle>同步选项</CardTitle>
@@ -309,6 +592,17 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
                         禁用大小校验 (覆盖清单设置)
                     </label>
            </div>
+                <div className="flex items-center space-x-2">
+                    <Checkbox
+                        id="force-rescan"
+                        checked={forceRescan}
+                        onCheckedChange={(checked) => setForceRescan(!!checked)}
+                        disabled={isDownloading}
+                    />
+                    <label htmlFor="force-rescan" className="text-sm font-medium">
+                        强制完整重新扫描 (忽略本地哈希索引)
+                    </label>
+                </div>
          </CardContent>
        </Card>
  
@@ -345,9 +639,56 @@ export function SyncConfirmation({ syncOptions, onBack }: SyncConfirmationProps)
              <div className="text-sm text-center">总进度: {overallProgress.toFixed(2)}%</div>
              {fileProgress && (
                <div className="text-xs text-center text-muted-foreground">
-                 正在下载: {fileProgress.file} ({fileProgress.progress.toFixed(2)}%)
+                 {isPaused ? '已暂停' : '正在下载'}: {fileProgress.file} ({fileProgress.progress.toFixed(2)}%)
                </div>
              )}
+             {sessionId && !syncReport && overallProgress < 100 && (
+               <div className="flex space-x-2">
+                 <Button variant="outline" className="flex-1" onClick={handlePauseResume}>
+                   {isPaused ? '继续' : '暂停'}
+                 </Button>
+                 <Button variant="outline" className="flex-1" onClick={() => handleCancel(true)}>
+                   取消 (保留已下载部分)
+                 </Button>
+                 <Button variant="destructive" className="flex-1" onClick={() => handleCancel(false)}>
+                   取消并清理
+                 </Button>
+               </div>
+             )}
+             {syncReport && (
+               <Card className="mt-4">
+                 <CardHeader>
+                   <CardTitle>
+                     {syncReport.status === 'completed' ? '同步完成' : syncReport.status === 'cancelled' ? '同步已取消' : '同步失败'}
+                   </CardTitle>
+                 </CardHeader>
+                 <CardContent className="space-y-2 text-sm">
+                   <div>
+                     更新 {syncReport.succeeded.length} 个，失败 {syncReport.failed.length} 个，跳过 {syncReport.skipped.length} 个，删除 {syncReport.deleted.length} 个；
+                     传输 {formatBytes(syncReport.bytesTransferred)}，用时 {(syncReport.durationMs / 1000).toFixed(1)} 秒
+                   </div>
+                   {syncReport.status === 'failed' && (
+                     <div className="text-red-500 text-xs space-y-1">
+                       {syncReport.error && <div>{syncReport.error}</div>}
+                       {syncReport.failed.map(file => (
+                         <div key={file.path}>{file.path}: {file.error}</div>
+                       ))}
+                     </div>
+                   )}
+                   {syncReport.hooks.length > 0 && (
+                     <div className="text-xs space-y-1">
+                       {syncReport.hooks.map((hook, index) => (
+                         <div key={index} className={hook.status === 'failed' ? 'text-red-500' : hook.status === 'skipped' ? 'text-muted-foreground' : ''}>
+                           {hook.phase === 'preSync' ? '同步前' : '同步后'} {hook.action}：{hook.status === 'succeeded' ? '完成' : hook.status === 'failed' ? '失败' : '跳过'}
+                           {hook.message && `（${hook.message}）`}
+                         </div>
+                       ))}
+                     </div>
+                   )}
+                   <Button className="w-full" onClick={handleCloseReport}>完成</Button>
+                 </CardContent>
+               </Card>
+             )}
              
             <Card className="mt-4">
                 <CardHeader>
@@ -5,6 +5,7 @@ import { PlusCircle } from 'lucide-react';
 import { CustomSyncImporter } from './custom-sync-importer';
 import { invoke } from '@tauri-apps/api';
 import { ProxiedImage } from './proxied-image';
+import { SyncProfiles, SyncProfile } from './sync-profiles';
 
 interface Modpack {
   name: string;
@@ -12,6 +13,7 @@ interface Modpack {
   description: string;
   author: string;
   thumbnail: string;
+  publicKeys?: string[]; // 发布者签名公钥
 }
 
 interface ModpackData {
@@ -19,10 +21,11 @@ interface ModpackData {
 }
 
 interface SyncMarketProps {
-    onSync: (url: string, localPackagePath?: string, useLocalFiles?: boolean) => void;
+    onSync: (url: string, localPackagePath?: string, useLocalFiles?: boolean, trustedKeys?: string[]) => void;
+    onRunProfile: (profile: SyncProfile) => void;
 }
 
-export function SyncMarket({ onSync }: SyncMarketProps) {
+export function SyncMarket({ onSync, onRunProfile }: SyncMarketProps) {
   const [modpacks, setModpacks] = useState<ModpackData | null>(null);
   const [isLoading, setIsLoading] = useState(true);
   const [error, setError] = useState<string | null>(null);
@@ -60,6 +63,7 @@ export function SyncMarket({ onSync }: SyncMarketProps) {
                 同步自定义包
             </Button>
         </div>
+      <SyncProfiles onRun={onRunProfile} />
       <div className="grid grid-cols-1 md:grid-cols-2 lg:grid-cols-3 xl:grid-cols-4 gap-4">
         {modpacks && Object.values(modpacks).map((pack) => (
           <Card key={pack.name}>
@@ -76,7 +80,7 @@ export function SyncMarket({ onSync }: SyncMarketProps) {
               <p className="text-sm text-muted-foreground">{pack.description}</p>
                 </CardContent>
             <CardFooter>
-              <Button className="w-full" onClick={() => onSync(pack.url)}>同步</Button>
+              <Button className="w-full" onClick={() => onSync(pack.url, undefined, undefined, pack.publicKeys)}>同步</Button>
             </CardFooter>
               </Card>
             ))}
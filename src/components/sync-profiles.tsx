@@ -0,0 +1,235 @@
+import { useState, useEffect } from 'react';
+import { Card, CardContent, CardHeader, CardTitle } from './ui/card';
+import { Button } from './ui/button';
+import { Input } from './ui/input';
+import { ScrollArea } from './ui/scroll-area';
+import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from './ui/select';
+import { invoke } from '@tauri-apps/api';
+import { listen } from '@tauri-apps/api/event';
+import { confirm } from '@tauri-apps/api/dialog';
+import { Play, Trash2, RefreshCw, History } from 'lucide-react';
+
+// 检查到新版本后自动同步的时机，scheduled 的 time 为本地时间 HH:MM
+export type AutoSync = { mode: 'off' } | { mode: 'scheduled'; time: string } | { mode: 'idle' };
+
+// 后端保存的同步配置，见 list_profiles
+export interface SyncProfile {
+  id: string;
+  name: string;
+  // 清单 URL、导出的 zip 包或 manifest.json 的路径；zip 包直接从包内安装
+  source: string;
+  targetDir: string;
+  trustedKeys: string[];
+  enabledGroups: string[] | null;
+  disableHashCheck: boolean;
+  disableSizeCheck: boolean;
+  forceRescan: boolean;
+  autoSync?: AutoSync;
+  lastSyncedVersion?: string | null;
+  lastSyncedAt?: string | null;
+}
+
+// 后台检查更新的结果，见 get_update_statuses
+interface UpdateStatus {
+  profileId: string;
+  latestVersion: string | null;
+  updateAvailable: boolean;
+  checkedAt: string | null;
+  error: string | null;
+}
+
+type HistoryEvent =
+  | { kind: 'update_available'; version: string; currentVersion: string | null }
+  | { kind: 'sync_started'; trigger: string; version: string }
+  | { kind: 'sync_finished'; trigger: string; version: string; status: string; error: string | null }
+  | { kind: 'error'; error: string };
+
+interface HistoryEntry {
+  at: string;
+  profileId: string;
+  profileName: string;
+  event: HistoryEvent;
+}
+
+const TRIGGER_LABELS: Record<string, string> = { manual: '手动', scheduled: '定时', idle: '空闲时' };
+const STATUS_LABELS: Record<string, string> = { completed: '完成', failed: '失败', cancelled: '已取消' };
+
+const describeEvent = (event: HistoryEvent) => {
+  switch (event.kind) {
+    case 'update_available':
+      return `发现新版本 ${event.version}`;
+    case 'sync_started':
+      return `${TRIGGER_LABELS[event.trigger] ?? event.trigger}同步 ${event.version} 开始`;
+    case 'sync_finished':
+      return `${TRIGGER_LABELS[event.trigger] ?? event.trigger}同步 ${event.version} ${STATUS_LABELS[event.status] ?? event.status}${event.error ? `: ${event.error}` : ''}`;
+    case 'error':
+      return `出错: ${event.error}`;
+  }
+};
+
+interface SyncProfilesProps {
+  onRun: (profile: SyncProfile) => void;
+}
+
+export function SyncProfiles({ onRun }: SyncProfilesProps) {
+  const [profiles, setProfiles] = useState<SyncProfile[]>([]);
+  const [statuses, setStatuses] = useState<Record<string, UpdateStatus>>({});
+  const [isChecking, setIsChecking] = useState(false);
+  const [history, setHistory] = useState<HistoryEntry[] | null>(null);
+
+  const applyStatuses = (list: UpdateStatus[]) => {
+    setStatuses(Object.fromEntries(list.map(status => [status.profileId, status])));
+  };
+
+  const loadProfiles = async () => {
+    try {
+      setProfiles(await invoke<SyncProfile[]>('list_profiles'));
+      applyStatuses(await invoke<UpdateStatus[]>('get_update_statuses'));
+    } catch (e) {
+      console.error('Failed to load sync profiles:', e);
+    }
+  };
+
+  const loadHistory = async () => {
+    try {
+      setHistory(await invoke<HistoryEntry[]>('get_sync_history', { limit: 50 }));
+    } catch (e) {
+      console.error('Failed to load sync history:', e);
+    }
+  };
+
+  useEffect(() => {
+    loadProfiles();
+    // 后台检查到更新或自动同步结束后刷新
+    const unlistenUpdate = listen('UPDATE_AVAILABLE', () => loadProfiles());
+    const unlistenFinished = listen('SYNC_FINISHED', () => loadProfiles());
+    return () => {
+      unlistenUpdate.then(f => f());
+      unlistenFinished.then(f => f());
+    };
+  }, []);
+
+  useEffect(() => {
+    if (history) loadHistory();
+  }, [profiles]);
+
+  const handleCheck = async () => {
+    setIsChecking(true);
+    try {
+      applyStatuses(await invoke<UpdateStatus[]>('check_profile_updates'));
+    } catch (e) {
+      console.error('Failed to check for profile updates:', e);
+    } finally {
+      setIsChecking(false);
+    }
+  };
+
+  const handleAutoSyncChange = async (profile: SyncProfile, autoSync: AutoSync) => {
+    try {
+      await invoke('update_profile', { profile: { ...profile, autoSync } });
+    } catch (e) {
+      console.error('Failed to update sync profile:', e);
+    }
+    loadProfiles();
+  };
+
+  const handleDelete = async (profile: SyncProfile) => {
+    if (!await confirm(`删除同步配置“${profile.name}”？目标目录中的文件不受影响。`, { title: '删除配置', type: 'warning' })) {
+      return;
+    }
+    try {
+      await invoke('delete_profile', { id: profile.id });
+    } catch (e) {
+      console.error('Failed to delete sync profile:', e);
+    }
+    loadProfiles();
+  };
+
+  if (profiles.length === 0) return null;
+
+  return (
+    <Card className="mb-4">
+      <CardHeader className="flex flex-row items-center justify-between space-y-0">
+        <CardTitle>我的同步配置</CardTitle>
+        <div className="flex space-x-2">
+          <Button size="sm" variant="outline" onClick={handleCheck} disabled={isChecking}>
+            <RefreshCw className={`h-4 w-4 mr-1 ${isChecking ? 'animate-spin' : ''}`} />
+            检查更新
+          </Button>
+          <Button size="sm" variant="ghost" onClick={() => history ? setHistory(null) : loadHistory()}>
+            <History className="h-4 w-4 mr-1" />
+            同步记录
+          </Button>
+        </div>
+      </CardHeader>
+      <CardContent className="space-y-2">
+        {profiles.map(profile => {
+          const status = statuses[profile.id];
+          const autoSync = profile.autoSync ?? { mode: 'off' };
+          return (
+            <div key={profile.id} className="flex items-center justify-between text-sm">
+              <div className="min-w-0">
+                <div className="font-medium">
+                  {profile.name}
+                  {status?.updateAvailable && (
+                    <span className="ml-2 text-xs text-primary">新版本 {status.latestVersion}</span>
+                  )}
+                </div>
+                <div className="text-xs text-muted-foreground truncate">
+                  {profile.targetDir} · {profile.lastSyncedVersion
+                    ? `已同步 ${profile.lastSyncedVersion}（${new Date(profile.lastSyncedAt ?? '').toLocaleString()}）`
+                    : '尚未同步'}
+                  {status?.error && ` · 检查更新失败: ${status.error}`}
+                </div>
+              </div>
+              <div className="flex items-center space-x-2 flex-shrink-0">
+                <Select
+                  value={autoSync.mode}
+                  onValueChange={(mode) => handleAutoSyncChange(profile, mode === 'scheduled'
+                    ? { mode: 'scheduled', time: '04:00' }
+                    : { mode: mode as 'off' | 'idle' })}
+                >
+                  <SelectTrigger className="w-[120px] h-8">
+                    <SelectValue />
+                  </SelectTrigger>
+                  <SelectContent>
+                    <SelectItem value="off">不自动同步</SelectItem>
+                    <SelectItem value="scheduled">定时同步</SelectItem>
+                    <SelectItem value="idle">空闲时同步</SelectItem>
+                  </SelectContent>
+                </Select>
+                {autoSync.mode === 'scheduled' && (
+                  <Input
+                    type="time"
+                    className="w-[100px] h-8"
+                    defaultValue={autoSync.time}
+                    onBlur={(e) => e.target.value && e.target.value !== autoSync.time
+                      && handleAutoSyncChange(profile, { mode: 'scheduled', time: e.target.value })}
+                  />
+                )}
+                <Button size="sm" onClick={() => onRun(profile)}>
+                  <Play className="h-4 w-4 mr-1" />
+                  同步
+                </Button>
+                <Button size="sm" variant="ghost" onClick={() => handleDelete(profile)}>
+                  <Trash2 className="h-4 w-4" />
+                </Button>
+              </div>
+            </div>
+          );
+        })}
+        {history && (
+          <ScrollArea className="max-h-48 border-t pt-2">
+            {history.length === 0 && <p className="text-xs text-muted-foreground">暂无记录</p>}
+            {history.map((entry, index) => (
+              <div key={index} className="text-xs">
+                <span className="text-muted-foreground">{new Date(entry.at).toLocaleString()}</span>
+                {' '}{entry.profileName}: {describeEvent(entry.event)}
+              </div>
+            ))}
+          </ScrollArea>
+        )}
+      </CardContent>
+    </Card>
+  );
+}